@@ -0,0 +1,594 @@
+use std::fs::File;
+use std::io::{BufRead, Read, Write};
+use std::net::{Ipv4Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use std::sync::Arc;
+
+use clap::{Parser, Subcommand};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+
+use bbcore::instruction::InstructionSet;
+
+use desktop_lib::client::{self, JobContext, Session};
+use desktop_lib::discovery;
+use desktop_lib::error::AppError;
+use desktop_lib::estimate;
+use desktop_lib::export;
+use desktop_lib::file::{read_app_config, read_drawing, AppConfig};
+use desktop_lib::optimise::{self, OptimisationReport};
+use desktop_lib::pens::{self, PenPlan};
+use desktop_lib::profiles::ProfileStore;
+use desktop_lib::progress::ProgressEvent;
+use desktop_lib::protocol;
+use desktop_lib::transport::Transport;
+use desktop_lib::wire;
+use desktop_lib::{styles, write_preview_to};
+
+
+/// The app identifier from `tauri.conf.json`, which names the app's cache and data directories.
+const APP_IDENTIFIER: &str = "com.blotbot.desktop";
+
+/// The file a running `plot` writes its control port to, so `pause` and `stop` can reach it.
+const CONTROL_FILE: &str = "cli_control";
+
+
+///
+/// Previews and plots Blot Bot drawings from a terminal.
+/// It shares the app's configuration and cache, so a drawing previewed in the app can be plotted here, and vice versa.
+///
+#[derive(Parser)]
+#[command(name = "blot", version)]
+struct Cli {
+    /// Path to an app config file, instead of the app's machine profiles
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
+
+    /// The machine profile to use, instead of the one selected in the app
+    #[arg(long, global = true)]
+    profile: Option<String>,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Generates a drawing from a save file or bundle, caches it for plotting, and renders its preview
+    Preview {
+        /// The save file or bundle
+        file: String,
+        /// Copies the preview image to this path
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Generates a drawing from a save file or bundle, and writes its instruction bytes, or an SVG, G-code or HPGL file if the output ends in .svg, .gcode or .hpgl
+    Export {
+        /// The save file or bundle
+        file: String,
+        /// The path to write the instructions, SVG, G-code or HPGL to
+        #[arg(short, long)]
+        output: PathBuf,
+    },
+    /// Plots a save file or bundle, or the most recently previewed drawing
+    Plot {
+        /// The save file or bundle, otherwise the cached drawing is plotted
+        file: Option<String>,
+    },
+    /// Pauses or resumes the drawing being plotted
+    Pause {
+        /// Resumes the drawing instead
+        #[arg(long)]
+        resume: bool,
+    },
+    /// Stops the drawing being plotted
+    Stop,
+    /// Moves the pen to a position on the page, in millimetres
+    Goto {
+        x: f64,
+        y: f64,
+    },
+    /// Sets the angle of the pen lift servo, from 0 to 180
+    Pen {
+        angle: i16,
+    },
+    /// Enables or disables the motors
+    Motors {
+        #[arg(value_parser = ["on", "off"])]
+        state: String,
+    },
+    /// Lists the machine profiles, marking the selected one
+    Profiles,
+    /// Checks the machine is reachable, and prints its handshake
+    Test,
+    /// Searches the local network for machines
+    Discover {
+        /// Also probes this IP:PORT directly, can be given more than once
+        #[arg(long)]
+        target: Vec<SocketAddr>,
+        /// How long to wait for replies, in milliseconds
+        #[arg(long, default_value_t = 1500)]
+        timeout_ms: u64,
+    },
+}
+
+
+#[tokio::main]
+async fn main() {
+    let cli = Cli::parse();
+
+    let cache_dir = match dirs::cache_dir() {
+        Some(dir) => dir.join(APP_IDENTIFIER),
+        None => { eprintln!("Couldn't find the cache directory."); std::process::exit(1); }
+    };
+    let data_dir = dirs::data_dir().map(|dir| dir.join(APP_IDENTIFIER)).unwrap_or_else(|| cache_dir.clone());
+    let config = ConfigSource { path: cli.config.clone(), profile: cli.profile.clone(), cache_dir: cache_dir.clone() };
+
+    let result = match cli.command {
+        Command::Preview { file, output } => preview(&config, &cache_dir, &data_dir, &file, output.as_deref()),
+        Command::Export { file, output } => export(&config, &cache_dir, &data_dir, &file, &output),
+        Command::Plot { file } => plot(&config, &cache_dir, &data_dir, file.as_deref()).await,
+        Command::Pause { resume } => send_control(&cache_dir, if resume { "resume" } else { "pause" }).await,
+        Command::Stop => send_control(&cache_dir, "stop").await,
+        Command::Goto { x, y } => match config.load() {
+            Ok(app_config) => wire::goto(&app_config, x, y).await,
+            Err(err) => Err(err),
+        },
+        Command::Pen { angle } => manual_control(&config, protocol::OP_PEN_LIFT, angle).await,
+        Command::Motors { state } => manual_control(&config, protocol::OP_MOTORS, if state == "on" { 0x01 } else { 0x00 }).await,
+        Command::Profiles => list_profiles(&cache_dir),
+        Command::Test => test_connection(&config).await,
+        Command::Discover { target, timeout_ms } => discover(target, timeout_ms).await,
+    };
+
+    if let Err(err) = result {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    }
+}
+
+
+///
+/// Where the app config is loaded from.
+///
+/// # Fields:
+/// - `path`: An app config file, used instead of the machine profiles
+/// - `profile`: The machine profile to use, otherwise the selected profile is used
+/// - `cache_dir`: The app cache directory, holding the machine profiles
+///
+struct ConfigSource {
+    path: Option<PathBuf>,
+    profile: Option<String>,
+    cache_dir: PathBuf,
+}
+
+impl ConfigSource {
+
+    ///
+    /// Gets the ID of the machine, which its checkpoints are saved under.
+    /// A config file is named after the file, so it doesn't share checkpoints with a profile.
+    ///
+    /// # Returns:
+    /// - The name of the machine profile, or the name of the config file
+    ///
+    fn machine_id(&self) -> String {
+        if let Some(path) = &self.path {
+            return path.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default();
+        }
+
+        match &self.profile {
+            Some(name) => name.clone(),
+            None => ProfileStore::read(&self.cache_dir).ok().and_then(|store| store.selected).unwrap_or_default(),
+        }
+    }
+
+    ///
+    /// Loads the app config.
+    ///
+    /// # Returns:
+    /// - The app config
+    /// - An error explaining why it couldn't be loaded
+    ///
+    fn load(&self) -> Result<AppConfig, AppError> {
+        if let Some(path) = &self.path {
+            return read_app_config(path).map_err(|err| AppError::NotConfigured(format!("{} ({})", err, path.display())));
+        }
+
+        let store = ProfileStore::read(&self.cache_dir)?;
+        match &self.profile {
+            Some(name) => store.get(name).cloned(),
+            None => store.selected_config().cloned(),
+        }
+    }
+}
+
+///
+/// Checks the machine is reachable, and prints its handshake and any warnings.
+///
+/// # Parameters:
+/// - `config`: Where to load the app config from
+///
+/// # Returns:
+/// - Void if the machine was reached
+/// - An error explaining why it couldn't be reached
+///
+async fn test_connection(config: &ConfigSource) -> Result<(), AppError> {
+    let app_config = config.load()?;
+    let report = client::test_connection_to(&Transport::from_config(&app_config)?).await?;
+
+    println!("Connected to {} in {:.1}ms", report.address, report.latency_ms);
+    println!("Protocol v{}, {} byte buffer, {} steps/s, {}μs pulse width", report.protocol_version, report.instruction_buffer_size, report.max_motor_speed, report.min_pulse_width);
+    for warning in report.warnings {
+        println!("Warning: {}", warning);
+    }
+    Ok(())
+}
+
+///
+/// Searches the local network for machines, and prints what was found.
+///
+/// # Parameters:
+/// - `targets`: Addresses to probe directly, as well as broadcasting
+/// - `timeout_ms`: How long to wait for replies, in milliseconds
+///
+/// # Returns:
+/// - Void if the function succeeded
+/// - An error if the probe couldn't be sent
+///
+async fn discover(mut targets: Vec<SocketAddr>, timeout_ms: u64) -> Result<(), AppError> {
+    targets.insert(0, SocketAddr::from((Ipv4Addr::BROADCAST, protocol::DISCOVERY_PORT)));
+
+    let found = discovery::discover(&targets, Duration::from_millis(timeout_ms)).await.map_err(|err| AppError::Connection(format!("Couldn't search for machines: {}", err)))?;
+    if found.is_empty() {
+        println!("No machines found.");
+    }
+    for machine in found {
+        println!("{}:{}  protocol {}, {} byte buffer, {} steps/s", machine.addr, machine.port, machine.protocol_version, machine.instruction_buffer_size, machine.max_motor_speed);
+    }
+    Ok(())
+}
+
+///
+/// Lists the machine profiles, marking the selected one.
+///
+/// # Parameters:
+/// - `cache_dir`: The app cache directory
+///
+/// # Returns:
+/// - Void if the function succeeded
+/// - An error if the profiles couldn't be read
+///
+fn list_profiles(cache_dir: &Path) -> Result<(), AppError> {
+    let store = ProfileStore::read(cache_dir)?;
+    for (name, app_config) in &store.profiles {
+        let marker = if store.selected.as_deref() == Some(name.as_str()) { "*" } else { " " };
+        println!("{} {} ({}:{}, {}x{}mm)", marker, name, app_config.machine_addr, app_config.machine_port, app_config.phys_page_width, app_config.phys_page_height);
+    }
+    Ok(())
+}
+
+///
+/// Loads a drawing from a save file or bundle and generates its instructions.
+/// The drawing's paths are optimised if that's turned on in the app config, and the travel saved is printed.
+///
+/// # Parameters:
+/// - `app_config`: The app config
+/// - `cache_dir`: The app cache directory, holding the machine profiles
+/// - `data_dir`: The app data directory, which bundles are unpacked into
+/// - `file`: The save file or bundle
+///
+/// # Returns:
+/// - The drawing's instruction set, pen plan, and optimisation report if it was optimised
+/// - An error explaining why the drawing couldn't be generated
+///
+fn generate(app_config: &AppConfig, cache_dir: &Path, data_dir: &Path, file: &str) -> Result<(InstructionSet, PenPlan, Option<OptimisationReport>), AppError> {
+    let (drawing_id, drawing_parameters, report) = read_drawing(file, data_dir)?;
+    for line in report {
+        println!("{}", line);
+    }
+
+    let style = match styles::get_style(drawing_id.as_str()) {
+        Some(style) => style,
+        None => { return Err(AppError::InvalidParameters("Unknown draw type".to_owned())); }
+    };
+
+    let json_params = drawing_parameters.to_string();
+    let (bytes, ix, iy) = (style.gen_instructions)(&app_config.physical_dimensions(), json_params.as_str()).map_err(AppError::InvalidParameters)?;
+    let plan = match style.gen_pen_plan {
+        Some(gen_pen_plan) => gen_pen_plan(&app_config.physical_dimensions(), json_params.as_str()).map_err(AppError::InvalidParameters)?,
+        None => PenPlan::single(),
+    };
+
+    let optimised = match app_config.optimise_paths {
+        true => optimise::optimise(&app_config.physical_dimensions(), &bytes, (ix, iy), &plan, &estimate::machine_profile(Some(app_config), cache_dir)),
+        false => None,
+    };
+    let (bytes, plan, report) = match optimised {
+        Some((bytes, plan, report)) => {
+            println!(
+                "Optimised {} strokes, merging {}: pen-up travel {:.0}mm -> {:.0}mm, estimated time {}s -> {}s",
+                report.strokes, report.merged, report.travel_before_mm, report.travel_after_mm, report.secs_before, report.secs_after,
+            );
+            (bytes, plan, Some(report))
+        },
+        None => (bytes, plan, None),
+    };
+
+    let instruction_set = InstructionSet::new(bytes, ix, iy).map_err(|err| AppError::InvalidParameters(err.to_string()))?;
+    Ok((instruction_set, plan, report))
+}
+
+///
+/// Generates a drawing, caches it for plotting, and renders its preview.
+///
+/// # Parameters:
+/// - `config`: Where to load the app config from
+/// - `cache_dir`: The app cache directory
+/// - `data_dir`: The app data directory
+/// - `file`: The save file or bundle
+/// - `output`: Copies the preview image to this path, if given
+///
+/// # Returns:
+/// - Void if the function succeeded
+/// - An error explaining why the function could not succeed
+///
+fn preview(config: &ConfigSource, cache_dir: &Path, data_dir: &Path, file: &str, output: Option<&Path>) -> Result<(), AppError> {
+    let app_config = config.load()?;
+    let (instruction_set, plan, report) = generate(&app_config, cache_dir, data_dir, file)?;
+
+    let preview_path = write_preview_to(cache_dir, &app_config.physical_dimensions(), &instruction_set, &plan)?;
+    optimise::write_report(cache_dir, report.as_ref());
+
+    match output {
+        Some(output) => {
+            std::fs::copy(&preview_path, output)?;
+            println!("Preview saved to {}", output.display());
+        },
+        None => { println!("Preview saved to {}", preview_path); },
+    }
+    Ok(())
+}
+
+///
+/// Generates a drawing, and writes its instruction bytes to a file, or its strokes if the file is an SVG, G-code or HPGL.
+/// G-code is written with the G-code settings of the app config.
+///
+/// # Parameters:
+/// - `config`: Where to load the app config from
+/// - `cache_dir`: The app cache directory
+/// - `data_dir`: The app data directory
+/// - `file`: The save file or bundle
+/// - `output`: The path to write the instructions, SVG, G-code or HPGL to
+///
+/// # Returns:
+/// - Void if the function succeeded
+/// - An error explaining why the function could not succeed
+///
+fn export(config: &ConfigSource, cache_dir: &Path, data_dir: &Path, file: &str, output: &Path) -> Result<(), AppError> {
+    let app_config = config.load()?;
+    let (instruction_set, plan, _) = generate(&app_config, cache_dir, data_dir, file)?;
+    let init = instruction_set.get_init();
+
+    let extension = output.extension().map(|ext| ext.to_string_lossy().to_lowercase());
+    match extension.as_deref() {
+        Some("svg") => {
+            export::write_svg(output, &app_config.physical_dimensions(), instruction_set.get_binary().as_slice(), (init.0, init.1), &plan)?;
+            println!("Wrote the drawing to {}", output.display());
+            return Ok(());
+        },
+        Some("gcode" | "nc") => {
+            export::write_gcode(output, &app_config.physical_dimensions(), instruction_set.get_binary().as_slice(), (init.0, init.1), &plan, &app_config.export_gcode)?;
+            println!("Wrote the drawing to {}", output.display());
+            return Ok(());
+        },
+        Some("hpgl" | "plt") => {
+            export::write_hpgl(output, &app_config.physical_dimensions(), instruction_set.get_binary().as_slice(), (init.0, init.1), &plan)?;
+            println!("Wrote the drawing to {}", output.display());
+            return Ok(());
+        },
+        _ => {},
+    }
+
+    let mut handle = File::create(output)?;
+    handle.write_all(instruction_set.get_binary().as_slice())?;
+
+    println!("Wrote {} bytes to {}, starting at ({}, {})", instruction_set.get_binary().len(), output.display(), init.0, init.1);
+    Ok(())
+}
+
+///
+/// Plots a drawing through the app's job runner, so it reconnects and checkpoints as the app does,
+/// printing the machine's progress. The operator is prompted on the terminal at each pen change.
+/// While plotting, the `pause` and `stop` subcommands are accepted through a local control socket.
+///
+/// # Parameters:
+/// - `config`: Where to load the app config from
+/// - `cache_dir`: The app cache directory
+/// - `data_dir`: The app data directory, which checkpoints are saved in
+/// - `file`: The save file or bundle, otherwise the cached drawing is plotted
+///
+/// # Returns:
+/// - Void if the function succeeded
+/// - An error explaining why the function could not succeed
+///
+async fn plot(config: &ConfigSource, cache_dir: &Path, data_dir: &Path, file: Option<&str>) -> Result<(), AppError> {
+    let app_config = config.load()?;
+
+    let (bytes, init, plan) = match file {
+        Some(file) => {
+            let (instruction_set, plan, _) = generate(&app_config, cache_dir, data_dir, file)?;
+            let init = instruction_set.get_init();
+            (instruction_set.get_binary().clone(), (init.0, init.1), plan)
+        },
+        None => read_cached_drawing(cache_dir)?,
+    };
+
+    wire::goto(&app_config, init.0, init.1).await?;
+
+    let session = Arc::new(Session::default());
+
+    let control = TcpListener::bind("127.0.0.1:0").await?;
+    let control_port = control.local_addr()?.port();
+    std::fs::write(cache_dir.join(CONTROL_FILE), control_port.to_string())?;
+    let control_task = tokio::spawn(serve_control(control, session.clone()));
+
+    let ctx = JobContext {
+        machine_id: config.machine_id(),
+        app_config,
+        cache_dir: cache_dir.to_owned(),
+        data_dir: data_dir.to_owned(),
+        emit: Arc::new(print_progress(session.clone())),
+    };
+    let result = client::run_job(&ctx, &session, &bytes, init, &plan).await;
+
+    control_task.abort();
+    let _ = std::fs::remove_file(cache_dir.join(CONTROL_FILE));
+    result.map(|_| ())
+}
+
+///
+/// Prints a job's progress events, and prompts the operator on the terminal when it waits for a pen change.
+///
+/// # Parameters:
+/// - `session`: The session the job runs in, which the operator's answer is sent through
+///
+/// # Returns:
+/// - The function to report the job's progress events to
+///
+fn print_progress(session: Arc<Session>) -> impl Fn(ProgressEvent) + Send + Sync {
+    move |event| {
+        let prompt = match &event {
+            ProgressEvent::PenChange { name, colour, .. } => Some(format!("Load {} ({}) and press enter, or type q to stop: ", name, colour)),
+            _ => None,
+        };
+        print_message(event);
+
+        // the job waits on its own task, so the terminal is read on another thread
+        if let Some(prompt) = prompt {
+            let session = session.clone();
+            std::thread::spawn(move || {
+                print!("{}", prompt);
+                let _ = std::io::stdout().flush();
+                let mut line = String::new();
+                let _ = std::io::stdin().lock().read_line(&mut line);
+
+                if let Some(swap_tx) = session.pen_swap.blocking_lock().take() {
+                    let _ = swap_tx.send(line.trim() != "q");
+                }
+            });
+        }
+    }
+}
+
+///
+/// Prints a progress event as a versioned JSON line.
+///
+/// # Parameters:
+/// - `event`: The progress event
+///
+fn print_message(event: ProgressEvent) {
+    if let Ok(line) = serde_json::to_string(&event.into_message(None)) {
+        println!("{}", line);
+    }
+}
+
+///
+/// The instruction bytes, (x, y) starting position and pen plan of a drawing.
+///
+type CachedDrawing = (Vec<u8>, (f64, f64), PenPlan);
+
+///
+/// Reads the most recently previewed drawing from the cache.
+///
+/// # Parameters:
+/// - `cache_dir`: The app cache directory
+///
+/// # Returns:
+/// - The instruction bytes, (x, y) starting position and pen plan of the drawing
+/// - An error if no drawing has been previewed
+///
+fn read_cached_drawing(cache_dir: &Path) -> Result<CachedDrawing, AppError> {
+    let mut bytes = Vec::new();
+    File::open(cache_dir.join("instructions.bin")).and_then(|mut handle| handle.read_to_end(&mut bytes)).map_err(|_| AppError::no_preview())?;
+
+    let start = std::fs::read_to_string(cache_dir.join("start.bin")).map_err(|_| AppError::no_preview())?;
+    let init: Vec<f64> = start.split_whitespace().filter_map(|s| s.parse::<f64>().ok()).collect();
+    let init = match init.as_slice() {
+        [x, y] => (*x, *y),
+        _ => { return Err(AppError::NoPreview("The cached start position is corrupt.".to_owned())); }
+    };
+
+    Ok((bytes, init, pens::read_plan(cache_dir)))
+}
+
+///
+/// Accepts `pause`, `resume` and `stop` commands from other invocations of the CLI,
+/// and forwards them to the machine.
+///
+/// # Parameters:
+/// - `control`: The bound control socket
+/// - `session`: The session of the running job
+///
+async fn serve_control(control: TcpListener, session: Arc<Session>) {
+    while let Ok((stream, _)) = control.accept().await {
+        // each connection is served on its own, so one which never sends a command can't hold up a stop
+        let session = session.clone();
+        tokio::spawn(async move {
+            let (reader, mut reply) = stream.into_split();
+            let mut line = String::new();
+            if BufReader::new(reader).read_line(&mut line).await.is_err() {
+                return;
+            }
+
+            let response = match line.trim() {
+                "pause" => session.set_paused(true, print_message).await.map(|_| "Paused".to_owned()),
+                "resume" => session.set_paused(false, print_message).await.map(|_| "Resumed".to_owned()),
+                "stop" => session.stop(print_message).await.map(|_| "Stopped".to_owned()),
+                other => Ok(format!("Unknown command: {}", other)),
+            };
+
+            let _ = reply.write_all(response.unwrap_or_else(|err| err.to_string()).as_bytes()).await;
+        });
+    }
+}
+
+///
+/// Sends a command to a running `plot`, and prints its response.
+///
+/// # Parameters:
+/// - `cache_dir`: The app cache directory
+/// - `command`: The command to send
+///
+/// # Returns:
+/// - Void if the function succeeded
+/// - An error explaining why the function could not succeed
+///
+async fn send_control(cache_dir: &Path, command: &str) -> Result<(), AppError> {
+    let port = std::fs::read_to_string(cache_dir.join(CONTROL_FILE)).map_err(|_| AppError::Protocol("Nothing is being plotted.".to_owned()))?;
+
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{}", port.trim())).await.map_err(|_| AppError::Protocol("Nothing is being plotted.".to_owned()))?;
+    stream.write_all(format!("{}\n", command).as_bytes()).await?;
+
+    let mut response = String::new();
+    let _ = tokio::io::AsyncReadExt::read_to_string(&mut stream, &mut response).await;
+    println!("{}", response);
+    Ok(())
+}
+
+///
+/// Applies a manual control to the machine.
+///
+/// # Parameters:
+/// - `config`: Where to load the app config from
+/// - `target_byte`: The control to apply
+/// - `data`: The value of the control
+///
+/// # Returns:
+/// - Void if the function succeeded
+/// - An error explaining why the function could not succeed
+///
+async fn manual_control(config: &ConfigSource, target_byte: u8, data: i16) -> Result<(), AppError> {
+    let app_config = config.load()?;
+    wire::manual_control(&app_config, target_byte, data).await
+}
@@ -0,0 +1,129 @@
+use std::time::Duration;
+
+use desktop_lib::discovery::Responder;
+use desktop_lib::mock_firmware::{MockConfig, MockFirmware};
+use desktop_lib::protocol;
+
+
+///
+/// Runs a simulated Blot Bot firmware until interrupted, so the app can be demonstrated without a machine.
+///
+/// Usage: `mock_firmware [--addr 127.0.0.1:8080] [--buffer-size N] [--max-speed N] [--pulse-width N]
+/// [--protocol N] [--ack-delay-ms N] [--disconnect-after N] [--skip-handshake] [--discoverable] [--pty]`
+///
+/// With `--discoverable`, it also answers discovery probes on `protocol::DISCOVERY_PORT`.
+/// With `--pty`, it's attached to a pseudo-terminal pair instead of listening on TCP, and prints
+/// the serial port to set in a machine profile. Pseudo-terminals are only available on Unix.
+///
+#[tokio::main]
+async fn main() {
+    let mut addr = "127.0.0.1:8080".to_owned();
+    let mut config = MockConfig::default();
+    let mut discoverable = false;
+    let mut pty = false;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--skip-handshake" {
+            config.skip_handshake = true;
+            continue;
+        }
+        if arg == "--discoverable" {
+            discoverable = true;
+            continue;
+        }
+        if arg == "--pty" {
+            pty = true;
+            continue;
+        }
+
+        let value = match args.next() {
+            Some(val) => val,
+            None => { eprintln!("Missing value for {}", arg); std::process::exit(2); }
+        };
+        let number = || match value.parse::<u64>() {
+            Ok(val) => val,
+            Err(_) => { eprintln!("Invalid value for {}: {}", arg, value); std::process::exit(2); }
+        };
+
+        match arg.as_str() {
+            "--addr" => { addr = value.clone(); },
+            "--buffer-size" => { config.handshake.instruction_buffer_size = number() as u32; },
+            "--max-speed" => { config.handshake.max_motor_speed = number() as u32; },
+            "--pulse-width" => { config.handshake.min_pulse_width = number() as u32; },
+            "--protocol" => { config.handshake.protocol_version = number() as u8; },
+            "--ack-delay-ms" => { config.ack_delay = Duration::from_millis(number()); },
+            "--disconnect-after" => { config.disconnect_after = Some(number() as usize); },
+            _ => { eprintln!("Unknown argument: {}", arg); std::process::exit(2); }
+        }
+    }
+
+    if pty {
+        run_pty(config).await;
+        return;
+    }
+
+    let handshake = config.handshake;
+    let firmware = match MockFirmware::bind(addr.as_str(), config).await {
+        Ok(val) => val,
+        Err(err) => { eprintln!("Couldn't listen on {}: {}", addr, err); std::process::exit(1); }
+    };
+    let firmware_addr = match firmware.addr() {
+        Some(val) => val,
+        None => { eprintln!("Couldn't listen on {}", addr); std::process::exit(1); }
+    };
+    println!("Simulated machine listening on {}", firmware_addr);
+
+    let responder = if discoverable {
+        let discovery_addr = format!("0.0.0.0:{}", protocol::DISCOVERY_PORT);
+        match Responder::bind(discovery_addr.as_str(), firmware_addr.port(), handshake).await {
+            Ok(val) => { println!("Answering discovery probes on {}", val.addr()); Some(val) },
+            Err(err) => { eprintln!("Couldn't listen on {}: {}", discovery_addr, err); std::process::exit(1); }
+        }
+    } else {
+        None
+    };
+
+    let _ = tokio::signal::ctrl_c().await;
+    if let Some(responder) = responder {
+        responder.shutdown();
+    }
+    let log = firmware.log().await;
+    println!("Received {} bytes in {} buffers over {} connections.", log.instructions.len(), log.buffers.len(), log.connections);
+    firmware.shutdown();
+}
+
+
+///
+/// Runs a simulated machine on a pseudo-terminal pair until interrupted.
+///
+/// # Parameters:
+/// - `config`: The behaviour of the machine
+///
+#[cfg(unix)]
+async fn run_pty(config: MockConfig) {
+    let (machine_end, app_end) = match tokio_serial::SerialStream::pair() {
+        Ok(val) => val,
+        Err(err) => { eprintln!("Couldn't open a pseudo-terminal: {}", err); std::process::exit(1); }
+    };
+
+    // the app's end is held open, so the machine's end isn't hung up between sessions
+    let port_name = tokio_serial::SerialPort::name(&app_end).unwrap_or_default();
+    let firmware = MockFirmware::attach(Box::new(machine_end), config);
+    println!("Simulated machine attached to {}", port_name);
+
+    let _ = tokio::signal::ctrl_c().await;
+    let log = firmware.log().await;
+    println!("Received {} bytes in {} buffers over {} sessions.", log.instructions.len(), log.buffers.len(), log.connections);
+    firmware.shutdown();
+    drop(app_end);
+}
+
+///
+/// Pseudo-terminals are only available on Unix.
+///
+#[cfg(not(unix))]
+async fn run_pty(_: MockConfig) {
+    eprintln!("--pty is only supported on Unix.");
+    std::process::exit(2);
+}
@@ -0,0 +1,256 @@
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+use serde_json::Value;
+use zip::write::SimpleFileOptions;
+use zip::{ZipArchive, ZipWriter};
+
+
+///
+/// The file extension of a self-contained drawing bundle.
+///
+pub const BUNDLE_EXTENSION: &str = "bbz";
+
+///
+/// The name of the save file document inside a bundle.
+///
+const DOCUMENT_NAME: &str = "drawing.json";
+
+///
+/// The directory inside a bundle which holds the referenced assets.
+///
+const ASSETS_DIR: &str = "assets";
+
+
+///
+/// Checks whether a path should be saved as a bundle, based on its extension.
+///
+/// # Parameters:
+/// - `path`: The path to save to
+///
+/// # Returns:
+/// - True if the path has the bundle extension
+///
+pub fn is_bundle_path(path: &str) -> bool {
+    Path::new(path).extension().is_some_and(|ext| ext == BUNDLE_EXTENSION)
+}
+
+///
+/// Checks whether a file is a bundle, by looking for the zip archive signature.
+///
+/// # Parameters:
+/// - `path`: The path of the file to check
+///
+/// # Returns:
+/// - True if the file is a zip archive
+///
+pub fn is_bundle_file(path: &str) -> bool {
+    let mut signature = [0u8; 4];
+    match File::open(path) {
+        Ok(mut handle) => handle.read_exact(&mut signature).is_ok() && signature == *b"PK\x03\x04",
+        Err(_) => false,
+    }
+}
+
+
+///
+/// Writes a save file document to a bundle, copying every asset it references into the archive.
+/// The asset paths in the document are rewritten to point inside the bundle.
+///
+/// # Parameters:
+/// - `path`: The path to save the bundle to
+/// - `asset_fields`: The drawing parameters which hold asset paths, from the style registry
+/// - `document`: The serialized save file, which is modified in place
+///
+/// # Returns:
+/// - Void if the function succeeded
+/// - An error, as a string, explaining why the function did not succeed
+///
+pub fn write_bundle(path: &str, asset_fields: &[&str], mut document: Value) -> Result<(), String> {
+    let file_handle = File::create(path).map_err(|err| err.to_string())?;
+    let mut writer = ZipWriter::new(file_handle);
+    let options = SimpleFileOptions::default();
+
+    for field in asset_fields {
+        let asset_path = match document["drawing_parameters"][*field].as_str() {
+            Some(val) if !val.is_empty() => val.to_owned(),
+            _ => continue,
+        };
+
+        let file_name = match Path::new(&asset_path).file_name() {
+            Some(name) => name.to_string_lossy().to_string(),
+            None => { return Err(format!("Invalid asset path: {}", asset_path).to_owned()); }
+        };
+        let bundled_path = format!("{}/{}-{}", ASSETS_DIR, field, file_name);
+
+        let mut asset = File::open(&asset_path).map_err(|err| format!("Couldn't read asset {}: {}", asset_path, err))?;
+        writer.start_file(bundled_path.as_str(), options).map_err(|err| err.to_string())?;
+        io::copy(&mut asset, &mut writer).map_err(|err| err.to_string())?;
+
+        document["drawing_parameters"][*field] = Value::from(bundled_path);
+    }
+
+    writer.start_file(DOCUMENT_NAME, options).map_err(|err| err.to_string())?;
+    writer.write_all(document.to_string().as_bytes()).map_err(|err| err.to_string())?;
+    writer.finish().map_err(|err| err.to_string())?;
+
+    Ok(())
+}
+
+///
+/// Reads a bundle, unpacking its assets into a directory.
+///
+/// # Parameters:
+/// - `path`: The path of the bundle
+/// - `extract_dir`: The directory to unpack the assets into
+///
+/// # Returns:
+/// - The save file document, with asset paths still relative to the bundle
+/// - An error, as a string, explaining why the function did not succeed, such as an entry escaping the directory
+///
+pub fn read_bundle(path: &str, extract_dir: &Path) -> Result<Value, String> {
+    let file_handle = File::open(path).map_err(|err| err.to_string())?;
+    let mut archive = ZipArchive::new(file_handle).map_err(|err| err.to_string())?;
+
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i).map_err(|err| err.to_string())?;
+
+        // enclosed_name rejects entries which would escape the extraction directory
+        let relative_path = match entry.enclosed_name() {
+            Some(val) => val,
+            None => { return Err(format!("The bundle has an entry outside its own directory: {}", entry.name())); }
+        };
+        if !relative_path.starts_with(ASSETS_DIR) || !entry.is_file() {
+            continue;
+        }
+
+        let out_path = extract_dir.join(relative_path);
+        if let Some(parent) = out_path.parent() {
+            std::fs::create_dir_all(parent).map_err(|err| err.to_string())?;
+        }
+        let mut out_file = File::create(&out_path).map_err(|err| err.to_string())?;
+        io::copy(&mut entry, &mut out_file).map_err(|err| err.to_string())?;
+    }
+
+    let document = archive.by_name(DOCUMENT_NAME).map_err(|_| "Bundle has no drawing".to_owned())?;
+    serde_json::from_reader(document).map_err(|err| err.to_string())
+}
+
+///
+/// Rewrites the bundle-relative asset paths of drawing parameters to their unpacked location.
+///
+/// # Parameters:
+/// - `asset_fields`: The drawing parameters which hold asset paths, from the style registry
+/// - `parameters`: The drawing parameters, modified in place
+/// - `extract_dir`: The directory the bundle was unpacked into
+///
+pub fn localise_assets(asset_fields: &[&str], parameters: &mut Value, extract_dir: &Path) {
+    for field in asset_fields {
+        let bundled_path = match parameters[*field].as_str() {
+            Some(val) if val.starts_with(ASSETS_DIR) => val.to_owned(),
+            _ => continue,
+        };
+
+        let local_path = extract_dir.join(bundled_path);
+        parameters[*field] = Value::from(local_path.to_string_lossy().to_string());
+    }
+}
+
+///
+/// Removes the directories earlier bundles were unpacked into. Only the open drawing's
+/// assets are needed, as it's regenerated from them, so every other directory is removed.
+///
+/// # Parameters:
+/// - `bundles_dir`: The directory bundles are unpacked into
+/// - `keep`: The directory of the open drawing, or None to remove them all
+///
+pub fn remove_extractions(bundles_dir: &Path, keep: Option<&Path>) {
+    let entries = match std::fs::read_dir(bundles_dir) {
+        Ok(val) => val,
+        Err(_) => { return; }
+    };
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() && keep != Some(path.as_path()) {
+            let _ = std::fs::remove_dir_all(path);
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    ///
+    /// A temporary directory for a test, removed when dropped.
+    ///
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> TempDir {
+            let dir = std::env::temp_dir().join(format!("blot-bundle-test-{}-{}", std::process::id(), name));
+            std::fs::create_dir_all(&dir).unwrap();
+            TempDir(dir)
+        }
+
+        fn path(&self, name: &str) -> String {
+            self.0.join(name).to_string_lossy().to_string()
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn round_trips_a_drawing_and_its_assets() {
+        let dir = TempDir::new("round-trip");
+        std::fs::write(dir.path("photo.png"), b"not really a png").unwrap();
+        let document = serde_json::json!({
+            "drawing_method": "image",
+            "drawing_parameters": { "image_path": dir.path("photo.png"), "unused_path": "", "scale": 2 },
+        });
+
+        let bundle = dir.path("drawing.bbz");
+        write_bundle(&bundle, &["image_path", "unused_path"], document).unwrap();
+        assert!(is_bundle_path(&bundle));
+        assert!(is_bundle_file(&bundle));
+        assert!(!is_bundle_file(&dir.path("photo.png")));
+
+        let extract_dir = dir.0.join("extracted");
+        let mut read = read_bundle(&bundle, &extract_dir).unwrap();
+        assert_eq!(read["drawing_method"], "image");
+        assert_eq!(read["drawing_parameters"]["image_path"], "assets/image_path-photo.png");
+        assert_eq!(read["drawing_parameters"]["unused_path"], "");
+        assert_eq!(read["drawing_parameters"]["scale"], 2);
+
+        localise_assets(&["image_path", "unused_path"], &mut read["drawing_parameters"], &extract_dir);
+        let local_path = read["drawing_parameters"]["image_path"].as_str().unwrap().to_owned();
+        assert!(Path::new(&local_path).starts_with(&extract_dir));
+        assert_eq!(std::fs::read(local_path).unwrap(), b"not really a png");
+    }
+
+    #[test]
+    fn rejects_entries_outside_the_bundle() {
+        let dir = TempDir::new("traversal");
+        let bundle = dir.path("evil.bbz");
+
+        let mut writer = ZipWriter::new(File::create(&bundle).unwrap());
+        writer.start_file("assets/../../escaped.txt", SimpleFileOptions::default()).unwrap();
+        writer.write_all(b"escaped").unwrap();
+        writer.start_file(DOCUMENT_NAME, SimpleFileOptions::default()).unwrap();
+        writer.write_all(b"{}").unwrap();
+        writer.finish().unwrap();
+
+        let extract_dir = dir.0.join("bundles").join("evil");
+        assert!(read_bundle(&bundle, &extract_dir).is_err());
+        assert!(!dir.0.join("escaped.txt").exists());
+        assert!(!extract_dir.exists());
+    }
+}
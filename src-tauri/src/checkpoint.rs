@@ -0,0 +1,157 @@
+use std::fs::File;
+use std::io::{BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+
+use serde::{Serialize, Deserialize};
+
+use crate::motion;
+use crate::pens::PenPlan;
+use crate::error::AppError;
+
+
+///
+/// The progress of the running job, saved to the disk as the drawing progresses
+/// so an interrupted drawing can be resumed. The job's instructions are saved alongside it.
+///
+/// # Fields:
+/// - `init_x`: The horizontal starting position of the job
+/// - `init_y`: The vertical starting position of the job
+/// - `plan`: The pen plan of the job
+/// - `acknowledged`: The index of the last instruction byte acknowledged by the machine
+/// - `total_bytes`: The number of instruction bytes in the job
+///
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Checkpoint {
+    pub init_x: f64,
+    pub init_y: f64,
+    pub plan: PenPlan,
+    pub acknowledged: usize,
+    pub total_bytes: usize,
+}
+
+
+///
+/// Gets the path of one of a machine's checkpoint files in the app data directory.
+/// The machine ID is hex encoded, so any profile name makes a valid and unique file name.
+///
+/// # Parameters:
+/// - `data_dir`: The app data directory
+/// - `machine_id`: The ID of the machine
+/// - `extension`: The extension of the file
+///
+/// # Returns:
+/// - The path to the file
+///
+fn checkpoint_path(data_dir: &Path, machine_id: &str, extension: &str) -> PathBuf {
+    let _ = std::fs::create_dir_all(data_dir);
+
+    let encoded_id: String = machine_id.bytes().map(|b| format!("{:02x}", b)).collect();
+    data_dir.join(format!("checkpoint-{}.{}", encoded_id, extension))
+}
+
+///
+/// Saves a new checkpoint at the start of a job, including a copy of its instructions.
+///
+/// # Parameters:
+/// - `data_dir`: The app data directory
+/// - `machine_id`: The ID of the machine
+/// - `bytes`: The instruction bytes of the job
+/// - `init`: The (x, y) starting position of the job
+/// - `plan`: The pen plan of the job
+///
+pub fn begin(data_dir: &Path, machine_id: &str, bytes: &[u8], init: (f64, f64), plan: &PenPlan) {
+    if let Ok(mut handle) = File::create(checkpoint_path(data_dir, machine_id, "bin")) {
+        let _ = handle.write_all(bytes);
+    }
+    write(data_dir, machine_id, &Checkpoint { init_x: init.0, init_y: init.1, plan: plan.clone(), acknowledged: 0, total_bytes: bytes.len() });
+}
+
+///
+/// Updates the acknowledged byte index of the current checkpoint.
+/// The index is rounded down to a whole instruction.
+///
+/// # Parameters:
+/// - `data_dir`: The app data directory
+/// - `machine_id`: The ID of the machine
+/// - `acknowledged`: The index of the last acknowledged instruction byte
+///
+pub fn update(data_dir: &Path, machine_id: &str, acknowledged: usize) {
+    if let Some(mut checkpoint) = read(data_dir, machine_id) {
+        checkpoint.acknowledged = (acknowledged - acknowledged % motion::INSTRUCTION_SIZE).min(checkpoint.total_bytes);
+        write(data_dir, machine_id, &checkpoint);
+    }
+}
+
+///
+/// Removes the checkpoint, once a job has finished or been stopped.
+///
+/// # Parameters:
+/// - `data_dir`: The app data directory
+/// - `machine_id`: The ID of the machine
+///
+pub fn clear(data_dir: &Path, machine_id: &str) {
+    let _ = std::fs::remove_file(checkpoint_path(data_dir, machine_id, "json"));
+    let _ = std::fs::remove_file(checkpoint_path(data_dir, machine_id, "bin"));
+}
+
+///
+/// Loads the current checkpoint.
+///
+/// # Parameters:
+/// - `data_dir`: The app data directory
+/// - `machine_id`: The ID of the machine
+///
+/// # Returns:
+/// - The checkpoint, or None if there's no interrupted job
+///
+pub fn read(data_dir: &Path, machine_id: &str) -> Option<Checkpoint> {
+    let handle = File::open(checkpoint_path(data_dir, machine_id, "json")).ok()?;
+    serde_json::from_reader(BufReader::new(handle)).ok()
+}
+
+///
+/// Loads the instructions of the job saved with the current checkpoint.
+///
+/// # Parameters:
+/// - `data_dir`: The app data directory
+/// - `machine_id`: The ID of the machine
+///
+/// # Returns:
+/// - The instruction bytes, or None if they couldn't be read
+///
+pub fn read_bytes(data_dir: &Path, machine_id: &str) -> Option<Vec<u8>> {
+    let mut buffer = Vec::new();
+    File::open(checkpoint_path(data_dir, machine_id, "bin")).ok()?.read_to_end(&mut buffer).ok()?;
+    Some(buffer)
+}
+
+///
+/// Saves a checkpoint to the disk.
+///
+/// # Parameters:
+/// - `data_dir`: The app data directory
+/// - `machine_id`: The ID of the machine
+/// - `checkpoint`: The checkpoint to save
+///
+fn write(data_dir: &Path, machine_id: &str, checkpoint: &Checkpoint) {
+    if let Ok(handle) = File::create(checkpoint_path(data_dir, machine_id, "json")) {
+        let _ = serde_json::to_writer(handle, checkpoint);
+    }
+}
+
+
+///
+/// A Tauri command which gets the checkpoint of an interrupted drawing.
+///
+/// # Parameters:
+/// - `app`: Injected dependency from Tauri
+/// - `machine_id`: The ID of the machine
+///
+/// # Returns:
+/// - The checkpoint, or None if there's nothing to resume
+///
+#[tauri::command(async)]
+pub async fn get_checkpoint(app: tauri::AppHandle, machine_id: &str) -> Result<Option<Checkpoint>, AppError> {
+    let data_dir = tauri::Manager::path(&app).app_data_dir().map_err(|err| AppError::Io(err.to_string()))?;
+    Ok(read(&data_dir, machine_id))
+}
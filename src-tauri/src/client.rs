@@ -1,100 +1,475 @@
-use std::io::{BufRead, BufReader, Read};
-use std::ops::DerefMut;
-use bbcore::client;
-use tokio::sync::Mutex;
-use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use std::io::{BufReader, Read};
+use tokio::sync::{oneshot, Mutex};
 use std::sync::Arc;
-use tauri::{Emitter, Manager, State};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use tauri::State;
+use serde::Serialize;
+use tokio::io::AsyncWriteExt;
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
 use std::fs::File;
-use std::io::Write;
+use std::path::PathBuf;
 
 use bbcore::client::state::ClientState;
 use bbcore::instruction::InstructionSet;
-use bbcore::hardware::PhysicalDimensions;
 
 use crate::file::{get_app_config_struct, AppConfig};
+use crate::pens::{self, PenPlan};
+use crate::checkpoint;
+use crate::motion;
+use crate::estimate::{self, Estimator, MachineProfile};
+use crate::profiles::{self, ProfileStore};
+use crate::error::AppError;
+use crate::progress::ProgressEvent;
+use crate::codec;
+use crate::optimise::{self, OptimisationReport};
+use crate::reconnect::{self, Backoff};
+use crate::transport::Transport;
+use crate::wire::{self, StopSignal};
+
+
+/// 
+/// How long a connection test waits for the machine's handshake.
+///
+const CONNECTION_TEST_TIMEOUT: Duration = Duration::from_secs(5);
 
 
 /// 
 /// Loads the cached instructions and sends them to the firmware for execution.
+/// Drawings with several pens are sent one segment at a time. At each pen boundary the
+/// job stops, emits a `pen_change` event, and waits for `confirm_pen_change` before resuming.
 /// It emits updates to the window through the `firm-prog` channel.
 ///
 /// # Parameters:
 /// - `app`: Injected dependency from Tauri
 /// - `state`: A Tauri-injected global state object
+/// - `machine_id`: The ID of the machine to draw on, the name of its profile
 ///
 /// # Returns:
 /// - Void if the function succeeded
 /// - An error explaining why the function could not succeed
 ///
 #[tauri::command(async)]
-pub async fn send_to_firmware(app: tauri::AppHandle, state: State<'_, AppState>) -> Result<(), String> {
-
-    let win = app.get_webview_window("main").unwrap();
+pub async fn send_to_firmware(app: tauri::AppHandle, state: State<'_, AppState>, machine_id: &str) -> Result<(), AppError> {
 
-    let cache_dir = tauri::Manager::path(&app).app_cache_dir().expect("Should get cache dir");
-    let _ = std::fs::create_dir_all(&cache_dir).map_err(|s| s.to_string());
+    let cache_dir = tauri::Manager::path(&app).app_cache_dir().map_err(|err| AppError::Io(err.to_string()))?;
 
     // getting instruction bytes
-    let ins_file_path = cache_dir.join("instructions.bin");
-    let mut ins_file = File::open(ins_file_path).unwrap();
-    let mut buffer = Vec::new();
-    let _ = ins_file.read_to_end(&mut buffer).unwrap();
+    let buffer = read_cached_instructions(&cache_dir)?;
 
     let ins_set = match InstructionSet::new(buffer, 0., 0.) { 
         Ok(val) => { val },
-        Err(e) => { println!("{e}"); return Err(e.to_string()); },
+        Err(e) => { return Err(AppError::InvalidParameters(e.to_string())); },
     };
+    let plan = pens::read_plan(&cache_dir);
+    let init = read_start_position(&app).unwrap_or((0., 0.));
 
-    let app_config = match get_app_config_struct(&app) {
-        Ok(val) => val,
-        Err(_) => { return Err("Print settings have not been configured.".to_owned()); }
+    let ctx = JobContext::for_app(&app, machine_id)?;
+    let session = state.session(machine_id).await;
+    run_job(&ctx, &session, ins_set.get_binary().as_slice(), init, &plan).await.map(|_| ())
+}
+
+/// 
+/// Resumes an interrupted drawing from its checkpoint. The pen is moved, raised, to the
+/// position at the last acknowledged byte, then the rest of the drawing is streamed.
+/// The machine doesn't know where the pen was left, so the operator must first move it back
+/// to the top left of the page, where the move starts from.
+/// It emits updates to the window through the `firm-prog` channel.
+///
+/// # Parameters:
+/// - `app`: Injected dependency from Tauri
+/// - `state`: A Tauri-injected global state object
+/// - `machine_id`: The ID of the machine whose drawing was interrupted
+/// - `homed`: True once the operator has moved the pen back to the top left of the page
+///
+/// # Returns:
+/// - Void if the function succeeded
+/// - An error explaining why the function could not succeed
+///
+#[tauri::command(async)]
+pub async fn resume_job(app: tauri::AppHandle, state: State<'_, AppState>, machine_id: &str, homed: bool) -> Result<(), AppError> {
+
+    if !homed {
+        return Err(AppError::InvalidParameters("Move the pen to the top left of the page before resuming the drawing.".to_owned()));
+    }
+
+    let ctx = JobContext::for_app(&app, machine_id)?;
+    let (saved, bytes) = match (checkpoint::read(&ctx.data_dir, machine_id), checkpoint::read_bytes(&ctx.data_dir, machine_id)) {
+        (Some(saved), Some(bytes)) if saved.total_bytes == bytes.len() => (saved, bytes),
+        _ => { return Err(AppError::NoPreview("There is no interrupted drawing to resume.".to_owned())); }
     };
 
-    let mut buf_idx_lock = state.buf_idx.lock().await;
-    *buf_idx_lock = 0;
-    drop(buf_idx_lock);
+    let phys_dim = ctx.app_config.physical_dimensions();
 
+    let (x, y, pen_down) = motion::end_position(&phys_dim, &bytes[..saved.acknowledged], (saved.init_x, saved.init_y));
 
-    win.emit("firm-prog", format!(r#"{{"event":"populate_network", "address":"{}"}}"#, format!("{}:{}", app_config.machine_addr, app_config.machine_port))).unwrap();
-    win.emit("firm-prog", format!(r#"{{"event":"populate_draw", "totalBytes":"{}"}}"#, ins_set.get_binary().len())).unwrap();
+    wire::goto(&ctx.app_config, x, y).await?;
 
-    // create new socket, and split it into owned directions
-    let (client, machine_config) = ClientState::new(&app_config.machine_addr, app_config.machine_port).await.unwrap();
-    let (stream_reader, stream_writer) = client.into_split();
+    // the pen arrives raised, so lower it again if it was drawing when the job was interrupted
+    let mut remaining = if pen_down { motion::encode(&[motion::Instruction::Pen(true)]) } else { Vec::new() };
+    let prefix_len = remaining.len();
+    remaining.extend_from_slice(&bytes[saved.acknowledged..]);
 
-    win.emit("firm-prog", r#"{"event":"connection", "message":"Machine accepted connection"}"#).unwrap();
-    win.emit("firm-prog", format!(r#"{{"event":"populate_machine", "insBytes":"{}", "stepSpeed":"{}", "pulseWidth":"{}", "protocol":"{}"}}"#, machine_config.instruction_buffer_size, machine_config.max_motor_speed, machine_config.min_pulse_width, machine_config.protocol_version)).unwrap();
-    
-    // lock writer, set writer as owned, drop it
-    let mut writer_lock = state.writer.lock().await;
-    *writer_lock = Some(stream_writer);
-    drop(writer_lock);
+    let plan = saved.plan.resume_from(saved.acknowledged, prefix_len);
 
-    // lock reader, set writer as owned, launch listen task
-    let mut reader_lock = state.reader.lock().await;
-    *reader_lock = Some(stream_reader);
-    let reader = reader_lock.as_mut().unwrap();
+    let session = state.session(machine_id).await;
+    run_job(&ctx, &session, remaining.as_slice(), (x, y), &plan).await.map(|_| ())
+}
+
+/// 
+/// Sends an instruction stream to the firmware for execution, one pen segment at a time.
+/// The acknowledged byte index is checkpointed to the disk every second, so an
+/// interrupted drawing can be resumed with `resume_job`. If the connection is lost, the job
+/// reconnects with back-off and carries on from the last acknowledged byte, until the
+/// profile's reconnection timeout runs out.
+/// It emits updates to the window through the `firm-prog` channel, tagged with the machine.
+/// Each machine runs one job at a time, but different machines can draw at once.
+///
+/// # Parameters:
+/// - `ctx`: The machine and where the job reports to, see `JobContext`
+/// - `session`: The machine's drawing session
+/// - `bytes`: The instruction bytes of the job
+/// - `init`: The (x, y) starting position of the job
+/// - `plan`: The job's pen plan
+///
+/// # Returns:
+/// - True if the job finished, false if it was stopped or cancelled
+/// - An error explaining why the function could not succeed
+///
+pub async fn run_job(ctx: &JobContext, session: &Session, bytes: &[u8], init: (f64, f64), plan: &PenPlan) -> Result<bool, AppError> {
+    if session.drawing.swap(true, Ordering::SeqCst) {
+        return Err(AppError::Protocol(format!("{} is already drawing.", ctx.machine_id)));
+    }
+
+    let result = stream_job(ctx, session, bytes, init, plan).await;
+
+    session.drawing.store(false, Ordering::SeqCst);
+    result
+}
 
-    ClientState::listen(reader, &state.writer, &state.buf_idx, &ins_set, &machine_config, move |msg| { let _ = win.emit("firm-prog", msg.as_str()); }).await;
+/// 
+/// Streams a job to a machine, see `run_job`.
+///
+/// # Parameters:
+/// - `ctx`: The machine and where the job reports to, see `JobContext`
+/// - `session`: The machine's drawing session
+/// - `bytes`: The instruction bytes of the job
+/// - `init`: The (x, y) starting position of the job
+/// - `plan`: The job's pen plan
+///
+/// # Returns:
+/// - True if the job finished, false if it was stopped or cancelled
+/// - An error explaining why the function could not succeed
+///
+async fn stream_job(ctx: &JobContext, session: &Session, bytes: &[u8], init: (f64, f64), plan: &PenPlan) -> Result<bool, AppError> {
+
+    let app_config = &ctx.app_config;
+    let transport = Transport::from_config(app_config)?;
+    let phys_dim = app_config.physical_dimensions();
+    let cache_dir = ctx.cache_dir.as_path();
+
+
+    ctx.emit(ProgressEvent::PopulateNetwork { address: transport.to_string() });
+    ctx.emit(ProgressEvent::PopulateDraw { total_bytes: bytes.len(), page_width: phys_dim.page_width(), page_height: phys_dim.page_height() });
+
+    // the pen's path is decoded up front, so its position can be looked up as bytes are acknowledged
+    let samples = Arc::new(motion::trace(&phys_dim, bytes, init));
+    let samples_sent = Arc::new(AtomicUsize::new(0));
+
+    // the estimate starts from the configured or last seen machine, and switches once the machine reports its own
+    let job_bytes = Arc::new(bytes.to_vec());
+    let estimator = Arc::new(std::sync::Mutex::new(Estimator::new(bytes, estimate::machine_profile(Some(app_config), cache_dir))));
+    if let Ok(estimator) = estimator.lock() {
+        ctx.emit(estimator.event());
+    }
 
-    let mut writer_lock = state.writer.lock().await;
-    *writer_lock = None;
-    *reader_lock = None;
-    let mut paused_lock = state.paused_flag.lock().await;
-    *paused_lock = false;
-    let mut buf_idx_lock = state.buf_idx.lock().await;
-    *buf_idx_lock = 0;
+    let reconnect_timeout = reconnect::reconnect_timeout(app_config);
+
+    let stopped = Arc::new(AtomicBool::new(false));
+    checkpoint::begin(&ctx.data_dir, &ctx.machine_id, bytes, init, plan);
+
+    for idx in 0..plan.segments.len() {
+        let (start, end) = plan.segment_range(idx, bytes.len());
+        if start >= end {
+            continue;
+        }
+        let is_last = end == bytes.len();
+
+        // the pen is raised at the start of every segment but the first, so wait for the operator to swap pens
+        if idx > 0 {
+            let pen = plan.segment_pen(idx);
+            let (swap_tx, swap_rx) = oneshot::channel();
+            session.stop.reset();
+            *session.pen_swap.lock().await = Some(swap_tx);
+
+            ctx.emit(ProgressEvent::PenChange { pen: plan.segments[idx].pen, name: pen.name, colour: pen.colour });
+
+            // there's no connection while the operator swaps pens, so stopping the drawing cancels the wait
+            let proceed = tokio::select! {
+                res = swap_rx => matches!(res, Ok(true)),
+                _ = session.stop.requested() => false,
+            };
+            *session.pen_swap.lock().await = None;
+
+            if !proceed {
+                ctx.emit(ProgressEvent::Shutdown);
+                stopped.store(true, Ordering::SeqCst);
+                break;
+            }
+        }
+
+        // the segment is sent from here, which moves up to the last acknowledged byte each time the connection is lost
+        let mut sent_from = start;
+        let mut backoff: Option<Backoff> = None;
+        let mut reconnecting = false;
+
+        loop {
+            if let (true, Some(backoff)) = (reconnecting, backoff.as_mut()) {
+                let delay = match backoff.next_delay() {
+                    Some(val) => val,
+                    None => {
+                        return Err(AppError::Connection(format!("Couldn't reconnect to the machine after {} seconds. The drawing can be resumed.", backoff.elapsed().as_secs())));
+                    }
+                };
+                ctx.emit(ProgressEvent::Reconnecting { attempt: backoff.attempts(), retry_in_ms: delay.as_millis() as u64, ins_pos: sent_from });
+
+                // stopping the drawing while it waits cancels the reconnection
+                let (cancel_tx, cancel_rx) = oneshot::channel();
+                *session.reconnect_cancel.lock().await = Some(cancel_tx);
+                let cancelled = tokio::select! {
+                    _ = tokio::time::sleep(delay) => false,
+                    res = cancel_rx => res.is_ok(),
+                };
+                *session.reconnect_cancel.lock().await = None;
+
+                if cancelled {
+                    ctx.emit(ProgressEvent::Shutdown);
+                    stopped.store(true, Ordering::SeqCst);
+                    break;
+                }
+            }
+
+            let mut buf_idx_lock = session.buf_idx.lock().await;
+            *buf_idx_lock = 0;
+            drop(buf_idx_lock);
+
+            // create new socket, and split it into owned directions. Serial bridges serve one connection, so each attempt gets its own
+            let endpoint = match transport.endpoint().await {
+                Ok(val) => val,
+                Err(_) if reconnecting => { continue; },
+                Err(err) => { return Err(AppError::Connection(format!("Couldn't connect to the machine: {}", err))); }
+            };
+            let (stream_reader, mut stream_writer, machine_config) = match wire::connect(&endpoint).await {
+                Ok(val) => val,
+                Err(_) if reconnecting => { continue; },
+                Err(err) => { return Err(err); }
+            };
+
+            ctx.emit(ProgressEvent::PopulateMachine {
+                instruction_buffer_size: machine_config.instruction_buffer_size as u64,
+                max_motor_speed: machine_config.max_motor_speed as u64,
+                min_pulse_width: machine_config.min_pulse_width as u64,
+                protocol_version: machine_config.protocol_version as u64,
+            });
+
+            // refuse a machine speaking a protocol this app can't, before sending it anything
+            let codec = match codec::negotiate(machine_config.protocol_version) {
+                Ok(val) => val,
+                Err(err) => {
+                    let _ = stream_writer.shutdown().await;
+                    return Err(err);
+                }
+            };
+            let seg_set = match InstructionSet::new(codec.encode_instructions(&bytes[sent_from..end]), 0., 0.) {
+                Ok(val) => val,
+                Err(e) => { return Err(AppError::InvalidParameters(e.to_string())); },
+            };
+
+            if reconnecting {
+                ctx.emit(ProgressEvent::Reconnected { ins_pos: sent_from });
+            }
+            ctx.emit(ProgressEvent::Connection { message: "Machine accepted connection".to_owned() });
+
+            let reported = MachineProfile { max_motor_speed: machine_config.max_motor_speed as u64, min_pulse_width: machine_config.min_pulse_width as u64 };
+            if let Err(err) = estimate::save_last_seen(cache_dir, &reported) {
+                #[cfg(debug_assertions)]
+                println!("Couldn't save the machine profile: {}", err);
+            }
+            if let Ok(mut estimator) = estimator.lock() {
+                estimator.set_profile(bytes, estimate::machine_profile(Some(app_config), cache_dir));
+            }
+
+            // lock writer, set writer as owned, drop it
+            let mut writer_lock = session.writer.lock().await;
+            *writer_lock = Some(stream_writer);
+            drop(writer_lock);
+
+            // lock reader, set writer as owned, launch listen task
+            let mut reader_lock = session.reader.lock().await;
+            *reader_lock = Some(stream_reader);
+            let reader = match reader_lock.as_mut() {
+                Some(val) => val,
+                None => { return Err(AppError::Connection("The connection to the machine was lost.".to_owned())); }
+            };
+
+            // checkpoint the acknowledged index every second, until the connection ends
+            let (done_tx, mut done_rx) = oneshot::channel::<()>();
+            let checkpoint_dir = ctx.data_dir.clone();
+            let checkpoint_buf_idx = session.buf_idx.clone();
+            let checkpoint_machine = ctx.machine_id.clone();
+            let checkpointer = tokio::spawn(async move {
+                let mut last_idx = 0;
+                loop {
+                    tokio::select! {
+                        _ = &mut done_rx => { break; },
+                        _ = tokio::time::sleep(Duration::from_secs(1)) => {},
+                    }
+                    let buf_idx = *checkpoint_buf_idx.lock().await;
+                    if buf_idx != last_idx {
+                        checkpoint::update(&checkpoint_dir, &checkpoint_machine, sent_from + buf_idx);
+                        last_idx = buf_idx;
+                    }
+                }
+            });
+
+            // the drawing only finishes after the last segment, and a shutdown ends every segment
+            let seg_emit = ctx.emit.clone();
+            let seg_stopped = stopped.clone();
+            let seg_finished = Arc::new(AtomicBool::new(false));
+            let listen_finished = seg_finished.clone();
+            let seg_samples = samples.clone();
+            let seg_samples_sent = samples_sent.clone();
+            let seg_bytes = job_bytes.clone();
+            let seg_estimator = estimator.clone();
+            ClientState::listen(reader, &session.writer, &session.buf_idx, &seg_set, &machine_config, move |msg| {
+                let event = match ProgressEvent::from_bbcore(msg.as_str()) {
+                    Some(val) => val,
+                    None => { return; }
+                };
+                match event {
+                    ProgressEvent::DrawingFinished => {
+                        listen_finished.store(true, Ordering::SeqCst);
+                        if !is_last { return; }
+                    },
+                    ProgressEvent::Shutdown => { seg_stopped.store(true, Ordering::SeqCst); },
+                    ProgressEvent::Drawing { mut secs_remaining, ins_pos } => {
+                        // the machine counts from where this connection started sending, so offset it into the whole job
+                        let ins_pos = sent_from + ins_pos;
+                        if let Ok(mut estimator) = seg_estimator.lock() {
+                            estimator.update(&seg_bytes, ins_pos);
+                            secs_remaining = estimator.secs_remaining();
+                            seg_emit(estimator.event());
+                        }
+                        seg_emit(ProgressEvent::Drawing { secs_remaining, ins_pos });
+                        seg_emit(pen_position(&seg_samples, &seg_samples_sent, ins_pos, init));
+                        return;
+                    },
+                    _ => {},
+                }
+                seg_emit(event);
+            }).await;
+
+            let _ = done_tx.send(());
+            let _ = checkpointer.await;
+            let acknowledged = *session.buf_idx.lock().await;
+            checkpoint::update(&ctx.data_dir, &ctx.machine_id, sent_from + acknowledged);
+
+            let mut writer_lock = session.writer.lock().await;
+            *writer_lock = None;
+            *reader_lock = None;
+            let mut paused_lock = session.paused_flag.lock().await;
+            *paused_lock = false;
+            let mut buf_idx_lock = session.buf_idx.lock().await;
+            *buf_idx_lock = 0;
+
+            drop(paused_lock);
+            drop(writer_lock);
+            drop(reader_lock);
+            drop(buf_idx_lock);
+
+            if stopped.load(Ordering::SeqCst) || seg_finished.load(Ordering::SeqCst) {
+                break;
+            }
+
+            // the connection ended without the machine finishing or stopping, so keep the checkpoint and reconnect
+            if reconnect_timeout.is_zero() {
+                return Err(AppError::Connection("The connection to the machine was lost. The drawing can be resumed.".to_owned()));
+            }
+
+            // resend from the last whole instruction the machine acknowledged. The back-off only restarts
+            // once the machine makes progress, so one which keeps dropping the connection still times out
+            let acknowledged = acknowledged - acknowledged % motion::INSTRUCTION_SIZE;
+            sent_from = (sent_from + acknowledged).min(end);
+            if sent_from == end {
+                if is_last { ctx.emit(ProgressEvent::DrawingFinished); }
+                break;
+            }
+            if acknowledged > 0 || backoff.is_none() {
+                backoff = Some(Backoff::new(reconnect_timeout));
+            }
+            reconnecting = true;
+        }
+
+        if stopped.load(Ordering::SeqCst) {
+            break;
+        }
+    }
 
-    drop(paused_lock);
-    drop(writer_lock);
-    drop(reader_lock);
-    drop(buf_idx_lock);
+    checkpoint::clear(&ctx.data_dir, &ctx.machine_id);
 
     #[cfg(debug_assertions)]
     println!("Cleanly exited drawing.");
 
-    Ok(())
+    Ok(!stopped.load(Ordering::SeqCst))
+}
+
+/// 
+/// Estimates the pen's position once the machine has reached a byte index, along with the
+/// strokes drawn since the previous estimate.
+///
+/// # Parameters:
+/// - `samples`: The trace of the job's instructions
+/// - `samples_sent`: The number of samples already sent, updated to include the new samples
+/// - `ins_pos`: The byte index the machine has reached
+/// - `init`: The (x, y) starting position of the job
+///
+/// # Returns:
+/// - The pen position event
+///
+fn pen_position(samples: &[motion::PenSample], samples_sent: &AtomicUsize, ins_pos: usize, init: (f64, f64)) -> ProgressEvent {
+    let until = motion::samples_until(samples, ins_pos);
+    let from = samples_sent.swap(until, Ordering::SeqCst).min(until);
+    let drawn = motion::trace_strokes(samples, from..until, init);
+
+    let (x, y, pen_down) = match until.checked_sub(1).and_then(|i| samples.get(i)) {
+        Some(sample) => (sample.x, sample.y, sample.pen_down),
+        None => (init.0, init.1, false),
+    };
+
+    ProgressEvent::PenPosition { ins_pos, x, y, pen_down, drawn }
+}
+
+/// 
+/// Confirms or cancels a pen swap, resuming or ending a job waiting at a pen boundary.
+///
+/// # Parameters:
+/// - `state`: A Tauri-injected global state object
+/// - `machine_id`: The ID of the machine waiting for a pen swap
+/// - `proceed`: True if the operator has swapped the pen, false to cancel the job
+///
+/// # Returns:
+/// - Void if the function succeeded
+/// - An error explaining why the function could not succeed
+///
+#[tauri::command(async)]
+pub async fn confirm_pen_change(state: State<'_, AppState>, machine_id: &str, proceed: bool) -> Result<(), AppError> {
+    let session = state.existing_session(machine_id).await?;
+    let swap_tx = session.pen_swap.lock().await.take();
+    match swap_tx {
+        Some(swap_tx) => { let _ = swap_tx.send(proceed); Ok(()) },
+        None => Err(AppError::Protocol("The job is not waiting for a pen change.".to_owned())),
+    }
 }
 
 /// 
@@ -104,27 +479,18 @@ pub async fn send_to_firmware(app: tauri::AppHandle, state: State<'_, AppState>)
 /// # Parameters:
 /// - `app`: Injected dependency from Tauri
 /// - `state`: A Tauri-injected global state object
+/// - `machine_id`: The ID of the machine to pause
 ///
 /// # Returns:
 /// - Void if the function succeeded
 /// - An error explaining why the function could not succeed
 ///
 #[tauri::command(async)]
-pub async fn pause_firmware(app: tauri::AppHandle, state: State<'_, AppState>) -> Result<(), String>  {
-
-    let mut writer_lock = state.writer.lock().await;
-    let writer = writer_lock.as_mut().unwrap();
+pub async fn pause_firmware(app: tauri::AppHandle, state: State<'_, AppState>, machine_id: &str) -> Result<(), AppError>  {
 
-    let mut paused_lock = state.paused_flag.lock().await;
-    *paused_lock.deref_mut() = !(*paused_lock);
-
-    let win = app.get_webview_window("main").unwrap();
-
-    ClientState::pause(writer, *paused_lock, move |msg| { let _ = win.emit("firm-prog", msg.as_str()); }).await;
-    
-    // drops occur out of scope
-
-    Ok(())
+    let session = state.existing_session(machine_id).await?;
+    let paused = !*session.paused_flag.lock().await;
+    session.set_paused(paused, |event| event.emit(&app, machine_id)).await
 }
 
 /// 
@@ -138,28 +504,38 @@ pub async fn pause_firmware(app: tauri::AppHandle, state: State<'_, AppState>) -
 /// - An error explaining why the function could not succeed
 ///
 #[tauri::command(async)]
-pub async fn move_pen_to_start(app: tauri::AppHandle) -> Result<(), String>  {
+pub async fn move_pen_to_start(app: tauri::AppHandle) -> Result<(), AppError>  {
+
+    let start_pos = match read_start_position(&app) {
+        Some(val) => val,
+        None => { return Err(AppError::no_preview()); }
+    };
+
+    let app_config = get_app_config_struct(&app)?;
+    wire::goto(&app_config, start_pos.0, start_pos.1).await
+}
+
+/// 
+/// Reads the cached starting position of the previewed drawing.
+///
+/// # Parameters:
+/// - `app`: The Tauri app handle
+///
+/// # Returns:
+/// - The (x, y) starting position, or None if no drawing has been previewed
+///
+pub(crate) fn read_start_position(app: &tauri::AppHandle) -> Option<(f64, f64)> {
+    let cache_dir = tauri::Manager::path(app).app_cache_dir().ok()?;
 
-    let cache_dir = tauri::Manager::path(&app).app_cache_dir().expect("Should get cache dir");
-    
-    // getting start position
-    let start_file_path = cache_dir.join("start.bin");
-    let start_file = File::open(start_file_path).unwrap();
+    let start_file = File::open(cache_dir.join("start.bin")).ok()?;
     let mut start_contents = String::new();
-    BufReader::new(start_file).read_to_string(&mut start_contents).unwrap();
+    BufReader::new(start_file).read_to_string(&mut start_contents).ok()?;
     let start_pos: Vec<f64> = start_contents.split_whitespace().filter_map(|s| s.parse::<f64>().ok()).collect();
 
-    let app_config = match get_app_config_struct(&app) {
-        Ok(val) => val,
-        Err(_) => { return Err("Print settings have not been configured.".to_owned()); }
-    };
-    let phys_dim = PhysicalDimensions::new(app_config.phys_motor_interspace, app_config.phys_page_left_offset, app_config.phys_page_top_offset, app_config.phys_page_width, app_config.phys_page_height);
-
-    if let Err(str) = bbcore::client::move_to_start(&app_config.machine_addr, app_config.machine_port, &phys_dim, start_pos[0], start_pos[1]) {
-        return Err(str.to_string().to_owned());
+    match start_pos.as_slice() {
+        [x, y] => Some((*x, *y)),
+        _ => None,
     }
-
-    Ok(())
 }
 
 /// 
@@ -173,18 +549,10 @@ pub async fn move_pen_to_start(app: tauri::AppHandle) -> Result<(), String>  {
 /// - An error explaining why the function could not succeed
 ///
 #[tauri::command(async)]
-pub async fn apply_manual_control(app: tauri::AppHandle, target_byte: u8, data: i16) -> Result<(), String>  {
+pub async fn apply_manual_control(app: tauri::AppHandle, target_byte: u8, data: i16) -> Result<(), AppError>  {
 
-    let app_config = match get_app_config_struct(&app) {
-        Ok(val) => val,
-        Err(_) => { return Err("Print settings have not been configured.".to_owned()); }
-    };
-
-    if let Err(str) = bbcore::client::apply_manual_control(&app_config.machine_addr, app_config.machine_port, target_byte, data) {
-        return Err(str.to_string().to_owned());
-    }
-
-    Ok(())
+    let app_config = get_app_config_struct(&app)?;
+    wire::manual_control(&app_config, target_byte, data).await
 }
 
 /// 
@@ -198,104 +566,390 @@ pub async fn apply_manual_control(app: tauri::AppHandle, target_byte: u8, data:
 /// - An error explaining why the function could not succeed
 ///
 #[tauri::command(async)]
-pub async fn manual_goto(app: tauri::AppHandle, x: f64, y: f64) -> Result<(), String>  {
-    let start_pos: Vec<f64> = vec![x, y];
-
-    let app_config = match get_app_config_struct(&app) {
-        Ok(val) => val,
-        Err(_) => { return Err("Print settings have not been configured.".to_owned()); }
-    };
-    let phys_dim = PhysicalDimensions::new(app_config.phys_motor_interspace, app_config.phys_page_left_offset, app_config.phys_page_top_offset, app_config.phys_page_width, app_config.phys_page_height);
-
-    if let Err(str) = bbcore::client::move_to_start(&app_config.machine_addr, app_config.machine_port, &phys_dim, start_pos[0], start_pos[1]) {
-        return Err(str.to_string().to_owned());
-    }
-
-    Ok(())
+pub async fn manual_goto(app: tauri::AppHandle, x: f64, y: f64) -> Result<(), AppError>  {
+    let app_config = get_app_config_struct(&app)?;
+    wire::goto(&app_config, x, y).await
 }
 
 
 /// 
-/// Sends a stop command to the firmware.
-/// It emits updates to the window through the `firm-prog` channel.
+/// Sends a stop command to the firmware. If the drawing is waiting to reconnect or for a pen swap, the wait is cancelled instead.
+/// The running drawing emits a `shutdown` event through the `firm-prog` channel once it has stopped.
 ///
 /// # Parameters:
 /// - `app`: Injected dependency from Tauri
 /// - `state`: A Tauri-injected global state object
+/// - `machine_id`: The ID of the machine to stop
 ///
 /// # Returns:
 /// - Void if the function succeeded
 /// - An error explaining why the function could not succeed
 ///
 #[tauri::command(async)]
-pub async fn stop_drawing(app: tauri::AppHandle, state: State<'_, AppState>) -> Result<(), String>  {
-    
-    let win = app.get_webview_window("main").unwrap();
+pub async fn stop_drawing(app: tauri::AppHandle, state: State<'_, AppState>, machine_id: &str) -> Result<(), AppError>  {
+
+    let session = state.existing_session(machine_id).await?;
+    session.stop(|event| event.emit(&app, machine_id)).await
+
+}
+
+
+/// 
+/// The result of a connection test.
+///
+/// # Fields:
+/// - `address`: The address tested, in the format `IP:PORT`, or the serial port
+/// - `latency_ms`: The time taken to connect and receive the handshake, in milliseconds
+/// - `protocol_version`: The firmware's protocol version
+/// - `instruction_buffer_size`: The size of the firmware's instruction buffer, in bytes
+/// - `max_motor_speed`: The maximum motor speed, in steps per second
+/// - `min_pulse_width`: The minimum step pulse width, in microseconds
+/// - `warnings`: Problems found with the machine's configuration, such as a protocol mismatch
+///
+#[derive(Serialize, Clone, Debug)]
+pub struct ConnectionReport {
+    pub address: String,
+    pub latency_ms: f64,
+    pub protocol_version: u64,
+    pub instruction_buffer_size: u64,
+    pub max_motor_speed: u64,
+    pub min_pulse_width: u64,
+    pub warnings: Vec<String>,
+}
+
+/// 
+/// A Tauri command which checks a machine is reachable, without drawing anything.
+/// It connects, reads the handshake and disconnects.
+///
+/// # Parameters:
+/// - `state`: A Tauri-injected global state object
+/// - `machine_id`: The ID of the machine to test, the name of its profile
+///
+/// # Returns:
+/// - The connection report
+/// - An error if the machine couldn't be reached, or is drawing
+///
+#[tauri::command(async)]
+pub async fn test_connection(app: tauri::AppHandle, state: State<'_, AppState>, machine_id: &str) -> Result<ConnectionReport, AppError> {
+    if let Ok(session) = state.existing_session(machine_id).await {
+        if session.drawing.load(Ordering::SeqCst) {
+            return Err(AppError::Protocol(format!("{} is drawing, so it can't be tested.", machine_id)));
+        }
+    }
 
-    let mut writer_lock = state.writer.lock().await;
-    let writer = writer_lock.as_mut().unwrap();
-    
-    ClientState::stop(writer, move |msg| { let _ = win.emit("firm-prog", msg.as_str()); }).await;
+    let app_config = profiles::machine_config(&app, machine_id)?;
+    test_connection_to(&Transport::from_config(&app_config)?).await
+}
+
+/// 
+/// Connects to a machine, reads its handshake and disconnects, timing the round trip.
+///
+/// # Parameters:
+/// - `transport`: Where to reach the machine
+///
+/// # Returns:
+/// - The connection report
+/// - An error if the machine couldn't be reached
+///
+pub async fn test_connection_to(transport: &Transport) -> Result<ConnectionReport, AppError> {
+    let started = Instant::now();
 
-    // will finish ClientState::listen in other thread
+    let endpoint = match transport.endpoint().await {
+        Ok(val) => val,
+        Err(err) => { return Err(AppError::Connection(format!("Couldn't connect to the machine: {}", err))); }
+    };
+    let (_, mut writer, machine_config) = match tokio::time::timeout(CONNECTION_TEST_TIMEOUT, wire::connect(&endpoint)).await {
+        Ok(Ok(val)) => val,
+        Ok(Err(err)) => { return Err(err); },
+        Err(_) => { return Err(AppError::Connection(format!("The machine at {} didn't respond.", transport))); },
+    };
+    let latency = started.elapsed();
+
+    let _ = writer.shutdown().await;
+
+    let mut report = ConnectionReport {
+        address: transport.to_string(),
+        latency_ms: latency.as_secs_f64() * 1000.,
+        protocol_version: machine_config.protocol_version as u64,
+        instruction_buffer_size: machine_config.instruction_buffer_size as u64,
+        max_motor_speed: machine_config.max_motor_speed as u64,
+        min_pulse_width: machine_config.min_pulse_width as u64,
+        warnings: Vec::new(),
+    };
 
-    Ok(())
+    if let Err(err) = codec::negotiate(machine_config.protocol_version) {
+        report.warnings.push(err.message().to_owned());
+    }
+    if report.instruction_buffer_size < motion::INSTRUCTION_SIZE as u64 {
+        report.warnings.push(format!("The machine's instruction buffer ({} bytes) can't hold an instruction.", report.instruction_buffer_size));
+    } else if !report.instruction_buffer_size.is_multiple_of(motion::INSTRUCTION_SIZE as u64) {
+        report.warnings.push(format!("The machine's instruction buffer ({} bytes) isn't a whole number of instructions.", report.instruction_buffer_size));
+    }
+    if report.max_motor_speed == 0 {
+        report.warnings.push("The machine reports a maximum motor speed of 0.".to_owned());
+    }
 
+    Ok(report)
 }
 
 
 /// 
 /// Estimates the drawing time, used for the 'Print' button.
+/// It uses the speed set in the print settings, or else the speed of the last connected machine.
 ///
 /// # Parameters:
 /// - `app`: Injected dependency from Tauri
 ///
 /// # Returns:
-/// - A u64 representing the estimated time taken to draw a drawing at 500s/s, or 0
+/// - The estimated time taken to draw the drawing, in seconds, the number of instruction bytes,
+///   and the path optimisation report if the drawing was optimised
+/// - An error if no drawing has been previewed
 ///
 #[tauri::command(async)]
-pub async fn get_image_stats(app: tauri::AppHandle) -> (u64, usize) {
+pub async fn get_image_stats(app: tauri::AppHandle) -> Result<(u64, usize, Option<OptimisationReport>), AppError> {
 
-    let cache_dir = tauri::Manager::path(&app).app_cache_dir().expect("Should get cache dir");
-    let _ = std::fs::create_dir_all(&cache_dir).map_err(|s| s.to_string());
+    let cache_dir = tauri::Manager::path(&app).app_cache_dir().map_err(|err| AppError::Io(err.to_string()))?;
 
     // getting instruction bytes
-    let ins_file_path = cache_dir.join("instructions.bin");
-    let mut ins_file = File::open(ins_file_path).unwrap();
-    let mut buffer = Vec::new();
-    let _ = ins_file.read_to_end(&mut buffer).unwrap();
+    let buffer = read_cached_instructions(&cache_dir)?;
 
     let ins_set = match InstructionSet::new(buffer, 0., 0.) { 
         Ok(val) => { val },
-        Err(e) => { println!("{e}"); return (0, 0); },
+        Err(e) => { return Err(AppError::InvalidParameters(e.to_string())); },
     };
 
-    let dur = client::calculate_draw_time(&ins_set.get_binary(), 500, 0);
+    let app_config = get_app_config_struct(&app).ok();
+    let dur = estimate::draw_time(ins_set.get_binary(), &estimate::machine_profile(app_config.as_ref(), &cache_dir));
 
-    (dur.as_secs(), ins_set.get_binary().len())
+    Ok((dur.as_secs(), ins_set.get_binary().len(), optimise::read_report(&cache_dir)))
+}
+
+/// 
+/// Reads the cached instructions of the previewed drawing.
+///
+/// # Parameters:
+/// - `cache_dir`: The app cache directory
+///
+/// # Returns:
+/// - The instruction bytes
+/// - An error if no drawing has been previewed
+///
+pub(crate) fn read_cached_instructions(cache_dir: &std::path::Path) -> Result<Vec<u8>, AppError> {
+    let mut ins_file = match File::open(cache_dir.join("instructions.bin")) {
+        Ok(handle) => handle,
+        Err(_) => { return Err(AppError::no_preview()); }
+    };
+    let mut buffer = Vec::new();
+    ins_file.read_to_end(&mut buffer)?;
+    Ok(buffer)
 }
 
 
 /// 
-/// A thread-safe global state containing values of the drawing state.
+/// The machine a job runs on, and where it reports to. The app builds one with `for_app`,
+/// and the command line builds its own, so both stream jobs through `run_job`.
+///
+/// # Fields:
+/// - `machine_id`: The ID of the machine, the name of its profile
+/// - `app_config`: The machine's app config
+/// - `cache_dir`: The app cache directory, holding the last seen machine
+/// - `data_dir`: The app data directory, holding checkpoints
+/// - `emit`: Called with every progress event of the job
+///
+#[derive(Clone)]
+pub struct JobContext {
+    pub machine_id: String,
+    pub app_config: AppConfig,
+    pub cache_dir: PathBuf,
+    pub data_dir: PathBuf,
+    pub emit: Arc<dyn Fn(ProgressEvent) + Send + Sync>,
+}
+
+impl JobContext {
+
+    /// 
+    /// Builds the context of a job run from the app, which emits through the `firm-prog` channel.
+    ///
+    /// # Parameters:
+    /// - `app`: The Tauri app handle
+    /// - `machine_id`: The ID of the machine, the name of its profile
+    ///
+    /// # Returns:
+    /// - The job context
+    /// - An error if the machine's profile or the app directories couldn't be found
+    ///
+    pub fn for_app(app: &tauri::AppHandle, machine_id: &str) -> Result<JobContext, AppError> {
+        let path = tauri::Manager::path(app);
+        let emit_app = app.clone();
+        let emit_machine = machine_id.to_owned();
+
+        Ok(JobContext {
+            machine_id: machine_id.to_owned(),
+            app_config: profiles::machine_config(app, machine_id)?,
+            cache_dir: path.app_cache_dir().map_err(|err| AppError::Io(err.to_string()))?,
+            data_dir: path.app_data_dir().map_err(|err| AppError::Io(err.to_string()))?,
+            emit: Arc::new(move |event: ProgressEvent| event.emit(&emit_app, &emit_machine)),
+        })
+    }
+
+    /// 
+    /// Reports a progress event of the job.
+    ///
+    /// # Parameters:
+    /// - `event`: The progress event
+    ///
+    pub fn emit(&self, event: ProgressEvent) {
+        (self.emit)(event)
+    }
+}
+
+/// 
+/// The drawing state of one machine. Each connected machine has its own session,
+/// so several machines can draw at once.
 ///
 /// # Fields:
-/// - `writer`: Mutex-guarded write half of a TcpStream
-/// - `reader`: Mutex-guarded read half of a TcpStream
+/// - `writer`: Mutex-guarded write half of the connection to the machine
+/// - `reader`: Mutex-guarded read half of the connection to the machine
 /// - `paused_flag`: Mutex-guarded flag to represent whether the machine is paused or not
 /// - `buf_idx`: Mutex-guarded usize representing the current buffer bound index
+/// - `pen_swap`: Mutex-guarded sender, set while a job is waiting for the operator to swap pens
+/// - `reconnect_cancel`: Mutex-guarded sender, set while a job is waiting to reconnect to the machine
+/// - `stop`: Cancels a job waiting for a pen swap, see `wire::StopSignal`
+/// - `drawing`: Flag set while a job is running on the machine
 ///
-pub struct AppState {
+#[derive(Default)]
+pub struct Session {
     pub writer: Arc<Mutex<Option<OwnedWriteHalf>>>,
     pub reader: Arc<Mutex<Option<OwnedReadHalf>>>,
     pub paused_flag: Arc<Mutex<bool>>,
     pub buf_idx: Arc<Mutex<usize>>,
+    pub pen_swap: Arc<Mutex<Option<oneshot::Sender<bool>>>>,
+    pub reconnect_cancel: Arc<Mutex<Option<oneshot::Sender<()>>>>,
+    pub stop: Arc<StopSignal>,
+    pub drawing: AtomicBool,
+}
+
+/// 
+/// A thread-safe global state containing values of the drawing state.
+///
+/// # Fields:
+/// - `sessions`: Mutex-guarded drawing sessions, keyed by machine ID
+/// - `paper_swap`: Mutex-guarded sender, set while the queue is waiting for the operator to change the paper
+/// - `queue_running`: Flag set while the job queue is running
+/// - `queue_stop`: Flag set to stop the job queue after the current job
+/// - `queue_file`: Mutex held while the saved queue is read or changed, so concurrent changes aren't lost
+///
+#[derive(Default)]
+pub struct AppState {
+    pub sessions: Mutex<HashMap<String, Arc<Session>>>,
+    pub paper_swap: Arc<Mutex<Option<oneshot::Sender<bool>>>>,
+    pub queue_running: Arc<AtomicBool>,
+    pub queue_stop: Arc<AtomicBool>,
+    pub queue_file: Mutex<()>,
+}
+
+impl Session {
+
+    /// 
+    /// Pauses or resumes the machine's drawing.
+    ///
+    /// # Parameters:
+    /// - `paused`: True to pause, false to resume
+    /// - `on_event`: Called with the events reported by the machine, such as `Pause`
+    ///
+    /// # Returns:
+    /// - Void if the function succeeded
+    /// - An error explaining why the function could not succeed
+    ///
+    pub async fn set_paused<F: FnMut(ProgressEvent)>(&self, paused: bool, mut on_event: F) -> Result<(), AppError> {
+        let mut writer_lock = self.writer.lock().await;
+        let writer = match writer_lock.as_mut() {
+            Some(val) => val,
+            None => { return Err(AppError::Protocol("The machine is not drawing.".to_owned())); }
+        };
+
+        *self.paused_flag.lock().await = paused;
+        ClientState::pause(writer, paused, move |msg| {
+            if let Some(event) = ProgressEvent::from_bbcore(msg.as_str()) { on_event(event); }
+        }).await;
+
+        Ok(())
+    }
+
+    /// 
+    /// Stops the machine's drawing. If the drawing is waiting to reconnect or for a pen swap, the wait is cancelled instead.
+    /// The running job emits a `Shutdown` event once it has stopped.
+    ///
+    /// # Parameters:
+    /// - `on_event`: Called with the events reported by the machine
+    ///
+    /// # Returns:
+    /// - Void if the function succeeded
+    /// - An error explaining why the function could not succeed
+    ///
+    pub async fn stop<F: FnMut(ProgressEvent)>(&self, mut on_event: F) -> Result<(), AppError> {
+        let mut writer_lock = self.writer.lock().await;
+        let writer = match writer_lock.as_mut() {
+            Some(val) => val,
+            None => {
+                if let Some(cancel_tx) = self.reconnect_cancel.lock().await.take() {
+                    let _ = cancel_tx.send(());
+                    return Ok(());
+                }
+                if self.pen_swap.lock().await.is_some() {
+                    self.stop.request();
+                    return Ok(());
+                }
+                return Err(AppError::Protocol("The machine is not drawing.".to_owned()));
+            }
+        };
+
+        // will finish ClientState::listen in the job's task
+        ClientState::stop(writer, move |msg| {
+            if let Some(event) = ProgressEvent::from_bbcore(msg.as_str()) { on_event(event); }
+        }).await;
+
+        Ok(())
+    }
+}
+
+impl AppState {
+
+    /// 
+    /// Gets a machine's drawing session, creating it if the machine hasn't been used yet.
+    ///
+    /// # Parameters:
+    /// - `machine_id`: The ID of the machine
+    ///
+    /// # Returns:
+    /// - The machine's session
+    ///
+    pub async fn session(&self, machine_id: &str) -> Arc<Session> {
+        self.sessions.lock().await.entry(machine_id.to_owned()).or_default().clone()
+    }
+
+    /// 
+    /// Gets the drawing session of a machine which has been used.
+    ///
+    /// # Parameters:
+    /// - `machine_id`: The ID of the machine
+    ///
+    /// # Returns:
+    /// - The machine's session
+    /// - An error if the machine has never drawn
+    ///
+    pub async fn existing_session(&self, machine_id: &str) -> Result<Arc<Session>, AppError> {
+        match self.sessions.lock().await.get(machine_id) {
+            Some(session) => Ok(session.clone()),
+            None => Err(AppError::Protocol(format!("{} is not drawing.", machine_id))),
+        }
+    }
 }
 
 
 
 /// 
-/// A function used to save the machine config to the disk.
+/// A function used to save the machine address to the selected machine profile.
 ///
 /// # Parameters:
 /// - `app`: Injected dependency from Tauri
@@ -305,112 +959,58 @@ pub struct AppState {
 ///
 /// # Returns:
 /// - Ok() if the function succeeded
-/// - A string explaning why the function failed
+/// - An error explaning why the function failed
 ///
 #[tauri::command(async)]
-pub async fn save_machine_config(app: tauri::AppHandle, _: State<'_, AppState>, addr: &str, port: usize) -> Result<(), String>  {
+pub async fn save_machine_config(app: tauri::AppHandle, _: State<'_, AppState>, addr: &str, port: usize) -> Result<(), AppError>  {
 
     // directory handling
-    let cache_dir = tauri::Manager::path(&app).app_cache_dir().expect("Should get cache dir");
-    let _ = std::fs::create_dir_all(&cache_dir).map_err(|s| s.to_string());
+    let cache_dir = tauri::Manager::path(&app).app_cache_dir().map_err(|err| AppError::Io(err.to_string()))?;
+
+    let port = u16::try_from(port).map_err(|_| AppError::InvalidParameters("Invalid port (1-65535).".to_owned()))?;
 
-    let ins_file_path = cache_dir.join("machine_conf");
-    let mut ins_file = File::create(ins_file_path).unwrap();
-    let _ = ins_file.write_all(format!("{}:{}", addr, port).as_bytes());
-    
-    Ok(())
+    let mut store = ProfileStore::read(&cache_dir)?;
+    let name = store.selected.clone().ok_or_else(AppError::not_configured)?;
+    if let Some(app_config) = store.profiles.get_mut(&name) {
+        app_config.machine_addr = addr.to_owned();
+        app_config.machine_port = port;
+    }
 
+    store.write(&cache_dir)
 }
 
 
 /// 
-/// A function used to load the machine config from the disk.
+/// A function used to load the machine address of the selected machine profile.
 ///
 /// # Parameters:
 /// - `app`: Injected dependency from Tauri
-/// - `_`: The app state
 ///
 /// # Returns:
-/// - The user-configured IP and port of the machine in the format `IP:PORT`
-/// - Err() if the function failed
+/// - The user-configured IP and port of the machine in the format `IP:PORT`, or `null` if it hasn't been saved
+/// - An error if the function failed
 ///
 #[tauri::command(async)]
-pub async fn get_machine_config(app: tauri::AppHandle) -> Result<String, ()>  {
-
-    // directory handling
-    let cache_dir = tauri::Manager::path(&app).app_cache_dir().expect("Should get cache dir");
-    let _ = std::fs::create_dir_all(&cache_dir).map_err(|s| s.to_string());
-
-    let ins_file_path = cache_dir.join("machine_conf");
-
-    match ins_file_path.try_exists() {
-        Ok(exists) => if !exists { return Ok("null".to_owned()); },
-        Err(_) => { return Ok("null".to_owned()); },
-    }
-
-    let ins_file = File::open(ins_file_path).unwrap();
-    let mut contents = String::new();
-
-    BufReader::new(ins_file).read_to_string(&mut contents).unwrap();
-    if contents.is_empty() {
-        return Ok("null".to_owned());
-    }
-    contents = match contents.strip_suffix("\n") {
-        Some(c) => c.to_string(),
-        None => contents
-    }; // optionally strip newline if it exists
-
-    let parts: Vec<&str> = contents.split(":").collect();
-
-    if parts.len() != 2 {
-        return Ok("null".to_owned());
-    }
-    
-    Ok(format!("{}:{}", parts.get(0).unwrap(), parts.get(1).unwrap()).to_owned())
+pub async fn get_machine_config(app: tauri::AppHandle) -> Result<String, AppError>  {
+    get_machine_config_noncmd(&app).await
 }
 
 
 /// 
-/// A function used to load the machine config from the disk.
+/// A function used to load the machine address of the selected machine profile.
 ///
 /// # Parameters:
-/// - `app`: Injected dependency from Tauri
-/// - `_`: The app state
+/// - `app`: The Tauri app handle
 ///
 /// # Returns:
-/// - The user-configured IP and port of the machine in the format `IP:PORT`
-/// - Err() if the function failed
+/// - The user-configured IP and port of the machine in the format `IP:PORT`, or `null` if it hasn't been saved
+/// - An error if the function failed
 ///
-pub async fn get_machine_config_noncmd(app: &tauri::AppHandle) -> Result<String, ()>  {
-
-    // directory handling
-    let cache_dir = tauri::Manager::path(app).app_cache_dir().expect("Should get cache dir");
-    let _ = std::fs::create_dir_all(&cache_dir).map_err(|s| s.to_string());
-
-    let ins_file_path = cache_dir.join("machine_conf");
-
-    match ins_file_path.try_exists() {
-        Ok(exists) => if !exists { return Ok("null".to_owned()); },
-        Err(_) => { return Ok("null".to_owned()); },
-    }
-
-    let ins_file = File::open(ins_file_path).unwrap();
-    let mut contents = String::new();
-
-    BufReader::new(ins_file).read_to_string(&mut contents).unwrap();
-    if contents.is_empty() {
-        return Ok("null".to_owned());
-    }
-    contents = match contents.strip_suffix("\n") {
-        Some(c) => c.to_string(),
-        None => contents
-    }; // optionally strip newline if it exists
-
-    let parts: Vec<&str> = contents.split(":").collect();
+pub async fn get_machine_config_noncmd(app: &tauri::AppHandle) -> Result<String, AppError>  {
 
-    if parts.len() != 2 {
-        return Ok("null".to_owned());
+    match get_app_config_struct(app) {
+        Ok(app_config) => Ok(format!("{}:{}", app_config.machine_addr, app_config.machine_port)),
+        Err(AppError::NotConfigured(_)) => Ok("null".to_owned()),
+        Err(err) => Err(err),
     }
-    
-    Ok(format!("{}:{}", parts.get(0).unwrap(), parts.get(1).unwrap()).to_owned())
 }
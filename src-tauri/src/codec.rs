@@ -0,0 +1,145 @@
+use std::ops::RangeInclusive;
+
+use crate::error::AppError;
+use crate::protocol;
+
+
+///
+/// The protocol versions this app can speak.
+///
+pub const SUPPORTED_VERSIONS: RangeInclusive<u8> = protocol::MIN_PROTOCOL_VERSION..=protocol::PROTOCOL_VERSION;
+
+
+///
+/// Converts the app's instruction stream into the wire format of one protocol version.
+/// The app always generates instructions in the format described in `motion`, and each
+/// codec translates them for the firmware it's talking to, so older machines keep working
+/// after the format changes. Supporting a new version means adding a codec to `CODECS`.
+///
+/// Codecs must keep each instruction at the same byte offset, as progress and checkpoints
+/// are tracked by the index the machine acknowledges.
+///
+pub trait Codec: Send + Sync {
+
+    ///
+    /// Gets the protocol version the codec speaks.
+    ///
+    fn version(&self) -> u8;
+
+    ///
+    /// Converts instruction bytes into the wire format.
+    ///
+    /// # Parameters:
+    /// - `bytes`: The instruction bytes, in the format described in `motion`
+    ///
+    /// # Returns:
+    /// - The bytes to send to the machine
+    ///
+    fn encode_instructions(&self, bytes: &[u8]) -> Vec<u8>;
+}
+
+
+///
+/// The codec for protocol version 1, whose wire format is the app's instruction format.
+///
+pub struct V1Codec;
+
+impl Codec for V1Codec {
+    fn version(&self) -> u8 {
+        1
+    }
+
+    fn encode_instructions(&self, bytes: &[u8]) -> Vec<u8> {
+        bytes.to_vec()
+    }
+}
+
+
+///
+/// The codec of every protocol version in `SUPPORTED_VERSIONS`, oldest first.
+///
+const CODECS: &[&dyn Codec] = &[&V1Codec];
+
+
+///
+/// Gets the codec for a protocol version.
+///
+/// # Parameters:
+/// - `version`: The protocol version
+///
+/// # Returns:
+/// - The codec, or None if the version isn't supported
+///
+pub fn codec_for(version: u8) -> Option<&'static dyn Codec> {
+    CODECS.iter().copied().find(|codec| codec.version() == version)
+}
+
+///
+/// Picks the codec for the protocol version a machine reported in its handshake.
+///
+/// # Parameters:
+/// - `version`: The protocol version the machine reported
+///
+/// # Returns:
+/// - The codec to talk to the machine with
+/// - An error explaining whether the app or the firmware needs updating, if the version isn't supported
+///
+pub fn negotiate(version: u8) -> Result<&'static dyn Codec, AppError> {
+    if let Some(codec) = codec_for(version) {
+        return Ok(codec);
+    }
+
+    let oldest = CODECS.iter().map(|codec| codec.version()).min().unwrap_or_default();
+    let newest = CODECS.iter().map(|codec| codec.version()).max().unwrap_or_default();
+    let supported = format!("v{} to v{}", oldest, newest);
+    if version > newest {
+        Err(AppError::Protocol(format!("The machine speaks protocol v{}, which is newer than this app supports ({}). Update the app.", version, supported)))
+    } else {
+        Err(AppError::Protocol(format!("The machine speaks protocol v{}, which is older than this app supports ({}). Update the firmware.", version, supported)))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn has_one_codec_per_supported_version() {
+        let versions: Vec<u8> = CODECS.iter().map(|codec| codec.version()).collect();
+        assert_eq!(versions, SUPPORTED_VERSIONS.collect::<Vec<u8>>());
+    }
+
+    #[test]
+    fn negotiates_every_supported_version() {
+        for version in SUPPORTED_VERSIONS {
+            assert_eq!(negotiate(version).unwrap().version(), version);
+        }
+    }
+
+    #[test]
+    fn refuses_older_versions() {
+        for version in 0..*SUPPORTED_VERSIONS.start() {
+            match negotiate(version) {
+                Err(AppError::Protocol(msg)) => assert!(msg.contains("older") && msg.contains("Update the firmware")),
+                _ => panic!("v{} should be refused", version),
+            }
+        }
+    }
+
+    #[test]
+    fn refuses_newer_versions() {
+        for version in [*SUPPORTED_VERSIONS.end() + 1, u8::MAX] {
+            match negotiate(version) {
+                Err(AppError::Protocol(msg)) => assert!(msg.contains("newer") && msg.contains("Update the app")),
+                _ => panic!("v{} should be refused", version),
+            }
+        }
+    }
+
+    #[test]
+    fn v1_sends_instructions_unchanged() {
+        let bytes: Vec<u8> = (0..=255).collect();
+        assert_eq!(V1Codec.encode_instructions(&bytes), bytes);
+    }
+}
@@ -0,0 +1,114 @@
+use serde::{Serialize, Deserialize};
+use bbcore::hardware::PhysicalDimensions;
+
+use crate::styles;
+use crate::motion;
+use crate::pens::{Pen, Segment};
+
+
+///
+/// A single layer of a composition.
+///
+/// # Fields:
+/// - `drawing_id`: The drawing method ID
+/// - `drawing_parameters`: The drawing parameters
+/// - `offset_x`: The horizontal offset of the layer on the page, in millimetres
+/// - `offset_y`: The vertical offset of the layer on the page, in millimetres
+/// - `pen`: The index of the pen used to draw the layer, in the composition's pen list
+///
+#[derive(Serialize, Deserialize)]
+pub struct Layer {
+    pub drawing_id: String,
+    pub drawing_parameters: serde_json::Value,
+    #[serde(default)]
+    pub offset_x: f64,
+    #[serde(default)]
+    pub offset_y: f64,
+    #[serde(default)]
+    pub pen: usize,
+}
+
+///
+/// A composition of several layers drawn onto the same page.
+/// Compositions aren't offered in the app yet, as they can't be edited or bundled there.
+///
+/// # Fields:
+/// - `layers`: The ordered layers of the composition, drawn first to last
+/// - `pens`: The pens used by the layers
+///
+#[derive(Serialize, Deserialize)]
+pub struct Composition {
+    pub layers: Vec<Layer>,
+    #[serde(default)]
+    pub pens: Vec<Pen>,
+}
+
+
+///
+/// Generates the instructions of every layer, and concatenates them into a single stream.
+/// The pen is raised and travels from the end of each layer to the start of the next.
+/// A new segment starts wherever consecutive layers use different pens.
+///
+/// # Parameters:
+/// - `phys_dim`: The physical dimensions of the machine
+/// - `layers`: The layers to draw, in order
+///
+/// # Returns:
+/// - The instruction bytes, the (x, y) starting position of the first layer, and the pen segments
+/// - An error, as a string, explaining which layer failed to generate
+///
+pub fn compose(phys_dim: &PhysicalDimensions, layers: &[Layer]) -> Result<(Vec<u8>, f64, f64, Vec<Segment>), String> {
+    let mut bytes = Vec::new();
+    let mut segments: Vec<Segment> = Vec::new();
+    let mut init = None;
+    let mut position = (0., 0.);
+
+    for (i, layer) in layers.iter().enumerate() {
+        let style = match styles::get_style(layer.drawing_id.as_str()) {
+            Some(style) => style,
+            None => { return Err(format!("Layer {}: Unknown draw type", i + 1).to_owned()); }
+        };
+
+        // shifting the page moves the whole layer, as styles draw relative to the page
+        let layer_dim = offset_dimensions(phys_dim, layer.offset_x, layer.offset_y);
+        let (layer_bytes, ix, iy) = match (style.gen_instructions)(&layer_dim, layer.drawing_parameters.to_string().as_str()) {
+            Ok(val) => val,
+            Err(err) => { return Err(format!("Layer {}: {}", i + 1, err).to_owned()); }
+        };
+
+        let layer_init = (ix + layer.offset_x, iy + layer.offset_y);
+        match init {
+            None => { init = Some(layer_init); },
+            Some(_) => { bytes.extend(motion::encode(&motion::travel(phys_dim, position, layer_init))); },
+        }
+
+        if segments.last().is_none_or(|segment| segment.pen != layer.pen) {
+            segments.push(Segment { pen: layer.pen, start: bytes.len() });
+        }
+
+        let (end_x, end_y, _) = motion::end_position(&layer_dim, &layer_bytes, (ix, iy));
+        position = (end_x + layer.offset_x, end_y + layer.offset_y);
+        bytes.extend(layer_bytes);
+    }
+
+    match init {
+        Some((ix, iy)) => Ok((bytes, ix, iy, segments)),
+        None => Err("Composition has no layers".to_owned()),
+    }
+}
+
+///
+/// Creates a copy of the physical dimensions, with the page moved by an offset.
+///
+/// # Parameters:
+/// - `phys_dim`: The physical dimensions of the machine
+/// - `offset_x`: The horizontal offset, in millimetres
+/// - `offset_y`: The vertical offset, in millimetres
+///
+/// # Returns:
+/// - The offset physical dimensions
+///
+fn offset_dimensions(phys_dim: &PhysicalDimensions, offset_x: f64, offset_y: f64) -> PhysicalDimensions {
+    PhysicalDimensions::new(phys_dim.motor_interspace(), phys_dim.page_left_offset() + offset_x, phys_dim.page_top_offset() + offset_y, phys_dim.page_width(), phys_dim.page_height())
+}
+
@@ -0,0 +1,269 @@
+use std::net::{Ipv4Addr, SocketAddr};
+use std::time::Duration;
+
+use serde::Serialize;
+use tokio::net::UdpSocket;
+use tokio::task::JoinHandle;
+
+use crate::error::AppError;
+use crate::protocol::{self, Handshake};
+
+
+///
+/// How long discovery waits for replies when no timeout is given.
+///
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_millis(1500);
+
+
+///
+/// A machine which replied to a discovery probe.
+///
+/// # Fields:
+/// - `addr`: The IP address of the machine
+/// - `port`: The TCP port the machine accepts drawings on
+/// - `protocol_version`: The firmware's protocol version
+/// - `instruction_buffer_size`: The size of the firmware's instruction buffer, in bytes
+/// - `max_motor_speed`: The maximum motor speed, in steps per second
+/// - `min_pulse_width`: The minimum step pulse width, in microseconds
+///
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct DiscoveredMachine {
+    pub addr: String,
+    pub port: u16,
+    pub protocol_version: u8,
+    pub instruction_buffer_size: u32,
+    pub max_motor_speed: u32,
+    pub min_pulse_width: u32,
+}
+
+///
+/// Encodes the reply a machine sends to a discovery probe.
+///
+/// # Parameters:
+/// - `port`: The TCP port the machine accepts drawings on
+/// - `handshake`: The machine's handshake
+///
+/// # Returns:
+/// - The reply bytes
+///
+pub fn encode_reply(port: u16, handshake: &Handshake) -> Vec<u8> {
+    let mut bytes = protocol::DISCOVERY_REPLY.to_vec();
+    bytes.extend_from_slice(&port.to_be_bytes());
+    bytes.extend_from_slice(&handshake.to_bytes());
+    bytes
+}
+
+///
+/// Decodes a reply to a discovery probe.
+///
+/// # Parameters:
+/// - `from`: The address the reply came from
+/// - `bytes`: The reply bytes
+///
+/// # Returns:
+/// - The machine, or None if the reply isn't from a Blot Bot
+///
+pub fn parse_reply(from: SocketAddr, bytes: &[u8]) -> Option<DiscoveredMachine> {
+    let body = bytes.strip_prefix(protocol::DISCOVERY_REPLY)?;
+    let port = u16::from_be_bytes([*body.first()?, *body.get(1)?]);
+    let handshake = Handshake::from_bytes(&body[2..])?;
+
+    Some(DiscoveredMachine {
+        addr: from.ip().to_string(),
+        port,
+        protocol_version: handshake.protocol_version,
+        instruction_buffer_size: handshake.instruction_buffer_size,
+        max_motor_speed: handshake.max_motor_speed,
+        min_pulse_width: handshake.min_pulse_width,
+    })
+}
+
+///
+/// Sends a discovery probe to each target, and collects the replies until the timeout.
+/// A machine replying more than once, such as to a broadcast and a direct probe, is listed once.
+///
+/// # Parameters:
+/// - `targets`: The addresses to probe, such as the broadcast address on `DISCOVERY_PORT`
+/// - `timeout`: How long to wait for replies
+///
+/// # Returns:
+/// - The machines which replied, in the order they replied
+/// - An IO error if the probe couldn't be sent
+///
+pub async fn discover(targets: &[SocketAddr], timeout: Duration) -> std::io::Result<Vec<DiscoveredMachine>> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).await?;
+    socket.set_broadcast(true)?;
+
+    for target in targets {
+        socket.send_to(protocol::DISCOVERY_PROBE, target).await?;
+    }
+
+    let mut found: Vec<DiscoveredMachine> = Vec::new();
+    let mut buf = [0u8; 64];
+    let deadline = tokio::time::Instant::now() + timeout;
+
+    while let Ok(received) = tokio::time::timeout_at(deadline, socket.recv_from(&mut buf)).await {
+        let (len, from) = match received {
+            Ok(val) => val,
+            Err(_) => { continue; }
+        };
+        if let Some(machine) = parse_reply(from, &buf[..len]) {
+            if !found.iter().any(|other| other.addr == machine.addr && other.port == machine.port) {
+                found.push(machine);
+            }
+        }
+    }
+
+    Ok(found)
+}
+
+
+///
+/// A stand-in for a machine's discovery responder, answering probes on a local UDP port.
+/// It's used alongside `mock_firmware::MockFirmware` to test discovery without hardware.
+///
+/// # Fields:
+/// - `addr`: The address the responder is listening on
+/// - `task`: The task answering probes
+///
+pub struct Responder {
+    addr: SocketAddr,
+    task: JoinHandle<()>,
+}
+
+impl Responder {
+
+    ///
+    /// Starts answering discovery probes.
+    ///
+    /// # Parameters:
+    /// - `addr`: The address to listen on, such as `0.0.0.0:41337` to answer broadcasts
+    /// - `port`: The TCP port to advertise
+    /// - `handshake`: The handshake to advertise
+    ///
+    /// # Returns:
+    /// - The running responder
+    /// - An IO error if the address couldn't be bound
+    ///
+    pub async fn bind(addr: &str, port: u16, handshake: Handshake) -> std::io::Result<Responder> {
+        let socket = UdpSocket::bind(addr).await?;
+        let addr = socket.local_addr()?;
+        let reply = encode_reply(port, &handshake);
+
+        let task = tokio::spawn(async move {
+            let mut buf = [0u8; 64];
+            while let Ok((len, from)) = socket.recv_from(&mut buf).await {
+                if &buf[..len] == protocol::DISCOVERY_PROBE {
+                    let _ = socket.send_to(&reply, from).await;
+                }
+            }
+        });
+
+        Ok(Responder { addr, task })
+    }
+
+    ///
+    /// Gets the address the responder is listening on.
+    ///
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    ///
+    /// Stops answering probes.
+    ///
+    pub fn shutdown(self) {
+        self.task.abort();
+    }
+}
+
+
+///
+/// A Tauri command which finds Blot Bot machines on the local network, by broadcasting a discovery probe.
+///
+/// # Parameters:
+/// - `timeout_ms`: How long to wait for replies, in milliseconds
+/// - `targets`: Extra `IP:PORT` addresses to probe directly, such as a machine on another subnet
+///
+/// # Returns:
+/// - The machines which replied
+/// - An error if the probe couldn't be sent
+///
+#[tauri::command(async)]
+pub async fn discover_machines(timeout_ms: Option<u64>, targets: Option<Vec<String>>) -> Result<Vec<DiscoveredMachine>, AppError> {
+    let mut addrs = vec![SocketAddr::from((Ipv4Addr::BROADCAST, protocol::DISCOVERY_PORT))];
+    for target in targets.unwrap_or_default() {
+        match target.parse::<SocketAddr>() {
+            Ok(addr) => addrs.push(addr),
+            Err(_) => { return Err(AppError::InvalidParameters(format!("Invalid address: {}", target))); }
+        }
+    }
+
+    let timeout = timeout_ms.map(Duration::from_millis).unwrap_or(DEFAULT_TIMEOUT);
+    discover(&addrs, timeout).await.map_err(|err| AppError::Connection(format!("Couldn't search for machines: {}", err)))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HANDSHAKE: Handshake = Handshake { protocol_version: 1, instruction_buffer_size: 1024, max_motor_speed: 500, min_pulse_width: 20 };
+
+    fn from() -> SocketAddr {
+        SocketAddr::from((Ipv4Addr::new(192, 168, 1, 20), protocol::DISCOVERY_PORT))
+    }
+
+    #[test]
+    fn parses_an_encoded_reply() {
+        let machine = parse_reply(from(), &encode_reply(8080, &HANDSHAKE)).expect("the reply should parse");
+
+        assert_eq!(machine, DiscoveredMachine {
+            addr: "192.168.1.20".to_owned(),
+            port: 8080,
+            protocol_version: 1,
+            instruction_buffer_size: 1024,
+            max_motor_speed: 500,
+            min_pulse_width: 20,
+        });
+    }
+
+    #[test]
+    fn rejects_replies_from_other_devices() {
+        let mut reply = encode_reply(8080, &HANDSHAKE);
+        reply[0] = b'X';
+
+        assert_eq!(parse_reply(from(), &reply), None);
+        assert_eq!(parse_reply(from(), protocol::DISCOVERY_PROBE), None);
+        assert_eq!(parse_reply(from(), b""), None);
+    }
+
+    #[test]
+    fn rejects_truncated_replies() {
+        let reply = encode_reply(8080, &HANDSHAKE);
+
+        for len in protocol::DISCOVERY_REPLY.len()..reply.len() {
+            assert_eq!(parse_reply(from(), &reply[..len]), None, "a {} byte reply should be rejected", len);
+        }
+    }
+
+    #[tokio::test]
+    async fn lists_a_machine_replying_twice_once() {
+        let responder = Responder::bind("127.0.0.1:0", 8080, HANDSHAKE).await.expect("the responder should bind");
+
+        let found = discover(&[responder.addr(), responder.addr()], Duration::from_millis(300)).await.expect("the probe should be sent");
+        responder.shutdown();
+
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].port, 8080);
+    }
+
+    #[tokio::test]
+    async fn ignores_silent_targets() {
+        let silent = std::net::UdpSocket::bind("127.0.0.1:0").expect("the socket should bind");
+
+        let found = discover(&[silent.local_addr().unwrap()], Duration::from_millis(200)).await.expect("the probe should be sent");
+
+        assert!(found.is_empty());
+    }
+}
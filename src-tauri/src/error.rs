@@ -0,0 +1,70 @@
+use serde::Serialize;
+
+
+///
+/// The error returned by every command. It serializes as `{ "kind": ..., "message": ... }`,
+/// so the UI can react to the kind and show the message.
+///
+/// # Variants:
+/// - `NotConfigured`: The print settings haven't been configured, or are invalid
+/// - `NoPreview`: There's no generated drawing to use
+/// - `Connection`: The machine couldn't be reached, or the connection was lost
+/// - `Protocol`: The machine sent something unexpected, or is in the wrong state
+/// - `InvalidParameters`: A drawing, save file or request was invalid
+/// - `Io`: A file couldn't be read or written
+///
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "kind", content = "message")]
+pub enum AppError {
+    NotConfigured(String),
+    NoPreview(String),
+    Connection(String),
+    Protocol(String),
+    InvalidParameters(String),
+    Io(String),
+}
+
+impl AppError {
+
+    ///
+    /// The error returned when the app config is missing or invalid.
+    ///
+    pub fn not_configured() -> AppError {
+        AppError::NotConfigured("Print settings have not been configured.".to_owned())
+    }
+
+    ///
+    /// The error returned when no drawing has been previewed.
+    ///
+    pub fn no_preview() -> AppError {
+        AppError::NoPreview("No drawing has been previewed.".to_owned())
+    }
+
+    ///
+    /// Gets the human readable message of the error.
+    ///
+    pub fn message(&self) -> &str {
+        match self {
+            AppError::NotConfigured(msg)
+            | AppError::NoPreview(msg)
+            | AppError::Connection(msg)
+            | AppError::Protocol(msg)
+            | AppError::InvalidParameters(msg)
+            | AppError::Io(msg) => msg.as_str(),
+        }
+    }
+}
+
+impl std::fmt::Display for AppError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message())
+    }
+}
+
+impl std::error::Error for AppError {}
+
+impl From<std::io::Error> for AppError {
+    fn from(err: std::io::Error) -> Self {
+        AppError::Io(err.to_string())
+    }
+}
@@ -0,0 +1,201 @@
+use std::fs::File;
+use std::io::{BufReader, Read, Write};
+use std::path::Path;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use serde::{Serialize, Deserialize};
+use bbcore::client;
+
+use crate::file::AppConfig;
+use crate::error::AppError;
+use crate::progress::ProgressEvent;
+
+
+///
+/// The motor speed assumed when neither the settings nor a previous connection provide one, in steps per second.
+///
+pub const DEFAULT_MAX_MOTOR_SPEED: u64 = 500;
+
+///
+/// The pulse width assumed when neither the settings nor a previous connection provide one, in microseconds.
+///
+pub const DEFAULT_MIN_PULSE_WIDTH: u64 = 0;
+
+///
+/// The file in the app cache directory holding the profile of the last connected machine.
+///
+const LAST_SEEN_FILE: &str = "machine_profile.json";
+
+
+///
+/// The speed characteristics of a machine, used to estimate drawing times.
+///
+/// # Fields:
+/// - `max_motor_speed`: The maximum motor speed, in steps per second
+/// - `min_pulse_width`: The minimum step pulse width, in microseconds
+///
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct MachineProfile {
+    pub max_motor_speed: u64,
+    pub min_pulse_width: u64,
+}
+
+impl Default for MachineProfile {
+    fn default() -> Self {
+        MachineProfile { max_motor_speed: DEFAULT_MAX_MOTOR_SPEED, min_pulse_width: DEFAULT_MIN_PULSE_WIDTH }
+    }
+}
+
+///
+/// Finds the machine profile to estimate with. Values set in the print settings take priority,
+/// then the values reported by the last connected machine, then the defaults.
+///
+/// # Parameters:
+/// - `app_config`: The app config, if it has been configured
+/// - `cache_dir`: The app cache directory
+///
+/// # Returns:
+/// - The machine profile
+///
+pub fn machine_profile(app_config: Option<&AppConfig>, cache_dir: &Path) -> MachineProfile {
+    let fallback = last_seen(cache_dir).unwrap_or_default();
+
+    MachineProfile {
+        max_motor_speed: app_config.and_then(|config| config.machine_max_motor_speed).filter(|speed| *speed > 0).unwrap_or(fallback.max_motor_speed),
+        min_pulse_width: app_config.and_then(|config| config.machine_min_pulse_width).unwrap_or(fallback.min_pulse_width),
+    }
+}
+
+///
+/// Reads the profile of the last connected machine.
+///
+/// # Parameters:
+/// - `cache_dir`: The app cache directory
+///
+/// # Returns:
+/// - The machine profile, or None if no machine has been connected
+///
+pub fn last_seen(cache_dir: &Path) -> Option<MachineProfile> {
+    let file_handle = File::open(cache_dir.join(LAST_SEEN_FILE)).ok()?;
+    let mut contents = String::new();
+    BufReader::new(file_handle).read_to_string(&mut contents).ok()?;
+
+    serde_json::from_str(contents.as_str()).ok()
+}
+
+///
+/// Saves the profile of a machine which accepted a connection, so later estimates can use it.
+///
+/// # Parameters:
+/// - `cache_dir`: The app cache directory
+/// - `profile`: The profile the machine reported
+///
+/// # Returns:
+/// - Void if the profile was saved
+/// - An error explaining why it couldn't be saved
+///
+pub fn save_last_seen(cache_dir: &Path, profile: &MachineProfile) -> Result<(), AppError> {
+    let contents = serde_json::to_string(profile).map_err(|err| AppError::Io(err.to_string()))?;
+
+    std::fs::create_dir_all(cache_dir)?;
+    let mut file_handle = File::create(cache_dir.join(LAST_SEEN_FILE))?;
+    file_handle.write_all(contents.as_bytes())?;
+
+    Ok(())
+}
+
+///
+/// Estimates how long a machine takes to execute some instruction bytes.
+///
+/// # Parameters:
+/// - `bytes`: The instruction bytes
+/// - `profile`: The machine profile
+///
+/// # Returns:
+/// - The estimated duration
+///
+pub fn draw_time(bytes: &[u8], profile: &MachineProfile) -> Duration {
+    client::calculate_draw_time(bytes, profile.max_motor_speed as _, profile.min_pulse_width as _)
+}
+
+
+///
+/// Keeps a drawing's time estimate up to date as the machine acknowledges instructions.
+/// Only the newly acknowledged bytes are estimated on each update, rather than the whole remainder.
+///
+/// # Fields:
+/// - `profile`: The machine profile being estimated with
+/// - `started`: When the drawing started
+/// - `acknowledged`: The byte index the estimate was last updated to
+/// - `remaining`: The estimated time to draw the bytes after `acknowledged`
+///
+pub struct Estimator {
+    profile: MachineProfile,
+    started: Instant,
+    acknowledged: usize,
+    remaining: Duration,
+}
+
+impl Estimator {
+
+    ///
+    /// Starts estimating a drawing.
+    ///
+    /// # Parameters:
+    /// - `bytes`: The instruction bytes of the drawing
+    /// - `profile`: The machine profile
+    ///
+    pub fn new(bytes: &[u8], profile: MachineProfile) -> Estimator {
+        Estimator { profile, started: Instant::now(), acknowledged: 0, remaining: draw_time(bytes, &profile) }
+    }
+
+    ///
+    /// Switches to the profile reported by the connected machine, re-estimating the remaining bytes.
+    ///
+    /// # Parameters:
+    /// - `bytes`: The instruction bytes of the drawing
+    /// - `profile`: The machine profile
+    ///
+    pub fn set_profile(&mut self, bytes: &[u8], profile: MachineProfile) {
+        if profile != self.profile {
+            self.profile = profile;
+            self.remaining = draw_time(&bytes[self.acknowledged.min(bytes.len())..], &profile);
+        }
+    }
+
+    ///
+    /// Updates the estimate once the machine has reached a byte index.
+    ///
+    /// # Parameters:
+    /// - `bytes`: The instruction bytes of the drawing
+    /// - `ins_pos`: The byte index the machine has reached
+    ///
+    pub fn update(&mut self, bytes: &[u8], ins_pos: usize) {
+        let ins_pos = ins_pos.min(bytes.len());
+        if ins_pos > self.acknowledged {
+            let drawn = draw_time(&bytes[self.acknowledged..ins_pos], &self.profile);
+            self.remaining = self.remaining.saturating_sub(drawn);
+            self.acknowledged = ins_pos;
+        }
+    }
+
+    ///
+    /// Gets the estimated seconds remaining.
+    ///
+    pub fn secs_remaining(&self) -> u64 {
+        self.remaining.as_secs()
+    }
+
+    ///
+    /// Gets the current estimate as an event, with the ETA as a unix timestamp.
+    ///
+    pub fn event(&self) -> ProgressEvent {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+
+        ProgressEvent::Estimate {
+            secs_elapsed: self.started.elapsed().as_secs(),
+            secs_remaining: self.remaining.as_secs(),
+            eta: (now + self.remaining).as_secs(),
+        }
+    }
+}
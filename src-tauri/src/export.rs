@@ -0,0 +1,578 @@
+use std::fmt::Write;
+use std::path::Path;
+
+use bbcore::hardware::PhysicalDimensions;
+use serde::{Serialize, Deserialize};
+
+use crate::client::{read_cached_instructions, read_start_position};
+use crate::file::get_app_config_struct;
+use crate::hpgl;
+use crate::motion;
+use crate::pens::{self, Pen, PenPlan};
+use crate::error::AppError;
+
+
+///
+/// The width of the lines in an exported SVG, in millimetres, roughly that of a fine liner.
+///
+pub const SVG_STROKE_WIDTH: f64 = 0.4;
+
+
+///
+/// How an exported G-code program raises and lowers the pen.
+///
+/// # Variants:
+/// - `Spindle`: `M3` lowers the pen and `M5` raises it, as on laser and servo pen plotters
+/// - `Z`: The pen is moved between two Z heights, as on CNC machines with a pen in the spindle
+///
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum PenLift {
+    #[default]
+    Spindle,
+    Z,
+}
+
+///
+/// The page corner an exported G-code program is measured from.
+/// The X and Y axes run along the page edges from the corner, so every coordinate is positive.
+///
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Origin {
+    TopLeft,
+    TopRight,
+    #[default]
+    BottomLeft,
+    BottomRight,
+}
+
+///
+/// The settings of an exported G-code program, saved in the app config.
+///
+/// # Fields:
+/// - `pen_lift`: How the pen is raised and lowered
+/// - `spindle_power`: The `S` value sent with `M3`, when the pen is lowered with the spindle
+/// - `z_up`: The Z height of the raised pen, in millimetres
+/// - `z_down`: The Z height of the lowered pen, in millimetres
+/// - `plunge_rate`: The feed rate the pen is lowered at, in millimetres per minute
+/// - `feed_rate`: The feed rate strokes are drawn at, in millimetres per minute
+/// - `travel_rate`: The feed rate of pen-up travel, or None to travel with rapid `G0` moves
+/// - `origin`: The page corner the program is measured from
+///
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct GcodeOptions {
+    pub pen_lift: PenLift,
+    pub spindle_power: u32,
+    pub z_up: f64,
+    pub z_down: f64,
+    pub plunge_rate: f64,
+    pub feed_rate: f64,
+    pub travel_rate: Option<f64>,
+    pub origin: Origin,
+}
+
+impl Default for GcodeOptions {
+    fn default() -> Self {
+        GcodeOptions {
+            pen_lift: PenLift::Spindle,
+            spindle_power: 1000,
+            z_up: 5.,
+            z_down: 0.,
+            plunge_rate: 500.,
+            feed_rate: 1500.,
+            travel_rate: None,
+            origin: Origin::BottomLeft,
+        }
+    }
+}
+
+impl GcodeOptions {
+
+    ///
+    /// Checks the feed rates are usable.
+    ///
+    /// # Returns:
+    /// - Void if the options are valid
+    /// - An error naming the invalid option
+    ///
+    pub fn validate(&self) -> Result<(), AppError> {
+        let rates = [("feed rate", Some(self.feed_rate)), ("plunge rate", Some(self.plunge_rate)), ("travel rate", self.travel_rate)];
+        for (name, rate) in rates {
+            if rate.is_some_and(|rate| !rate.is_finite() || rate <= 0.) {
+                return Err(AppError::InvalidParameters(format!("The G-code {} must be greater than 0.", name)));
+            }
+        }
+        Ok(())
+    }
+
+    ///
+    /// Converts a page position to the program's coordinates, measured from the origin corner.
+    ///
+    fn position(&self, phys_dim: &PhysicalDimensions, (x, y): (f64, f64)) -> (f64, f64) {
+        let (width, height) = (phys_dim.page_width(), phys_dim.page_height());
+        match self.origin {
+            Origin::TopLeft => (x, y),
+            Origin::TopRight => (width - x, y),
+            Origin::BottomLeft => (x, height - y),
+            Origin::BottomRight => (width - x, height - y),
+        }
+    }
+
+    ///
+    /// The commands which raise the pen.
+    ///
+    fn pen_up(&self) -> String {
+        match self.pen_lift {
+            PenLift::Spindle => "M5".to_owned(),
+            PenLift::Z => format!("G0 Z{}", number(self.z_up)),
+        }
+    }
+
+    ///
+    /// The commands which lower the pen.
+    ///
+    fn pen_down(&self) -> String {
+        match self.pen_lift {
+            PenLift::Spindle => format!("M3 S{}", self.spindle_power),
+            PenLift::Z => format!("G1 Z{} F{}", number(self.z_down), number(self.plunge_rate)),
+        }
+    }
+
+    ///
+    /// The command which travels to a position with the pen raised.
+    ///
+    fn travel(&self, (x, y): (f64, f64)) -> String {
+        match self.travel_rate {
+            Some(rate) => format!("G1 X{} Y{} F{}", number(x), number(y), number(rate)),
+            None => format!("G0 X{} Y{}", number(x), number(y)),
+        }
+    }
+}
+
+///
+/// The strokes drawn with one pen, which become a layer of an exported drawing.
+///
+/// # Fields:
+/// - `pen`: The pen the strokes are drawn with
+/// - `strokes`: The pen-down strokes, each a list of (x, y) points in page millimetres
+///
+pub struct Layer {
+    pub pen: Pen,
+    pub strokes: Vec<Vec<(f64, f64)>>,
+}
+
+///
+/// Reconstructs the strokes of a drawing in page space, with one layer for each pen of its plan.
+/// Segments drawn with the same pen are merged into one layer, in the order the pens are first used.
+///
+/// # Parameters:
+/// - `phys_dim`: The physical dimensions of the machine
+/// - `bytes`: The instruction bytes
+/// - `init`: The (x, y) starting position of the drawing, in page millimetres
+/// - `plan`: The drawing's pen plan
+///
+/// # Returns:
+/// - The layers of the drawing
+///
+pub fn layers(phys_dim: &PhysicalDimensions, bytes: &[u8], init: (f64, f64), plan: &PenPlan) -> Vec<Layer> {
+    let samples = motion::trace(phys_dim, bytes, init);
+    let mut layers: Vec<(usize, Layer)> = Vec::new();
+
+    for idx in 0..plan.segments.len() {
+        let (start, end) = plan.segment_range(idx, bytes.len());
+        if start >= end {
+            continue;
+        }
+
+        let range = motion::samples_until(&samples, start)..motion::samples_until(&samples, end);
+        let strokes = motion::trace_strokes(&samples, range, init);
+        let pen_idx = plan.segments[idx].pen;
+
+        match layers.iter_mut().find(|(idx, _)| *idx == pen_idx) {
+            Some((_, layer)) => layer.strokes.extend(strokes),
+            None => layers.push((pen_idx, Layer { pen: plan.segment_pen(idx), strokes })),
+        }
+    }
+
+    layers.into_iter().map(|(_, layer)| layer).collect()
+}
+
+///
+/// Writes the layers of a drawing as an SVG document the size of the page, in millimetres.
+/// Each layer is a group named after its pen, which Inkscape opens as a layer.
+///
+/// # Parameters:
+/// - `phys_dim`: The physical dimensions of the machine
+/// - `layers`: The layers of the drawing, see `layers`
+///
+/// # Returns:
+/// - The SVG document
+///
+pub fn svg(phys_dim: &PhysicalDimensions, layers: &[Layer]) -> String {
+    let (width, height) = (number(phys_dim.page_width()), number(phys_dim.page_height()));
+    let mut doc = String::new();
+
+    let _ = writeln!(doc, r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+    let _ = writeln!(doc, r#"<svg xmlns="http://www.w3.org/2000/svg" xmlns:inkscape="http://www.inkscape.org/namespaces/inkscape" width="{}mm" height="{}mm" viewBox="0 0 {} {}">"#, width, height, width, height);
+
+    for (idx, layer) in layers.iter().enumerate() {
+        let _ = writeln!(doc, r#"  <g id="pen-{}" inkscape:groupmode="layer" inkscape:label="{}" fill="none" stroke="{}" stroke-width="{}" stroke-linecap="round" stroke-linejoin="round">"#,
+            idx, escape(layer.pen.name.as_str()), escape(layer.pen.colour.as_str()), SVG_STROKE_WIDTH);
+
+        for stroke in &layer.strokes {
+            let points: Vec<String> = stroke.iter().map(|(x, y)| format!("{},{}", number(*x), number(*y))).collect();
+            let _ = writeln!(doc, r#"    <polyline points="{}" />"#, points.join(" "));
+        }
+
+        let _ = writeln!(doc, "  </g>");
+    }
+
+    let _ = writeln!(doc, "</svg>");
+    doc
+}
+
+///
+/// Exports a drawing as an SVG file, see `svg`.
+///
+/// # Parameters:
+/// - `path`: The path to write the SVG to
+/// - `phys_dim`: The physical dimensions of the machine
+/// - `bytes`: The instruction bytes
+/// - `init`: The (x, y) starting position of the drawing, in page millimetres
+/// - `plan`: The drawing's pen plan
+///
+/// # Returns:
+/// - Void if the SVG was written
+/// - An error if the file couldn't be written
+///
+pub fn write_svg(path: &Path, phys_dim: &PhysicalDimensions, bytes: &[u8], init: (f64, f64), plan: &PenPlan) -> Result<(), AppError> {
+    let doc = svg(phys_dim, &layers(phys_dim, bytes, init, plan));
+    std::fs::write(path, doc).map_err(|err| AppError::Io(format!("Couldn't write the SVG: {}", err)))
+}
+
+///
+/// Writes the layers of a drawing as a G-code program for a Cartesian plotter, in absolute millimetres.
+/// Each stroke is reached with the pen raised, and drawn at the feed rate. The program pauses with `M0`
+/// before each layer after the first, so the pen can be changed.
+///
+/// # Parameters:
+/// - `phys_dim`: The physical dimensions of the machine
+/// - `layers`: The layers of the drawing, see `layers`
+/// - `options`: The settings of the program
+///
+/// # Returns:
+/// - The G-code program
+///
+pub fn gcode(phys_dim: &PhysicalDimensions, layers: &[Layer], options: &GcodeOptions) -> String {
+    let origin = match options.origin {
+        Origin::TopLeft => "top left",
+        Origin::TopRight => "top right",
+        Origin::BottomLeft => "bottom left",
+        Origin::BottomRight => "bottom right",
+    };
+    let mut program = String::new();
+
+    let _ = writeln!(program, "; Blot Bot drawing, {} x {} mm page, origin at the {}", number(phys_dim.page_width()), number(phys_dim.page_height()), origin);
+    let _ = writeln!(program, "G21 ; millimetres");
+    let _ = writeln!(program, "G90 ; absolute positioning");
+    let _ = writeln!(program, "{}", options.pen_up());
+
+    for (idx, layer) in layers.iter().enumerate() {
+        let name = comment(layer.pen.name.as_str());
+        let _ = writeln!(program, "; pen: {} ({})", name, comment(layer.pen.colour.as_str()));
+        if idx > 0 {
+            let _ = writeln!(program, "M0 ; load {}", name);
+        }
+
+        for stroke in &layer.strokes {
+            let mut points = stroke.iter().map(|point| options.position(phys_dim, *point));
+            let Some(first) = points.next() else { continue; };
+
+            let _ = writeln!(program, "{}", options.travel(first));
+            let _ = writeln!(program, "{}", options.pen_down());
+            for (i, (x, y)) in points.enumerate() {
+                // the feed rate is modal, so it only needs setting on the first move of the stroke
+                match i {
+                    0 => { let _ = writeln!(program, "G1 X{} Y{} F{}", number(x), number(y), number(options.feed_rate)); },
+                    _ => { let _ = writeln!(program, "G1 X{} Y{}", number(x), number(y)); },
+                }
+            }
+            let _ = writeln!(program, "{}", options.pen_up());
+        }
+    }
+
+    let _ = writeln!(program, "{}", options.travel((0., 0.)));
+    let _ = writeln!(program, "M2");
+    program
+}
+
+///
+/// Exports a drawing as a G-code file, see `gcode`.
+///
+/// # Parameters:
+/// - `path`: The path to write the program to
+/// - `phys_dim`: The physical dimensions of the machine
+/// - `bytes`: The instruction bytes
+/// - `init`: The (x, y) starting position of the drawing, in page millimetres
+/// - `plan`: The drawing's pen plan
+/// - `options`: The settings of the program
+///
+/// # Returns:
+/// - Void if the program was written
+/// - An error if the options are invalid, or the file couldn't be written
+///
+pub fn write_gcode(path: &Path, phys_dim: &PhysicalDimensions, bytes: &[u8], init: (f64, f64), plan: &PenPlan, options: &GcodeOptions) -> Result<(), AppError> {
+    options.validate()?;
+    let program = gcode(phys_dim, &layers(phys_dim, bytes, init, plan), options);
+    std::fs::write(path, program).map_err(|err| AppError::Io(format!("Couldn't write the G-code: {}", err)))
+}
+
+///
+/// Writes the layers of a drawing as an HPGL program, in plotter units from the bottom left of the page.
+/// Each layer selects its own pen, numbered from 1 in the order the layers are drawn.
+///
+/// # Parameters:
+/// - `phys_dim`: The physical dimensions of the machine
+/// - `layers`: The layers of the drawing, see `layers`
+///
+/// # Returns:
+/// - The HPGL program
+///
+pub fn hpgl(phys_dim: &PhysicalDimensions, layers: &[Layer]) -> String {
+    // HPGL's Y axis points up the page
+    let units = |(x, y): (f64, f64)| ((x * hpgl::UNITS_PER_MM).round() as i64, ((phys_dim.page_height() - y) * hpgl::UNITS_PER_MM).round() as i64);
+    let mut program = String::new();
+
+    let _ = writeln!(program, "IN;PA;");
+    for (idx, layer) in layers.iter().enumerate() {
+        let _ = writeln!(program, "SP{};", idx + 1);
+
+        for stroke in &layer.strokes {
+            let mut points = stroke.iter().map(|point| units(*point));
+            let Some((x, y)) = points.next() else { continue; };
+
+            let coordinates: Vec<String> = points.map(|(x, y)| format!("{},{}", x, y)).collect();
+            let _ = writeln!(program, "PU{},{};PD{};", x, y, coordinates.join(","));
+        }
+    }
+
+    let _ = writeln!(program, "PU;SP0;");
+    program
+}
+
+///
+/// Exports a drawing as an HPGL file, see `hpgl`.
+///
+/// # Parameters:
+/// - `path`: The path to write the program to
+/// - `phys_dim`: The physical dimensions of the machine
+/// - `bytes`: The instruction bytes
+/// - `init`: The (x, y) starting position of the drawing, in page millimetres
+/// - `plan`: The drawing's pen plan
+///
+/// # Returns:
+/// - Void if the program was written
+/// - An error if the file couldn't be written
+///
+pub fn write_hpgl(path: &Path, phys_dim: &PhysicalDimensions, bytes: &[u8], init: (f64, f64), plan: &PenPlan) -> Result<(), AppError> {
+    let program = hpgl(phys_dim, &layers(phys_dim, bytes, init, plan));
+    std::fs::write(path, program).map_err(|err| AppError::Io(format!("Couldn't write the HPGL: {}", err)))
+}
+
+///
+/// Formats a coordinate to the micrometre, without trailing zeros.
+///
+fn number(value: f64) -> String {
+    let formatted = format!("{:.3}", value);
+    let trimmed = formatted.trim_end_matches('0').trim_end_matches('.');
+    match trimmed {
+        "-0" => "0".to_owned(),
+        _ => trimmed.to_owned(),
+    }
+}
+
+///
+/// Makes text safe to write in a G-code comment, replacing the characters which would end it early,
+/// or start another comment, with spaces.
+///
+fn comment(text: &str) -> String {
+    text.chars().map(|c| if c == '(' || c == ')' || c == ';' || c.is_control() { ' ' } else { c }).collect()
+}
+
+///
+/// Escapes text for use in an XML attribute.
+///
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+
+///
+/// A Tauri command which exports the previewed drawing as an SVG, with pen-up moves left out.
+///
+/// # Parameters:
+/// - `app`: Injected dependency from Tauri
+/// - `path`: The path to write the SVG to
+///
+/// # Returns:
+/// - Void if the SVG was written
+/// - An error explaining why the drawing couldn't be exported
+///
+#[tauri::command(async)]
+pub fn export_svg(app: tauri::AppHandle, path: &str) -> Result<(), AppError> {
+    let cache_dir = tauri::Manager::path(&app).app_cache_dir().map_err(|err| AppError::Io(err.to_string()))?;
+
+    let bytes = read_cached_instructions(&cache_dir)?;
+    let init = read_start_position(&app).ok_or_else(AppError::no_preview)?;
+    let plan = pens::read_plan(&cache_dir);
+
+    write_svg(Path::new(path), &crate::get_physical_dimensions(&app), &bytes, init, &plan)
+}
+
+///
+/// A Tauri command which exports the previewed drawing as G-code for a Cartesian plotter,
+/// using the G-code settings of the selected machine profile.
+///
+/// # Parameters:
+/// - `app`: Injected dependency from Tauri
+/// - `path`: The path to write the program to
+///
+/// # Returns:
+/// - Void if the program was written
+/// - An error explaining why the drawing couldn't be exported
+///
+#[tauri::command(async)]
+pub fn export_gcode(app: tauri::AppHandle, path: &str) -> Result<(), AppError> {
+    let cache_dir = tauri::Manager::path(&app).app_cache_dir().map_err(|err| AppError::Io(err.to_string()))?;
+
+    let bytes = read_cached_instructions(&cache_dir)?;
+    let init = read_start_position(&app).ok_or_else(AppError::no_preview)?;
+    let plan = pens::read_plan(&cache_dir);
+    let options = get_app_config_struct(&app).map(|app_config| app_config.export_gcode).unwrap_or_default();
+
+    write_gcode(Path::new(path), &crate::get_physical_dimensions(&app), &bytes, init, &plan, &options)
+}
+
+///
+/// A Tauri command which exports the previewed drawing as HPGL, with a pen for each pen of the drawing.
+///
+/// # Parameters:
+/// - `app`: Injected dependency from Tauri
+/// - `path`: The path to write the program to
+///
+/// # Returns:
+/// - Void if the program was written
+/// - An error explaining why the drawing couldn't be exported
+///
+#[tauri::command(async)]
+pub fn export_hpgl(app: tauri::AppHandle, path: &str) -> Result<(), AppError> {
+    let cache_dir = tauri::Manager::path(&app).app_cache_dir().map_err(|err| AppError::Io(err.to_string()))?;
+
+    let bytes = read_cached_instructions(&cache_dir)?;
+    let init = read_start_position(&app).ok_or_else(AppError::no_preview)?;
+    let plan = pens::read_plan(&cache_dir);
+
+    write_hpgl(Path::new(path), &crate::get_physical_dimensions(&app), &bytes, init, &plan)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn phys_dim() -> PhysicalDimensions {
+        PhysicalDimensions::new(754., 272., 192., 210., 297.)
+    }
+
+    fn layer(name: &str, strokes: Vec<Vec<(f64, f64)>>) -> Layer {
+        Layer { pen: Pen { name: name.to_owned(), colour: "#000000".to_owned() }, strokes }
+    }
+
+    fn lines(program: &str) -> Vec<&str> {
+        program.lines().collect()
+    }
+
+    #[test]
+    fn lifts_the_pen_with_the_spindle() {
+        let options = GcodeOptions { spindle_power: 800, ..GcodeOptions::default() };
+        let program = gcode(&phys_dim(), &[layer("Black", vec![vec![(10., 20.), (30., 20.)]])], &options);
+
+        assert_eq!(&lines(&program)[3..], [
+            "M5",
+            "; pen: Black (#000000)",
+            "G0 X10 Y277",
+            "M3 S800",
+            "G1 X30 Y277 F1500",
+            "M5",
+            "G0 X0 Y0",
+            "M2",
+        ]);
+    }
+
+    #[test]
+    fn lifts_the_pen_along_z() {
+        let options = GcodeOptions { pen_lift: PenLift::Z, z_up: 4.5, z_down: -0.25, plunge_rate: 300., travel_rate: Some(3000.), ..GcodeOptions::default() };
+        let program = gcode(&phys_dim(), &[layer("Black", vec![vec![(10., 20.), (30., 20.)]])], &options);
+
+        assert_eq!(&lines(&program)[3..], [
+            "G0 Z4.5",
+            "; pen: Black (#000000)",
+            "G1 X10 Y277 F3000",
+            "G1 Z-0.25 F300",
+            "G1 X30 Y277 F1500",
+            "G0 Z4.5",
+            "G1 X0 Y0 F3000",
+            "M2",
+        ]);
+        assert!(!program.contains("M3") && !program.contains("M5"));
+    }
+
+    #[test]
+    fn measures_from_the_origin_corner() {
+        let corners = [(Origin::TopLeft, "G0 X10 Y20"), (Origin::TopRight, "G0 X200 Y20"), (Origin::BottomLeft, "G0 X10 Y277"), (Origin::BottomRight, "G0 X200 Y277")];
+        for (origin, travel) in corners {
+            let options = GcodeOptions { origin, ..GcodeOptions::default() };
+            let program = gcode(&phys_dim(), &[layer("Black", vec![vec![(10., 20.), (30., 40.)]])], &options);
+            assert_eq!(lines(&program)[5], travel);
+        }
+    }
+
+    #[test]
+    fn pauses_between_layers() {
+        let layers = [
+            layer("Black", vec![vec![(10., 20.), (30., 20.)]]),
+            layer("Red", vec![vec![(50., 60.), (70., 60.)]]),
+            layer("Blue", vec![vec![(90., 100.), (110., 100.)]]),
+        ];
+        let program = gcode(&phys_dim(), &layers, &GcodeOptions::default());
+        let lines = lines(&program);
+
+        let pauses: Vec<usize> = lines.iter().enumerate().filter(|(_, line)| line.starts_with("M0")).map(|(idx, _)| idx).collect();
+        assert_eq!(pauses.len(), 2);
+        assert_eq!(lines[pauses[0]], "M0 ; load Red");
+        assert_eq!(lines[pauses[1]], "M0 ; load Blue");
+        // each pause comes after the previous layer has lifted the pen, and before the next is drawn
+        assert_eq!(lines[pauses[0] - 2], "M5");
+        assert_eq!(lines[pauses[0] + 1], "G0 X50 Y237");
+
+        let single = gcode(&phys_dim(), &layers[..1], &GcodeOptions::default());
+        assert!(!single.lines().any(|line| line.starts_with("M0")));
+    }
+
+    #[test]
+    fn keeps_pen_names_inside_their_comments() {
+        let layers = [
+            layer("Black", vec![vec![(10., 20.), (30., 20.)]]),
+            layer("Red (fine); M2\nG0 X0", vec![vec![(50., 60.), (70., 60.)]]),
+        ];
+        let program = gcode(&phys_dim(), &layers, &GcodeOptions::default());
+        let lines = lines(&program);
+
+        assert!(lines.contains(&"M0 ; load Red  fine   M2 G0 X0"));
+        assert!(lines.contains(&"; pen: Red  fine   M2 G0 X0 (#000000)"));
+        assert_eq!(lines.iter().filter(|line| line.starts_with("M2")).count(), 1);
+        assert_eq!(lines.iter().filter(|line| line.starts_with("G0 X0")).count(), 1);
+    }
+}
@@ -1,189 +1,226 @@
 use std::fs::File;
-use std::io::{BufReader, Read, Seek, SeekFrom, Write};
+use std::io::{BufReader, Read};
+use std::path::Path;
 
 use serde::{Serialize, Deserialize};
-use bbcore::drawing::DrawParameters;
-use bbcore::drawing::lines::LinesParameters;
-use bbcore::drawing::cascade::CascadeParameters;
-use bbcore::drawing::scribble::ScribbleParameters;
-use bbcore::drawing::dunes::DunesParameters;
-use bbcore::drawing::islands::IslandsParameters;
-use bbcore::drawing::bubbles::BubblesParameters;
-use bbcore::drawing::waves::WavesParameters;
-use bbcore::drawing::entropy::EntropyParameters;
-use bbcore::drawing::vinyl::VinylParameters;
-use bbcore::drawing::atom::AtomParameters;
-use bbcore::drawing::custom::CustomParameters;
+use bbcore::hardware::PhysicalDimensions;
+
+use crate::styles;
+use crate::migration;
+use crate::bundle;
+use crate::profiles::{self, ProfileStore};
+use crate::transport::TransportKind;
+use crate::export::GcodeOptions;
+use crate::error::AppError;
 
 /// 
 /// Used to serialize / deserialize a save file, including the drawing method ID.
+/// The parameters are kept as raw JSON, and validated against the style registry.
 ///
 /// # Fields:
+/// - `format_version`: The save file format version, see `migration::FORMAT_VERSION`
 /// - `drawing_id`: The drawing method ID
 /// - `drawing_parameters`: The drawing parameters
 ///
 #[derive(Serialize, Deserialize)]
-#[serde(bound = "T: Serialize + for<'de2> Deserialize<'de2>")]
-struct FsDrawing<T : DrawParameters> {
+struct FsDrawing {
+    format_version: u64,
     drawing_id: String,
-    drawing_parameters: T 
+    drawing_parameters: serde_json::Value,
 }
 
 /// 
-/// Used to deserialize just the drawing method ID
+/// A Tauri command to save a drawing method and parameters to a file.
+/// It validates the parameters against the style registry, then serializes
+/// a `FsDrawing` into a string which is saved to a file.
+/// If the path has the bundle extension, referenced assets are embedded alongside it.
 ///
-/// # Fields:
+/// # Parameters:
+/// - `path`: The path to save the file to
 /// - `drawing_id`: The drawing method ID
+/// - `json_params`: The serialized drawing parameters
 ///
-#[derive(Deserialize)]
-struct PreDrawingId {
-    drawing_id: String,
-}
+/// # Returns:
+/// - Void if the function succeeded
+/// - An error explaining why the function did not succeed
+///
+#[tauri::command(async)]
+pub async fn save_file(path: &str, drawing_id: &str, json_params: &str) -> Result<(), AppError> {
+
+    let style = match styles::get_style(drawing_id) {
+        Some(style) => style,
+        None => { return Err(AppError::InvalidParameters("No such drawing ID".to_owned())); }
+    };
+
+    let drawing_parameters = match serde_json::from_str::<serde_json::Value>(json_params) {
+        Ok(val) => (style.validate)(&val),
+        Err(err) => Err(err.to_string()),
+    };
+
+    let drawing_parameters = match drawing_parameters {
+        Ok(val) => val,
+        Err(err) => { return Err(AppError::InvalidParameters(format!("Error saving file: {}", err))); }
+    };
 
+    let drawing = FsDrawing { format_version: migration::FORMAT_VERSION, drawing_id: style.id.to_owned(), drawing_parameters };
 
-macro_rules! cast_and_save {
-    ($drw_p:ty, $par:expr, $fh:expr, $drw_id:expr) => {
-        match serde_json::from_str::<$drw_p>($par) {
-            Ok(val) => { serde_json::to_writer($fh, &FsDrawing { drawing_id: $drw_id.to_string(), drawing_parameters: val }) },
-            Err(err) => Err(err)
+    if bundle::is_bundle_path(path) {
+        let document = match serde_json::to_value(&drawing) {
+            Ok(val) => val,
+            Err(err) => { return Err(AppError::InvalidParameters(format!("Error saving file: {}", err))); }
+        };
+
+        if let Err(err) = bundle::write_bundle(path, style.asset_fields, document) {
+            return Err(AppError::Io(format!("Error saving bundle: {}", err)));
         }
+
+        return Ok(());
     }
-}
 
-macro_rules! validate_load_format {
-    ($drw_p:ty, $buf_r:expr) => {
-        match serde_json::from_reader::<_, FsDrawing<$drw_p>>($buf_r) {
-            Ok(val) => Ok(serde_json::to_string(&val.drawing_parameters).unwrap()),
-            Err(err) => Err(err.to_string())
-        }
+    let file_handle = match File::create(path) {
+        Ok(handle) => handle,
+        Err(err) => { return Err(AppError::Io(format!("Error saving file: {}", err))); }
+    };
+
+    match serde_json::to_writer(file_handle, &drawing) {
+        Ok(()) => {},
+        Err(err) => { return Err(AppError::Io(format!("Error saving file: {}", err))); }
     }
+
+    Ok(())
 }
 
 /// 
-/// A Tauri command to save a drawing method and parameters to a file.
-/// It serializes a `FsDrawing` into a string which is saved to a file.
+/// A Tauri command to load a drawing method into a string.
+/// Older save files are upgraded through the migration chain, and any parameters
+/// missing from the file are set to their defaults before validating against the style registry.
+/// Bundles have their assets unpacked into app storage, and their asset paths rewritten.
 ///
 /// # Parameters:
+/// - `app`: Injected dependency from Tauri
 /// - `path`: The path to save the file to
-/// - `drawing_id`: The drawing method ID
-/// - `json_params`: The serialized drawing parameters
 ///
 /// # Returns:
-/// - Void if the function succeeded
-/// - An error, as a string, explaining why the function did not succeed
+/// - (drawing_id, drawing_parameters, report), where report lists what was migrated or defaulted
+/// - An error explaining why the function did not succeed
 ///
 #[tauri::command(async)]
-pub async fn save_file(path: &str, drawing_id: &str, json_params: &str) -> Result<(), String> {
-
-    let file_handle = File::create(path).unwrap();
-
-    match match drawing_id {
-            "cascade" => {
-                cast_and_save!(CascadeParameters, json_params, file_handle, "cascade")
-            },
-            "lines" => {
-                cast_and_save!(LinesParameters, json_params, file_handle, "lines")
-            },
-            "bubbles" => {
-                cast_and_save!(BubblesParameters, json_params, file_handle, "bubbles")
-            },
-            "scribble" => {
-                cast_and_save!(ScribbleParameters, json_params, file_handle, "scribble")
-            },
-            "dunes" => {
-                cast_and_save!(DunesParameters, json_params, file_handle, "dunes")
-            },
-            "islands" => {
-                cast_and_save!(IslandsParameters, json_params, file_handle, "islands")
-            },
-            "waves" => {
-                cast_and_save!(WavesParameters, json_params, file_handle, "waves")
-            },
-            "entropy" => {
-                cast_and_save!(EntropyParameters, json_params, file_handle, "entropy")
-            },
-            "vinyl" => {
-                cast_and_save!(VinylParameters, json_params, file_handle, "vinyl")
-            },
-            "atom" => {
-                cast_and_save!(AtomParameters, json_params, file_handle, "atom")
-            },
-            "custom" => {
-                cast_and_save!(CustomParameters, json_params, file_handle, "custom")
-            },
-            _ => { return Err("No such drawing ID".to_owned()); }
-    } {
-        Ok(()) => {},
-        Err(err) => { return Err(format!("Error saving file: {}", err).to_owned()); }
+pub async fn open_file(app: tauri::AppHandle, path: &str) -> Result<(String, String, Vec<String>), AppError> {
+    let data_dir = tauri::Manager::path(&app).app_data_dir().map_err(|err| AppError::Io(err.to_string()))?;
+
+    let (drawing_id, drawing_parameters, report) = read_drawing(path, &data_dir)?;
+    Ok((drawing_id, drawing_parameters.to_string(), report))
+}
+
+/// 
+/// Loads a drawing from a save file or bundle, upgrading and validating it.
+/// Bundles are unpacked into the `bundles` folder of the data directory, replacing the bundle opened before.
+///
+/// # Parameters:
+/// - `path`: The path of the file
+/// - `data_dir`: The app data directory
+///
+/// # Returns:
+/// - (drawing_id, drawing_parameters, report), where report lists what was migrated or defaulted
+/// - An error explaining why the function did not succeed
+///
+pub fn read_drawing(path: &str, data_dir: &Path) -> Result<(String, serde_json::Value, Vec<String>), AppError> {
+
+    let bundles_dir = data_dir.join("bundles");
+
+    if !bundle::is_bundle_file(path) {
+        return load_document(path, None);
     }
 
-    Ok(())
+    let bundle_name = Path::new(path).file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+    let unique_id = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map(|d| d.as_millis()).unwrap_or(0);
+    let bundle_dir = bundles_dir.join(format!("{}-{}", bundle_name, unique_id));
+
+    // a bundle which fails to load leaves nothing behind, and one which loads replaces the last
+    let result = load_document(path, Some(&bundle_dir));
+    match result {
+        Ok(_) => bundle::remove_extractions(&bundles_dir, Some(&bundle_dir)),
+        Err(_) => { let _ = std::fs::remove_dir_all(&bundle_dir); },
+    }
+    result
 }
 
 /// 
-/// A Tauri command to load a drawing method into a string.
-/// It first deserializes just the drawing_id, to determine which object
-/// to deserialize into.
+/// Reads, upgrades and validates a save file, or a bundle unpacked into a directory.
 ///
 /// # Parameters:
-/// - `path`: The path to save the file to
+/// - `path`: The path of the file
+/// - `extract_dir`: The directory to unpack the bundle into, or None if the file isn't a bundle
 ///
 /// # Returns:
-/// - (drawing_id, drawing_parameters) both as strings
-/// - An error, as a string, explaining why the function did not succeed
+/// - (drawing_id, drawing_parameters, report), where report lists what was migrated or defaulted
+/// - An error explaining why the function did not succeed
 ///
-#[tauri::command(async)]
-pub async fn open_file(path: &str) -> Result<(String, String), String> {
+fn load_document(path: &str, extract_dir: Option<&Path>) -> Result<(String, serde_json::Value, Vec<String>), AppError> {
 
-    let mut file_handle = File::open(path).unwrap();
-    let drawing_id: PreDrawingId = match serde_json::from_reader(BufReader::new(&file_handle)) {
+    let mut document: serde_json::Value = if let Some(bundle_dir) = extract_dir {
+        match bundle::read_bundle(path, bundle_dir) {
+            Ok(val) => val,
+            Err(err) => { return Err(AppError::InvalidParameters(format!("Corrupt bundle: {}", err))); }
+        }
+    } else {
+        let file_handle = match File::open(path) {
+            Ok(handle) => handle,
+            Err(err) => { return Err(AppError::Io(format!("Couldn't open file: {}", err))); }
+        };
+
+        match serde_json::from_reader(BufReader::new(file_handle)) {
+            Ok(val) => val,
+            Err(err) => { return Err(AppError::InvalidParameters(format!("Corrupt save file: {}", err))); }
+        }
+    };
+
+    let mut report = match migration::migrate(&mut document) {
+        Ok(report) => report,
+        Err(err) => { return Err(AppError::InvalidParameters(format!("Couldn't upgrade save file: {}", err))); }
+    };
+
+    let mut drawing: FsDrawing = match serde_json::from_value(document) {
         Ok(val) => val,
-        Err(err) => { return Err(format!("Corrupt save file: {}", err).to_owned()); }
+        Err(err) => { return Err(AppError::InvalidParameters(format!("Corrupt save file: {}", err))); }
     };
 
-    file_handle.seek(SeekFrom::Start(0)).unwrap();
-    let buf_read = BufReader::new(&file_handle);
-    match match drawing_id.drawing_id.as_str() {
-            "cascade" => {
-                validate_load_format!(CascadeParameters, buf_read)
-            },
-            "lines" => {
-                validate_load_format!(LinesParameters, buf_read)
-            },
-            "bubbles" => {
-                validate_load_format!(BubblesParameters, buf_read)
-            },
-            "scribble" => {
-                validate_load_format!(ScribbleParameters, buf_read)
-            },
-            "dunes" => {
-                validate_load_format!(DunesParameters, buf_read)
-            },
-            "islands" => {
-                validate_load_format!(IslandsParameters, buf_read)
-            },
-            "waves" => {
-                validate_load_format!(WavesParameters, buf_read)
-            },
-            "entropy" => {
-                validate_load_format!(EntropyParameters, buf_read)
-            },
-            "vinyl" => {
-                validate_load_format!(VinylParameters, buf_read)
-            },
-            "atom" => {
-                validate_load_format!(AtomParameters, buf_read)
-            },
-            "custom" => {
-                validate_load_format!(CustomParameters, buf_read)
-            },
-            _ => { Err("Invalid drawing type".to_owned()) }
-    } {
-        Ok(str) => { return Ok((drawing_id.drawing_id, str)) },
-        Err(err) => { return Err(format!("Corrupt save file: {}", err).to_owned()); }
+    let style = match styles::get_style(drawing.drawing_id.as_str()) {
+        Some(style) => style,
+        None => { return Err(AppError::InvalidParameters("Corrupt save file: Invalid drawing type".to_owned())); }
+    };
+
+    if let Some(bundle_dir) = extract_dir {
+        bundle::localise_assets(style.asset_fields, &mut drawing.drawing_parameters, bundle_dir);
+    }
+
+    match load_parameters(style, drawing.drawing_parameters, &mut report) {
+        Ok(val) => Ok((drawing.drawing_id, val, report)),
+        Err(err) => Err(AppError::InvalidParameters(format!("Corrupt save file: {}", err))),
     }
 }
 
+/// 
+/// Prepares saved drawing parameters for use, setting any missing parameters to their
+/// defaults and validating them against the style registry.
+///
+/// # Parameters:
+/// - `style`: The registry entry of the drawing style
+/// - `parameters`: The saved drawing parameters
+/// - `report`: The migration report, appended to when a parameter is defaulted
+///
+/// # Returns:
+/// - The validated drawing parameters
+/// - An error, as a string, explaining why the parameters are invalid
+///
+pub(crate) fn load_parameters(style: &styles::Style, mut parameters: serde_json::Value, report: &mut Vec<String>) -> Result<serde_json::Value, String> {
+    if let Some(defaults) = styles::default_parameters(style.id) {
+        migration::reconcile_parameters(&defaults, &mut parameters, report);
+    }
+
+    (style.validate)(&parameters)
+}
+
 
 
 /// 
@@ -197,8 +234,16 @@ pub async fn open_file(path: &str) -> Result<(String, String), String> {
 /// - `phys_page_top_offset`: The vertical distance between the left motor shaft and the top left of the page
 /// - `phys_page_width`: The width of the page
 /// - `phys_page_height`: The height of the page
+/// - `machine_max_motor_speed`: The machine's maximum motor speed, or None to use the last connected machine's
+/// - `machine_min_pulse_width`: The machine's minimum pulse width, or None to use the last connected machine's
+/// - `machine_transport`: Whether the machine is reached over the network or a serial port
+/// - `machine_serial_port`: The serial port of the machine, such as `/dev/ttyUSB0` or `COM3`
+/// - `machine_baud_rate`: The baud rate of the serial port, or None for the default
+/// - `machine_reconnect_timeout_secs`: How long a drawing tries to reconnect after losing the machine, or None for the default
+/// - `export_gcode`: The settings of drawings exported as G-code
+/// - `optimise_paths`: Whether drawings are reordered to cut down pen-up travel, see `optimise`
 ///
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct AppConfig {
     pub machine_addr: String,
     pub machine_port: u16,
@@ -208,6 +253,36 @@ pub struct AppConfig {
     pub phys_page_top_offset: f64,
     pub phys_page_width: f64,
     pub phys_page_height: f64,
+
+    #[serde(default)]
+    pub machine_max_motor_speed: Option<u64>,
+    #[serde(default)]
+    pub machine_min_pulse_width: Option<u64>,
+    #[serde(default)]
+    pub machine_reconnect_timeout_secs: Option<u64>,
+
+    #[serde(default)]
+    pub machine_transport: TransportKind,
+    #[serde(default)]
+    pub machine_serial_port: Option<String>,
+    #[serde(default)]
+    pub machine_baud_rate: Option<u32>,
+
+    #[serde(default)]
+    pub export_gcode: GcodeOptions,
+
+    #[serde(default)]
+    pub optimise_paths: bool,
+}
+
+impl AppConfig {
+
+    /// 
+    /// Gets the physical dimensions of the configured machine.
+    ///
+    pub fn physical_dimensions(&self) -> PhysicalDimensions {
+        PhysicalDimensions::new(self.phys_motor_interspace, self.phys_page_left_offset, self.phys_page_top_offset, self.phys_page_width, self.phys_page_height)
+    }
 }
 
 /// 
@@ -221,66 +296,49 @@ pub struct AppConfig {
 /// - An error explaining why the function failed
 ///
 #[tauri::command(async)]
-pub fn get_app_config(app: tauri::AppHandle) -> Result<String, ()> {
+pub fn get_app_config(app: tauri::AppHandle) -> Result<String, AppError> {
 
-    let cache_dir = tauri::Manager::path(&app).app_cache_dir().expect("Should get cache dir");
-    let app_config_path = cache_dir.join("app_config.json");
-
-    match app_config_path.try_exists() {
-        Ok(exists) => if !exists { return Err(()) },
-        Err(_) => { return Err(()) }
-    };
-
-    let file_handle = match File::open(app_config_path) {
-        Ok(handle) => handle,
-        Err(_) => { return Err(()) }
-    };
-
-    let mut contents = String::new();
-    BufReader::new(file_handle).read_to_string(&mut contents).unwrap();
-
-    match serde_json::from_str::<AppConfig>(contents.as_str()) {
-        Ok(_) => { return Ok(contents) },
-        Err(_) => { return Err(()) }
-    };
+    let app_config = get_app_config_struct(&app)?;
 
+    match serde_json::to_string(&app_config) {
+        Ok(val) => Ok(val),
+        Err(err) => Err(AppError::Io(err.to_string())),
+    }
 }
 
 /// 
-/// A function used to save the desired app configuration to the disk.
+/// A function used to save the desired app configuration to the selected machine profile.
+/// If there are no profiles yet, it's saved as the `Default` profile.
 ///
 /// # Parameters:
 /// - `app`: Injected dependency from Tauri
 /// - `stringified_config`: The JSON to save, as a string
 ///
 /// # Returns:
-/// - A Result<> to determine whether the function failed or succeeded
+/// - Void if the function succeeded
+/// - An error explaining why the function failed
 ///
 #[tauri::command(async)]
-pub fn save_app_config(app: tauri::AppHandle, stringified_config: &str) -> Result<(), ()> {
+pub fn save_app_config(app: tauri::AppHandle, stringified_config: &str) -> Result<(), AppError> {
 
-    let cache_dir = tauri::Manager::path(&app).app_cache_dir().expect("Should get cache dir");
-    let app_config_path = cache_dir.join("app_config.json");
-    
-    let mut file_handle = match File::create(app_config_path) {
-        Ok(handle) => handle,
-        Err(_) => { return Err(()) }
-    };
+    let cache_dir = tauri::Manager::path(&app).app_cache_dir().map_err(|err| AppError::Io(err.to_string()))?;
 
     // check its in the right format (can be serialized)
-    match serde_json::from_str::<AppConfig>(stringified_config) {
-        Ok(_) => { },
-        Err(_) => { return Err(()) }
-    };
-    
-    match file_handle.write_all(stringified_config.as_bytes()) {
-        Ok(_) => { return Ok(()); },
-        Err(_) => { return Err(()); }
+    let app_config = match serde_json::from_str::<AppConfig>(stringified_config) {
+        Ok(val) => val,
+        Err(err) => { return Err(AppError::InvalidParameters(format!("Invalid print settings: {}", err))); }
     };
+
+    let mut store = ProfileStore::read(&cache_dir)?;
+    let name = store.selected.clone().unwrap_or_else(|| profiles::DEFAULT_PROFILE.to_owned());
+    store.profiles.insert(name.clone(), app_config);
+    store.selected = Some(name);
+
+    store.write(&cache_dir)
 }
 
 /// 
-/// A function used to get the app config as a Rust struct.
+/// A function used to get the app config of the selected machine profile as a Rust struct.
 ///
 /// # Parameters:
 /// - `app`: Injected dependency from Tauri
@@ -289,27 +347,59 @@ pub fn save_app_config(app: tauri::AppHandle, stringified_config: &str) -> Resul
 /// - A new AppConfig object
 /// - An error explaning why the app config couldn't be created
 ///
-pub fn get_app_config_struct(app: &tauri::AppHandle) -> Result<AppConfig, ()> {
+pub fn get_app_config_struct(app: &tauri::AppHandle) -> Result<AppConfig, AppError> {
     
-    let cache_dir = tauri::Manager::path(app).app_cache_dir().expect("Should get cache dir");
-    let app_config_path = cache_dir.join("app_config.json");
+    let cache_dir = tauri::Manager::path(app).app_cache_dir().map_err(|_| AppError::not_configured())?;
+    ProfileStore::read(&cache_dir)?.selected_config().cloned()
+}
 
-    match app_config_path.try_exists() {
-        Ok(exists) => if !exists { return Err(()) },
-        Err(_) => { return Err(()) }
-    };
+/// 
+/// A function used to read an app config file as a Rust struct.
+///
+/// # Parameters:
+/// - `app_config_path`: The path of the app config file
+///
+/// # Returns:
+/// - A new AppConfig object
+/// - An error explaning why the app config couldn't be created
+///
+pub fn read_app_config(app_config_path: &Path) -> Result<AppConfig, AppError> {
 
     let file_handle = match File::open(app_config_path) {
         Ok(handle) => handle,
-        Err(_) => { return Err(()) }
+        Err(_) => { return Err(AppError::not_configured()); }
     };
 
     let mut contents = String::new();
-    BufReader::new(file_handle).read_to_string(&mut contents).unwrap();
+    BufReader::new(file_handle).read_to_string(&mut contents)?;
 
     match serde_json::from_str::<AppConfig>(contents.as_str()) {
-        Ok(val) => { return Ok(val) },
-        Err(_) => { return Err(()) }
-    };
+        Ok(val) => Ok(val),
+        Err(err) => Err(AppError::NotConfigured(format!("The print settings are invalid: {}", err))),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
+    #[test]
+    fn loads_a_baseline_save_file_with_defaults() {
+        // files saved before versioning have no format version, and only the parameters of their time
+        let path = std::env::temp_dir().join(format!("blot-baseline-{}.json", std::process::id()));
+        std::fs::write(&path, r#"{"drawing_id":"hpgl","drawing_parameters":{"hpgl_path":"/drawings/plot.hpgl"}}"#).unwrap();
+
+        let loaded = load_document(path.to_str().unwrap(), None);
+        let _ = std::fs::remove_file(&path);
+        let (drawing_id, parameters, report) = loaded.unwrap();
+
+        assert_eq!(drawing_id, "hpgl");
+        assert_eq!(parameters["hpgl_path"], "/drawings/plot.hpgl");
+        assert_eq!(parameters["margin"].as_f64(), Some(15.));
+        assert_eq!(parameters["rotation"].as_f64(), Some(0.));
+        assert!(report.iter().any(|line| line.contains("format version 1")));
+        assert!(report.iter().any(|line| line.contains("'margin'")));
+        assert!(report.iter().any(|line| line.contains("'rotation'")));
+    }
 }
@@ -0,0 +1,319 @@
+use serde::{Serialize, Deserialize};
+use bbcore::hardware::PhysicalDimensions;
+
+use crate::motion;
+use crate::pens::{Pen, PenPlan, Segment};
+use crate::svg_import::fit_to_page;
+
+
+///
+/// The number of HPGL plotter units in a millimetre.
+///
+pub const UNITS_PER_MM: f64 = 40.;
+
+///
+/// The colours of the pens in a classic eight pen carousel, by pen number from 1.
+///
+const PEN_COLOURS: [&str; 8] = ["#000000", "#d62728", "#2ca02c", "#1f77b4", "#9467bd", "#ff7f0e", "#8c564b", "#e377c2"];
+
+
+///
+/// The parameters of the `hpgl` drawing style.
+///
+/// # Fields:
+/// - `hpgl_path`: The path of the HPGL file to draw
+/// - `margin`: The space left around the drawing on every side of the page, in millimetres
+/// - `rotation`: The clockwise rotation of the artwork, in degrees
+///
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct HpglParameters {
+    pub hpgl_path: String,
+    pub margin: f64,
+    pub rotation: f64,
+}
+
+///
+/// A point in the artwork, in plotter units.
+///
+type Point = (f64, f64);
+
+///
+/// A pen-down stroke read from an HPGL file.
+///
+/// # Fields:
+/// - `pen`: The pen number selected with `SP` when the stroke was drawn
+/// - `points`: The points of the stroke, in plotter units with Y pointing up
+///
+struct Stroke {
+    pen: usize,
+    points: Vec<Point>,
+}
+
+
+///
+/// Generates the instructions of the `hpgl` drawing style, which plots an HPGL file.
+/// The artwork is rotated, then scaled and centred to fit the page inside the margins.
+///
+/// # Parameters:
+/// - `phys_dim`: The physical dimensions of the machine
+/// - `params`: The parameters of the style
+///
+/// # Returns:
+/// - The instruction bytes, and the (x, y) starting position of the drawing
+/// - An error explaining why the file couldn't be drawn
+///
+pub fn gen_instructions(phys_dim: &PhysicalDimensions, params: &HpglParameters) -> Result<(Vec<u8>, f64, f64), String> {
+    let (bytes, init, _) = generate(phys_dim, params)?;
+    Ok((bytes, init.0, init.1))
+}
+
+///
+/// Generates the pen plan of the `hpgl` drawing style, with a pen for each pen number the file selects.
+///
+/// # Parameters:
+/// - `phys_dim`: The physical dimensions of the machine
+/// - `params`: The parameters of the style
+///
+/// # Returns:
+/// - The pen plan of the instructions from `gen_instructions`
+/// - An error explaining why the file couldn't be drawn
+///
+pub fn gen_pen_plan(phys_dim: &PhysicalDimensions, params: &HpglParameters) -> Result<PenPlan, String> {
+    generate(phys_dim, params).map(|(_, _, plan)| plan)
+}
+
+///
+/// The instruction bytes, (x, y) starting position and pen plan of an HPGL drawing.
+///
+type HpglDrawing = (Vec<u8>, (f64, f64), PenPlan);
+
+///
+/// Reads an HPGL file and generates its instructions, drawing everything with one pen before moving onto the next.
+/// The pens are drawn in the order the file first selects them.
+///
+/// # Parameters:
+/// - `phys_dim`: The physical dimensions of the machine
+/// - `params`: The parameters of the style
+///
+/// # Returns:
+/// - The instruction bytes, the (x, y) starting position of the drawing, and its pen plan
+/// - An error explaining why the file couldn't be drawn
+///
+fn generate(phys_dim: &PhysicalDimensions, params: &HpglParameters) -> Result<HpglDrawing, String> {
+    if params.hpgl_path.is_empty() {
+        return Err("Select an HPGL file to draw.".to_owned());
+    }
+
+    let data = std::fs::read(&params.hpgl_path).map_err(|err| format!("Couldn't read the HPGL file: {}", err))?;
+    let strokes = parse(String::from_utf8_lossy(&data).as_ref());
+
+    // strokes are grouped by pen, keeping the file's order within each pen
+    let mut groups: Vec<(usize, Vec<Vec<Point>>)> = Vec::new();
+    let (sin, cos) = params.rotation.to_radians().sin_cos();
+    for stroke in strokes {
+        // the page's Y axis points down, where HPGL's points up
+        let points = stroke.points.into_iter().map(|(x, y)| (x * cos + y * sin, x * sin - y * cos)).collect();
+        match groups.iter_mut().find(|(pen, _)| *pen == stroke.pen) {
+            Some((_, group)) => group.push(points),
+            None => groups.push((stroke.pen, vec![points])),
+        }
+    }
+
+    let area = (phys_dim.page_width() - 2. * params.margin, phys_dim.page_height() - 2. * params.margin);
+    if area.0 <= 0. || area.1 <= 0. {
+        return Err("The margins leave no room on the page.".to_owned());
+    }
+
+    let counts: Vec<usize> = groups.iter().map(|(_, group)| group.len()).collect();
+    let mut page_strokes = fit_to_page(phys_dim, groups.iter().flat_map(|(_, group)| group.iter().cloned()).collect(), area).ok_or("The HPGL file has nothing to draw.")?.into_iter();
+
+    let mut bytes = Vec::new();
+    let mut init = None;
+    let mut position = (0., 0.);
+    let mut plan = PenPlan { pens: Vec::new(), segments: Vec::new() };
+
+    for (&(pen, _), count) in groups.iter().zip(counts) {
+        let group_strokes: Vec<Vec<Point>> = page_strokes.by_ref().take(count).collect();
+        let (group_bytes, group_init) = motion::draw_strokes(phys_dim, &group_strokes);
+
+        match init {
+            None => { init = Some(group_init); },
+            Some(_) => { bytes.extend(motion::encode(&motion::travel(phys_dim, position, group_init))); },
+        }
+
+        plan.segments.push(Segment { pen: plan.pens.len(), start: bytes.len() });
+        plan.pens.push(Pen { name: format!("Pen {}", pen), colour: PEN_COLOURS[(pen.max(1) - 1) % PEN_COLOURS.len()].to_owned() });
+
+        position = group_strokes.iter().rev().find(|stroke| stroke.len() > 1).map_or(position, |stroke| stroke[stroke.len() - 1]);
+        bytes.extend(group_bytes);
+    }
+
+    match init {
+        Some(init) => Ok((bytes, init, plan)),
+        None => Err("The HPGL file has nothing to draw.".to_owned()),
+    }
+}
+
+///
+/// Parses the pen-down strokes of an HPGL program. `IN`, `PU`, `PD`, `PA`, `PR` and `SP` are followed,
+/// and every other instruction is skipped, along with the text of `LB` labels.
+///
+/// # Parameters:
+/// - `program`: The HPGL program
+///
+/// # Returns:
+/// - The strokes, in the order they're drawn
+///
+fn parse(program: &str) -> Vec<Stroke> {
+    let mut strokes = Vec::new();
+    let mut current: Vec<Point> = Vec::new();
+    let mut position = (0., 0.);
+    let mut pen_down = false;
+    let mut absolute = true;
+    let mut pen = 1;
+
+    let chars: Vec<char> = program.chars().collect();
+    let mut i = 0;
+
+    while i + 1 < chars.len() {
+        if !chars[i].is_ascii_alphabetic() || !chars[i + 1].is_ascii_alphabetic() {
+            i += 1;
+            continue;
+        }
+
+        let mnemonic = format!("{}{}", chars[i], chars[i + 1]).to_ascii_uppercase();
+        i += 2;
+
+        // labels run to the end of text character, and may hold anything
+        if mnemonic == "LB" {
+            while i < chars.len() && chars[i] != '\u{3}' {
+                i += 1;
+            }
+            continue;
+        }
+
+        let start = i;
+        while i < chars.len() && chars[i] != ';' && (!chars[i].is_ascii_alphabetic() || is_exponent(&chars, i)) {
+            i += 1;
+        }
+        let values: Vec<f64> = chars[start..i].iter().collect::<String>()
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter_map(|value| value.parse::<f64>().ok())
+            .collect();
+
+        match mnemonic.as_str() {
+            "IN" => {
+                finish_stroke(&mut strokes, &mut current, pen);
+                position = (0., 0.);
+                pen_down = false;
+                absolute = true;
+                continue;
+            },
+            "SP" => {
+                finish_stroke(&mut strokes, &mut current, pen);
+                pen = values.first().map_or(0, |value| value.max(0.) as usize);
+                continue;
+            },
+            "PU" => { finish_stroke(&mut strokes, &mut current, pen); pen_down = false; },
+            "PD" => { pen_down = true; },
+            "PA" => { absolute = true; },
+            "PR" => { absolute = false; },
+            _ => { continue; },
+        }
+
+        for pair in values.chunks_exact(2) {
+            let target = if absolute { (pair[0], pair[1]) } else { (position.0 + pair[0], position.1 + pair[1]) };
+
+            // pen 0 is no pen at all, so nothing is drawn until one is selected
+            if pen_down && pen != 0 {
+                if current.is_empty() {
+                    current.push(position);
+                }
+                current.push(target);
+            }
+            position = target;
+        }
+    }
+
+    finish_stroke(&mut strokes, &mut current, pen);
+    strokes
+}
+
+///
+/// Checks if a letter in a parameter list is the exponent of a number, such as the `e` of `1e3`,
+/// rather than the start of the next instruction.
+///
+/// # Parameters:
+/// - `chars`: The characters of the program
+/// - `i`: The index of the letter
+///
+/// # Returns:
+/// - True if the letter is an exponent
+///
+fn is_exponent(chars: &[char], i: usize) -> bool {
+    let exponent = match chars.get(i + 1) {
+        Some('+' | '-') => chars.get(i + 2),
+        other => other,
+    };
+    matches!(chars[i], 'e' | 'E') && i > 0 && chars[i - 1].is_ascii_digit() && exponent.is_some_and(|c| c.is_ascii_digit())
+}
+
+///
+/// Ends the stroke being drawn, keeping it if it's more than a single point.
+///
+/// # Parameters:
+/// - `strokes`: The finished strokes
+/// - `current`: The points of the stroke being drawn, left empty
+/// - `pen`: The pen number the stroke was drawn with
+///
+fn finish_stroke(strokes: &mut Vec<Stroke>, current: &mut Vec<Point>, pen: usize) {
+    let points = std::mem::take(current);
+    if points.len() > 1 {
+        strokes.push(Stroke { pen, points });
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn points(program: &str) -> Vec<(usize, Vec<Point>)> {
+        parse(program).into_iter().map(|stroke| (stroke.pen, stroke.points)).collect()
+    }
+
+    #[test]
+    fn switches_between_absolute_and_relative_moves() {
+        assert_eq!(points("IN;SP1;PU100,100;PD;PR50,0,0,50;PA0,0;PU;"), vec![
+            (1, vec![(100., 100.), (150., 100.), (150., 150.), (0., 0.)]),
+        ]);
+    }
+
+    #[test]
+    fn draws_nothing_with_pen_zero() {
+        assert_eq!(points("IN;SP0;PD100,100;PU;SP2;PA0,0;PD10,0;PU;"), vec![
+            (2, vec![(0., 0.), (10., 0.)]),
+        ]);
+    }
+
+    #[test]
+    fn skips_the_text_of_labels() {
+        assert_eq!(points("IN;SP1;PA0,0;LBPD99,99;PA;\u{3};PD10,10;PU;"), vec![
+            (1, vec![(0., 0.), (10., 10.)]),
+        ]);
+    }
+
+    #[test]
+    fn lowers_the_pen_without_moving() {
+        assert_eq!(points("IN;SP1;PA20,20;PD;PA30,20;PA30,30;PU;"), vec![
+            (1, vec![(20., 20.), (30., 20.), (30., 30.)]),
+        ]);
+    }
+
+    #[test]
+    fn reads_numbers_with_exponents() {
+        assert_eq!(points("IN;SP1;PU1e3,2E2;PD1.5e3,2e+2;PU"), vec![
+            (1, vec![(1000., 200.), (1500., 200.)]),
+        ]);
+    }
+}
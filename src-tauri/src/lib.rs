@@ -1,43 +1,44 @@
-use bbcore::drawing::lines::{LinesMethod, LinesParameters};
-use bbcore::drawing::cascade::{CascadeMethod, CascadeParameters};
-use bbcore::drawing::scribble::{ScribbleMethod, ScribbleParameters};
-use bbcore::drawing::dunes::{DunesMethod, DunesParameters};
-use bbcore::drawing::islands::{IslandsMethod, IslandsParameters};
-use bbcore::drawing::bubbles::{BubblesMethod, BubblesParameters};
-use bbcore::drawing::waves::{WavesMethod, WavesParameters};
-use bbcore::drawing::entropy::{EntropyMethod, EntropyParameters};
-use bbcore::drawing::vinyl::{VinylMethod, VinylParameters};
-use bbcore::drawing::shades::{ShadesMethod, ShadesParameters};
-use bbcore::drawing::atom::{AtomMethod, AtomParameters};
-use bbcore::drawing::custom::{CustomMethod, CustomParameters};
 use bbcore::hardware::PhysicalDimensions;
-use bbcore::drawing::DrawMethod;
 use bbcore::preview::generate_preview;
 use bbcore::instruction::InstructionSet;
 use file::get_app_config_struct;
+use pens::PenPlan;
+use error::AppError;
 use std::fs::File;
 use std::io::Write;
-use tokio::sync::Mutex;
-use std::sync::Arc;
+use std::path::Path;
 
+pub mod error;
 pub mod file;
 pub mod client;
 pub mod plugin_handler;
-
-
-macro_rules! generate_preview {
-    ($drw_t:expr, $drw_p:ty, $jp:expr, $pdr:expr) => {
-        match serde_json::from_str::<$drw_p>($jp) {
-            Ok(val) => $drw_t.gen_instructions($pdr, &val),
-            Err(err) => return "error:".to_owned() + err.to_string().as_str(),
-        }
-    }
-}
+pub mod styles;
+pub mod migration;
+pub mod bundle;
+pub mod motion;
+pub mod composition;
+pub mod pens;
+pub mod queue;
+pub mod checkpoint;
+pub mod progress;
+pub mod protocol;
+pub mod mock_firmware;
+pub mod estimate;
+pub mod profiles;
+pub mod discovery;
+pub mod codec;
+pub mod reconnect;
+pub mod transport;
+pub mod wire;
+pub mod export;
+pub mod svg_import;
+pub mod hpgl;
+pub mod optimise;
 
 
 /// 
 /// A Tauri command used to generate a preview of a drawing, save the instructions,
-/// and returns the path to the preview image.
+/// and returns the path to the preview image. The drawing's paths are optimised first if that's turned on.
 ///
 /// # Parameters:
 /// - `app`: Injected dependency from Tauri
@@ -46,89 +47,106 @@ macro_rules! generate_preview {
 ///
 /// # Returns:
 /// - A path pointing to the preview image
+/// - An error explaining why the preview couldn't be generated
 ///
 #[tauri::command(async)]
-fn gen_preview(app: tauri::AppHandle, style_id: &str, json_params: &str) -> String {
-    let config = get_app_config_struct(&app);
+fn gen_preview(app: tauri::AppHandle, style_id: &str, json_params: &str) -> Result<String, AppError> {
+    let phys_dim = get_physical_dimensions(&app);
 
-    let phys_dim = match config {
-        Ok(app_config) => PhysicalDimensions::new(app_config.phys_motor_interspace, app_config.phys_page_left_offset, app_config.phys_page_top_offset, app_config.phys_page_width, app_config.phys_page_height),
-        Err(_) => PhysicalDimensions::new(754., (754. - 210.) / 1.98, 192., 210., 297.),
+    let style = match styles::get_style(style_id) {
+        Some(style) => style,
+        None => { return Err(AppError::InvalidParameters("Unknown draw type".to_owned())); }
     };
 
-    let ins_bytes: Result<(Vec<u8>, f64, f64), String> = match style_id {
-        "cascade" => {
-            generate_preview!(CascadeMethod {}, CascadeParameters, json_params, &phys_dim)
-        },
-        "lines" => {
-            generate_preview!(LinesMethod {}, LinesParameters, json_params, &phys_dim)
-        },
-        "bubbles" => {
-            generate_preview!(BubblesMethod {}, BubblesParameters, json_params, &phys_dim)
-        },
-        "scribble" => {
-            generate_preview!(ScribbleMethod {}, ScribbleParameters, json_params, &phys_dim)
-        },
-        "dunes" => {
-            generate_preview!(DunesMethod {}, DunesParameters, json_params, &phys_dim)
-        },
-        "islands" => {
-            generate_preview!(IslandsMethod {}, IslandsParameters, json_params, &phys_dim)
-        },
-        "waves" => {
-            generate_preview!(WavesMethod {}, WavesParameters, json_params, &phys_dim)
-        },
-        "entropy" => {
-            generate_preview!(EntropyMethod {}, EntropyParameters, json_params, &phys_dim)
-        },
-        "vinyl" => {
-            generate_preview!(VinylMethod {}, VinylParameters, json_params, &phys_dim)
-        },
-        "shades" => {
-            generate_preview!(ShadesMethod {}, ShadesParameters, json_params, &phys_dim)
-        },
-        "atom" => {
-            generate_preview!(AtomMethod {}, AtomParameters, json_params, &phys_dim)
-        },
-        "custom" => {
-            generate_preview!(CustomMethod {}, CustomParameters, json_params, &phys_dim)
-        },
-        _ => {
-            Err("error:Unknown draw type".to_owned())
-        }
+    let (bytes, ix, iy) = (style.gen_instructions)(&phys_dim, json_params).map_err(AppError::InvalidParameters)?;
+    let plan = match style.gen_pen_plan {
+        Some(gen_pen_plan) => gen_pen_plan(&phys_dim, json_params).map_err(AppError::InvalidParameters)?,
+        None => PenPlan::single(),
     };
 
-    if let Err(err_str) = ins_bytes {
-        return format!("error generating bytes:{}", err_str).to_owned();
+    let (bytes, plan) = optimise::apply(&app, &phys_dim, bytes, (ix, iy), plan);
+    let instruction_set = InstructionSet::new(bytes, ix, iy).map_err(|err| AppError::InvalidParameters(err.to_string()))?;
+
+    write_preview(&app, &phys_dim, &instruction_set, &plan)
+}
+
+
+/// 
+/// Gets the physical dimensions of the machine from the app config, falling back
+/// to an A4 page on a default machine if the app hasn't been configured.
+///
+/// # Parameters:
+/// - `app`: The Tauri app handle
+///
+/// # Returns:
+/// - The physical dimensions of the machine
+///
+pub(crate) fn get_physical_dimensions(app: &tauri::AppHandle) -> PhysicalDimensions {
+    match get_app_config_struct(app) {
+        Ok(app_config) => app_config.physical_dimensions(),
+        Err(_) => PhysicalDimensions::new(754., (754. - 210.) / 1.98, 192., 210., 297.),
     }
+}
 
-    let instruction_set = match ins_bytes {
-        Ok((bytes, ix, iy)) => InstructionSet::new(bytes, ix, iy).unwrap(),
-        Err(str) => { panic!("{}", str); }
-    };
 
+/// 
+/// Caches the instructions, start position and pen plan of a drawing, and renders its preview image.
+/// Drawings with more than one pen are rendered in the pens' colours.
+///
+/// # Parameters:
+/// - `app`: The Tauri app handle
+/// - `phys_dim`: The physical dimensions of the machine
+/// - `instruction_set`: The drawing's instruction set
+/// - `plan`: The drawing's pen plan
+///
+/// # Returns:
+/// - A path pointing to the preview image
+/// - An error explaining why the preview couldn't be rendered
+///
+pub(crate) fn write_preview(app: &tauri::AppHandle, phys_dim: &PhysicalDimensions, instruction_set: &InstructionSet, plan: &PenPlan) -> Result<String, AppError> {
+    let cache_dir = tauri::Manager::path(app).app_cache_dir().map_err(|err| AppError::Io(err.to_string()))?;
+    write_preview_to(&cache_dir, phys_dim, instruction_set, plan)
+}
+
+/// 
+/// Caches the instructions, start position and pen plan of a drawing in a cache directory,
+/// and renders its preview image there.
+///
+/// # Parameters:
+/// - `cache_dir`: The app cache directory
+/// - `phys_dim`: The physical dimensions of the machine
+/// - `instruction_set`: The drawing's instruction set
+/// - `plan`: The drawing's pen plan
+///
+/// # Returns:
+/// - A path pointing to the preview image
+/// - An error explaining why the preview couldn't be rendered
+///
+pub fn write_preview_to(cache_dir: &Path, phys_dim: &PhysicalDimensions, instruction_set: &InstructionSet, plan: &PenPlan) -> Result<String, AppError> {
     // directory handling
-    let cache_dir = tauri::Manager::path(&app).app_cache_dir().expect("Should get cache dir");
-    let _ = std::fs::create_dir_all(&cache_dir).map_err(|s| s.to_string());
+    std::fs::create_dir_all(cache_dir)?;
 
-    let ins_file_path = cache_dir.join("instructions.bin");
-    let mut ins_file = File::create(ins_file_path).unwrap();
-    let _ = ins_file.write_all(instruction_set.get_binary().as_slice());
+    let mut ins_file = File::create(cache_dir.join("instructions.bin"))?;
+    ins_file.write_all(instruction_set.get_binary().as_slice())?;
 
-    let start_file_path = cache_dir.join("start.bin");
-    let mut start_file = File::create(start_file_path).unwrap();
-    let _ = start_file.write_all(format!("{} {}", instruction_set.get_init().0, instruction_set.get_init().1).as_bytes());
+    let mut start_file = File::create(cache_dir.join("start.bin"))?;
+    start_file.write_all(format!("{} {}", instruction_set.get_init().0, instruction_set.get_init().1).as_bytes())?;
+
+    pens::write_plan(cache_dir, plan);
 
     let preview_path = cache_dir.join("preview.png");
+    let preview_str = preview_path.to_string_lossy().to_string();
 
-    match generate_preview((instruction_set.get_init().0, instruction_set.get_init().1), &phys_dim, &instruction_set, preview_path.to_str().unwrap()) {
-        Some(err) => {
-            return format!("error generate bytes:{}", err).to_owned();
-        },
-        None => {}
+    let preview_err = if plan.segments.len() > 1 {
+        pens::generate_coloured_preview(phys_dim, instruction_set, plan, &preview_path)
+    } else {
+        generate_preview((instruction_set.get_init().0, instruction_set.get_init().1), phys_dim, instruction_set, preview_str.as_str()).map(|err| err.to_string())
     };
 
-    preview_path.to_str().unwrap().to_owned()
+    match preview_err {
+        Some(err) => Err(AppError::Io(format!("Couldn't render the preview: {}", err))),
+        None => Ok(preview_str),
+    }
 }
 
 
@@ -138,7 +156,7 @@ fn gen_preview(app: tauri::AppHandle, style_id: &str, json_params: &str) -> Stri
 ///
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
-    let state = client::AppState { writer: Arc::new(Mutex::new(None)), reader: Arc::new(Mutex::new(None)), paused_flag: Arc::new(Mutex::new(false)), buf_idx: Arc::new(Mutex::new(0)) };
+    let state = client::AppState::default();
 
     tauri::Builder::default()
         .plugin(tauri_plugin_opener::init())
@@ -146,8 +164,12 @@ pub fn run() {
         .manage(state)
         .invoke_handler(tauri::generate_handler![
             gen_preview,
+            styles::list_styles,
             client::send_to_firmware,
+            client::resume_job,
+            checkpoint::get_checkpoint,
             client::pause_firmware,
+            client::confirm_pen_change,
             client::move_pen_to_start,
             client::apply_manual_control,
             client::manual_goto,
@@ -155,10 +177,26 @@ pub fn run() {
             client::save_machine_config,
             client::get_machine_config,
             client::get_image_stats,
+            export::export_svg,
+            export::export_gcode,
+            export::export_hpgl,
+            client::test_connection,
+            queue::enqueue_job,
+            queue::list_jobs,
+            queue::remove_job,
+            queue::start_queue,
+            queue::stop_queue,
+            queue::confirm_paper_change,
             file::save_file,
             file::open_file,
             file::get_app_config,
             file::save_app_config,
+            profiles::list_profiles,
+            profiles::create_profile,
+            profiles::switch_profile,
+            profiles::delete_profile,
+            discovery::discover_machines,
+            transport::list_serial_ports,
             plugin_handler::get_parameters,
         ])
         .run(tauri::generate_context!())
@@ -0,0 +1,150 @@
+use serde_json::{Map, Value};
+
+
+///
+/// The current version of the save file format, written into every saved drawing.
+/// Files saved before versioning was introduced have no `format_version`, and are treated as version 0.
+///
+pub const FORMAT_VERSION: u64 = 1;
+
+
+///
+/// A single step in the migration chain, upgrading a save file from `from` to `from + 1`.
+///
+/// # Fields:
+/// - `from`: The format version this migration upgrades from
+/// - `description`: A human readable description, included in the migration report
+/// - `apply`: Mutates the save file document in place, pushing any extra notes to the report
+///
+struct Migration {
+    from: u64,
+    description: &'static str,
+    apply: fn(&mut Map<String, Value>, &mut Vec<String>),
+}
+
+
+///
+/// The chain of migrations, ordered by the version they upgrade from.
+/// When a bbcore parameter struct renames a field, a migration should be appended here,
+/// and `FORMAT_VERSION` bumped.
+///
+static MIGRATIONS: &[Migration] = &[
+    Migration {
+        from: 0,
+        description: "Upgraded an unversioned save file to format version 1",
+        apply: |_, _| {},
+    },
+];
+
+
+///
+/// Upgrades a save file document to the current format version, by applying each
+/// migration in the chain from the document's version onwards.
+///
+/// # Parameters:
+/// - `document`: The save file, as a JSON value
+///
+/// # Returns:
+/// - A report of every migration applied, which may be empty
+/// - An error, as a string, if the document can't be migrated
+///
+pub fn migrate(document: &mut Value) -> Result<Vec<String>, String> {
+    let object = match document.as_object_mut() {
+        Some(obj) => obj,
+        None => { return Err("Expected a JSON object".to_owned()); }
+    };
+
+    let mut version = match object.get("format_version") {
+        None => 0,
+        Some(val) => match val.as_u64() {
+            Some(v) => v,
+            None => { return Err("Invalid format version".to_owned()); }
+        },
+    };
+
+    if version > FORMAT_VERSION {
+        return Err(format!("File was saved with format version {}, but this app only supports up to version {}", version, FORMAT_VERSION).to_owned());
+    }
+
+    let mut report = Vec::new();
+    let from_version = version;
+    for migration in MIGRATIONS.iter().filter(|m| m.from >= from_version) {
+        (migration.apply)(object, &mut report);
+        report.push(migration.description.to_owned());
+        version = migration.from + 1;
+        object.insert("format_version".to_owned(), Value::from(version));
+    }
+
+    Ok(report)
+}
+
+
+///
+/// Reconciles saved drawing parameters against the style's current parameters.
+/// Fields missing from the save file, usually because the style gained a parameter
+/// after the file was saved, are filled with their defaults. Each change is added to the report.
+///
+/// # Parameters:
+/// - `defaults`: The default parameters of the drawing style
+/// - `parameters`: The saved drawing parameters, modified in place
+/// - `report`: The migration report to append to
+///
+pub fn reconcile_parameters(defaults: &Map<String, Value>, parameters: &mut Value, report: &mut Vec<String>) {
+    let object = match parameters.as_object_mut() {
+        Some(obj) => obj,
+        None => { return; }
+    };
+
+    for (key, default) in defaults {
+        if !object.contains_key(key) {
+            report.push(format!("Parameter '{}' was missing, so it was set to its default of {}", key, default));
+            object.insert(key.clone(), default.clone());
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn upgrades_an_unversioned_save_file() {
+        let mut document = json!({ "drawing_id": "lines", "drawing_parameters": { "spacing": 4 } });
+
+        let report = migrate(&mut document).unwrap();
+        assert_eq!(report, vec![MIGRATIONS[0].description.to_owned()]);
+        assert_eq!(document["format_version"], FORMAT_VERSION);
+        assert_eq!(document["drawing_parameters"], json!({ "spacing": 4 }));
+    }
+
+    #[test]
+    fn leaves_a_current_save_file_alone() {
+        let mut document = json!({ "format_version": FORMAT_VERSION, "drawing_id": "lines", "drawing_parameters": {} });
+        let before = document.clone();
+
+        assert!(migrate(&mut document).unwrap().is_empty());
+        assert_eq!(document, before);
+    }
+
+    #[test]
+    fn refuses_newer_and_invalid_versions() {
+        assert!(migrate(&mut json!({ "format_version": FORMAT_VERSION + 1 })).is_err());
+        assert!(migrate(&mut json!({ "format_version": "one" })).is_err());
+        assert!(migrate(&mut json!([])).is_err());
+    }
+
+    #[test]
+    fn fills_missing_parameters_with_their_defaults() {
+        let defaults = json!({ "margin": 15, "rotation": 0, "path": "" });
+        let mut parameters = json!({ "margin": 5, "unknown": true });
+        let mut report = Vec::new();
+
+        reconcile_parameters(defaults.as_object().unwrap(), &mut parameters, &mut report);
+        assert_eq!(parameters, json!({ "margin": 5, "rotation": 0, "path": "", "unknown": true }));
+        assert_eq!(report.len(), 2);
+        assert!(report.iter().any(|line| line.contains("'rotation'")));
+        assert!(report.iter().any(|line| line.contains("'path'")));
+    }
+}
@@ -0,0 +1,348 @@
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpListener;
+use tokio::sync::{mpsc, watch, Mutex};
+use tokio::task::JoinHandle;
+
+use crate::protocol::{self, Handshake};
+use crate::transport::Link;
+
+
+///
+/// The behaviour of a simulated machine.
+///
+/// # Fields:
+/// - `handshake`: The configuration the machine reports when a client connects
+/// - `ack_delay`: How long the machine takes to execute each buffer before asking for the next
+/// - `disconnect_after`: Drops the connection once this many instruction bytes have been received, over TCP only
+/// - `skip_handshake`: Accepts connections without sending a handshake
+///
+#[derive(Clone)]
+pub struct MockConfig {
+    pub handshake: Handshake,
+    pub ack_delay: Duration,
+    pub disconnect_after: Option<usize>,
+    pub skip_handshake: bool,
+}
+
+impl Default for MockConfig {
+    fn default() -> Self {
+        MockConfig {
+            handshake: Handshake { protocol_version: 1, instruction_buffer_size: 1024, max_motor_speed: 500, min_pulse_width: 0 },
+            ack_delay: Duration::from_millis(50),
+            disconnect_after: None,
+            skip_handshake: false,
+        }
+    }
+}
+
+///
+/// Everything the simulated machine has received, for inspection once a client has finished with it.
+///
+/// # Fields:
+/// - `connections`: The number of connections accepted
+/// - `buffers`: The length of every instruction buffer received, in order
+/// - `instructions`: Every instruction byte received, in order
+/// - `manual_controls`: Every (target byte, value) manual control received, in order
+/// - `paused`: Whether the machine is currently paused
+/// - `holding`: Whether the machine is holding a buffer until it's resumed
+/// - `requests_while_paused`: The number of buffers asked for while paused, which should stay at zero
+/// - `stopped`: Whether a client has stopped the machine
+/// - `finished`: Whether a drawing has been run to its end
+///
+#[derive(Clone, Default)]
+pub struct MockLog {
+    pub connections: usize,
+    pub buffers: Vec<usize>,
+    pub instructions: Vec<u8>,
+    pub manual_controls: Vec<(u8, i16)>,
+    pub paused: bool,
+    pub holding: bool,
+    pub requests_while_paused: usize,
+    pub stopped: bool,
+    pub finished: bool,
+}
+
+///
+/// A simulated Blot Bot firmware, listening on a local TCP port or attached to a serial port.
+/// It speaks the protocol described in `protocol`, which `tests/firmware.rs` checks against bbcore's client.
+///
+/// # Fields:
+/// - `addr`: The address the machine is listening on, or None if it's attached to a serial port
+/// - `log`: Everything the machine has received
+/// - `task`: The task accepting connections
+///
+pub struct MockFirmware {
+    addr: Option<SocketAddr>,
+    log: Arc<Mutex<MockLog>>,
+    task: JoinHandle<()>,
+}
+
+impl MockFirmware {
+
+    ///
+    /// Starts a simulated machine on a free local port.
+    ///
+    /// # Parameters:
+    /// - `config`: The behaviour of the machine
+    ///
+    /// # Returns:
+    /// - The running machine
+    /// - An IO error if the port couldn't be bound
+    ///
+    pub async fn start(config: MockConfig) -> std::io::Result<MockFirmware> {
+        MockFirmware::bind("127.0.0.1:0", config).await
+    }
+
+    ///
+    /// Starts a simulated machine on a given address.
+    ///
+    /// # Parameters:
+    /// - `addr`: The address to listen on, such as `0.0.0.0:8080`
+    /// - `config`: The behaviour of the machine
+    ///
+    /// # Returns:
+    /// - The running machine
+    /// - An IO error if the address couldn't be bound
+    ///
+    pub async fn bind(addr: &str, config: MockConfig) -> std::io::Result<MockFirmware> {
+        let listener = TcpListener::bind(addr).await?;
+        let addr = listener.local_addr()?;
+        let log = Arc::new(Mutex::new(MockLog::default()));
+
+        let task_log = log.clone();
+        let task = tokio::spawn(async move {
+            // the machine serves one client at a time, like the real firmware
+            while let Ok((stream, _)) = listener.accept().await {
+                task_log.lock().await.connections += 1;
+                let mut link: Box<dyn Link> = Box::new(stream);
+                let _ = serve(&mut link, &config, &task_log).await;
+            }
+        });
+
+        Ok(MockFirmware { addr: Some(addr), log, task })
+    }
+
+    ///
+    /// Starts a simulated machine on a serial port, such as one end of a pseudo-terminal pair.
+    /// A serial port can't tell the machine when the app opens or closes it, so each session
+    /// starts with a handshake as soon as the last has ended, like a board which resets.
+    /// Sessions end as they do over TCP, except that `disconnect_after` has no effect.
+    ///
+    /// # Parameters:
+    /// - `link`: The machine's end of the serial connection
+    /// - `config`: The behaviour of the machine
+    ///
+    /// # Returns:
+    /// - The running machine
+    ///
+    pub fn attach(mut link: Box<dyn Link>, config: MockConfig) -> MockFirmware {
+        let config = MockConfig { disconnect_after: None, ..config };
+        let log = Arc::new(Mutex::new(MockLog::default()));
+
+        let task_log = log.clone();
+        let task = tokio::spawn(async move {
+            loop {
+                task_log.lock().await.connections += 1;
+                if let Err(err) = serve(&mut link, &config, &task_log).await {
+                    // the port has gone, rather than the app sending something invalid
+                    if err.kind() != std::io::ErrorKind::InvalidData {
+                        break;
+                    }
+                }
+            }
+        });
+
+        MockFirmware { addr: None, log, task }
+    }
+
+    ///
+    /// Gets the address the machine is listening on.
+    ///
+    /// # Returns:
+    /// - The address, or None if the machine is attached to a serial port
+    ///
+    pub fn addr(&self) -> Option<SocketAddr> {
+        self.addr
+    }
+
+    ///
+    /// Gets a copy of everything the machine has received so far.
+    ///
+    pub async fn log(&self) -> MockLog {
+        self.log.lock().await.clone()
+    }
+
+    ///
+    /// Stops listening, dropping any connected client.
+    ///
+    pub fn shutdown(self) {
+        self.task.abort();
+    }
+}
+
+
+///
+/// Serves a single client until it disconnects, stops the machine, applies a manual control,
+/// or the drawing has been executed, or until a fault is injected.
+/// Commands are received alongside executing buffers, so a pause or stop takes effect part way through a buffer.
+///
+/// # Parameters:
+/// - `link`: The client's connection
+/// - `config`: The behaviour of the machine
+/// - `log`: The log to record received data in
+///
+/// # Returns:
+/// - Void once the session has ended
+/// - An IO error if the connection failed
+///
+async fn serve(link: &mut Box<dyn Link>, config: &MockConfig, log: &Arc<Mutex<MockLog>>) -> std::io::Result<()> {
+    let (mut reader, mut writer) = tokio::io::split(link);
+
+    if !config.skip_handshake {
+        config.handshake.write(&mut writer).await?;
+    }
+    writer.write_u8(protocol::ST_NEED_BUFFER).await?;
+
+    let (paused_tx, paused_rx) = watch::channel(false);
+    let (buffer_tx, buffer_rx) = mpsc::channel::<usize>(1);
+
+    // whichever ends first ends the session, dropping the other
+    tokio::select! {
+        result = receive(&mut reader, config, log, buffer_tx, paused_tx) => result,
+        _ = execute(writer, buffer_rx, paused_rx, config.ack_delay, log.clone()) => Ok(()),
+    }
+}
+
+///
+/// Receives commands from the client, passing buffers and pauses on to the executor.
+///
+/// # Parameters:
+/// - `reader`: The read half of the client's connection
+/// - `config`: The behaviour of the machine
+/// - `log`: The log to record received data in
+/// - `buffer_tx`: Sends the length of each buffer to execute
+/// - `paused_tx`: Sends the paused state of the machine
+///
+/// # Returns:
+/// - Void once the client has stopped the machine, applied a manual control, or a fault was injected
+/// - An IO error if the connection failed, or the client sent something invalid
+///
+async fn receive<R: AsyncRead + Unpin>(reader: &mut R, config: &MockConfig, log: &Arc<Mutex<MockLog>>, buffer_tx: mpsc::Sender<usize>, paused_tx: watch::Sender<bool>) -> std::io::Result<()> {
+    let mut received = 0;
+    loop {
+        let opcode = reader.read_u8().await?;
+
+        match opcode {
+            protocol::OP_BUFFER => {
+                let len = reader.read_u32().await? as usize;
+                if len > config.handshake.instruction_buffer_size as usize {
+                    return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "buffer larger than the instruction buffer"));
+                }
+
+                let mut buffer = vec![0; len];
+                reader.read_exact(&mut buffer).await?;
+                received += len;
+
+                let mut log_lock = log.lock().await;
+                log_lock.buffers.push(len);
+                log_lock.instructions.extend_from_slice(&buffer);
+                drop(log_lock);
+
+                if config.disconnect_after.is_some_and(|limit| received >= limit) {
+                    return Ok(());
+                }
+                if buffer_tx.send(len).await.is_err() {
+                    return Ok(());
+                }
+            },
+            protocol::OP_PEN_LIFT | protocol::OP_MOTORS => {
+                // the app sends one control per connection
+                let value = reader.read_i16().await?;
+                log.lock().await.manual_controls.push((opcode, value));
+                return Ok(());
+            },
+            protocol::OP_PAUSE => {
+                let paused = reader.read_u8().await? == 1;
+                log.lock().await.paused = paused;
+                let _ = paused_tx.send(paused);
+            },
+            protocol::OP_STOP => {
+                log.lock().await.stopped = true;
+                return Ok(());
+            },
+            _ => {
+                return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, format!("unknown opcode {}", opcode)));
+            },
+        }
+    }
+}
+
+///
+/// Executes received buffers one at a time, asking the client for the next buffer
+/// once each has been executed. An empty buffer ends the drawing.
+///
+/// # Parameters:
+/// - `writer`: The write half of the client's connection
+/// - `buffer_rx`: Receives the length of each buffer to execute
+/// - `paused_rx`: Receives the paused state of the machine
+/// - `ack_delay`: How long each buffer takes to execute
+/// - `log`: The log to record the finished drawing in
+///
+async fn execute<W: AsyncWrite + Unpin>(mut writer: W, mut buffer_rx: mpsc::Receiver<usize>, mut paused_rx: watch::Receiver<bool>, ack_delay: Duration, log: Arc<Mutex<MockLog>>) {
+    while let Some(len) = buffer_rx.recv().await {
+        if len == 0 {
+            log.lock().await.finished = true;
+            let _ = writer.write_u8(protocol::ST_FINISHED).await;
+            break;
+        }
+
+        // a paused machine holds its buffer until it's resumed
+        let mut remaining = ack_delay;
+        while !remaining.is_zero() {
+            if *paused_rx.borrow() {
+                if !hold(&mut paused_rx, &log).await { return; }
+                continue;
+            }
+            let tick = remaining.min(Duration::from_millis(10));
+            tokio::time::sleep(tick).await;
+            remaining -= tick;
+        }
+        if *paused_rx.borrow() && !hold(&mut paused_rx, &log).await {
+            return;
+        }
+
+        if *paused_rx.borrow() {
+            log.lock().await.requests_while_paused += 1;
+        }
+        if writer.write_u8(protocol::ST_NEED_BUFFER).await.is_err() {
+            break;
+        }
+    }
+}
+
+///
+/// Holds the current buffer until the machine is resumed, recording it in the log.
+///
+/// # Parameters:
+/// - `paused_rx`: Receives the paused state of the machine
+/// - `log`: The log to record the hold in
+///
+/// # Returns:
+/// - True once the machine is resumed, or false if the connection ended
+///
+async fn hold(paused_rx: &mut watch::Receiver<bool>, log: &Arc<Mutex<MockLog>>) -> bool {
+    log.lock().await.holding = true;
+    let mut resumed = true;
+    while *paused_rx.borrow() {
+        if paused_rx.changed().await.is_err() {
+            resumed = false;
+            break;
+        }
+    }
+    log.lock().await.holding = false;
+    resumed
+}
@@ -0,0 +1,376 @@
+use std::ops::Range;
+
+use byteorder::{BigEndian, ByteOrder};
+use bbcore::hardware::PhysicalDimensions;
+
+
+// The constants, encoding and kinematics below mirror bbcore's, which it doesn't expose yet.
+// `tests/motion.rs` checks them against instructions generated by bbcore's drawing styles, and
+// fails if the two disagree. Once bbcore exposes them, this module should use its definitions instead.
+
+///
+/// The number of motor steps per millimetre of belt.
+///
+pub const STEPS_PER_MM: f64 = 5.;
+
+///
+/// The size of a single instruction in the instruction stream, in bytes.
+/// Each instruction is a pair of big-endian i16 values: the left motor steps, then the right motor steps.
+///
+pub const INSTRUCTION_SIZE: usize = 4;
+
+///
+/// A left motor value which marks the instruction as a pen command, rather than a movement.
+/// The right motor value then holds 1 to lower the pen, or 0 to raise it.
+///
+const PEN_COMMAND: i16 = i16::MIN;
+
+///
+/// The longest pen-up travel segment, in millimetres. Travel is split into segments
+/// so the pen follows a roughly straight line across the page.
+///
+const TRAVEL_SEGMENT_MM: f64 = 5.;
+
+///
+/// The longest pen-down segment, in millimetres. Strokes are split more finely than travel,
+/// as the belts only move the pen in a straight line over short distances.
+///
+const DRAW_SEGMENT_MM: f64 = 1.;
+
+
+///
+/// A single decoded instruction.
+///
+/// # Variants:
+/// - `Move`: A relative movement of the left and right motors, in steps
+/// - `Pen`: A pen command, true to lower the pen, false to raise it
+///
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Instruction {
+    Move(i16, i16),
+    Pen(bool),
+}
+
+///
+/// The pen's state after an instruction has been executed.
+///
+/// # Fields:
+/// - `byte_idx`: The index of the byte following the instruction
+/// - `x`: The horizontal position of the pen on the page, in millimetres
+/// - `y`: The vertical position of the pen on the page, in millimetres
+/// - `pen_down`: Whether the pen is touching the page
+///
+#[derive(Clone, Copy, Debug)]
+pub struct PenSample {
+    pub byte_idx: usize,
+    pub x: f64,
+    pub y: f64,
+    pub pen_down: bool,
+}
+
+
+///
+/// Decodes an instruction stream. Trailing bytes which don't form a whole instruction are ignored.
+///
+/// # Parameters:
+/// - `bytes`: The instruction bytes
+///
+/// # Returns:
+/// - An iterator over the instructions
+///
+pub fn decode(bytes: &[u8]) -> impl Iterator<Item = Instruction> + '_ {
+    bytes.chunks_exact(INSTRUCTION_SIZE).map(|chunk| {
+        let left = BigEndian::read_i16(&chunk[0..2]);
+        let right = BigEndian::read_i16(&chunk[2..4]);
+        if left == PEN_COMMAND {
+            Instruction::Pen(right != 0)
+        } else {
+            Instruction::Move(left, right)
+        }
+    })
+}
+
+///
+/// Encodes instructions into an instruction stream.
+///
+/// # Parameters:
+/// - `instructions`: The instructions to encode
+///
+/// # Returns:
+/// - The instruction bytes
+///
+pub fn encode(instructions: &[Instruction]) -> Vec<u8> {
+    let mut bytes = vec![0u8; instructions.len() * INSTRUCTION_SIZE];
+    for (instruction, chunk) in instructions.iter().zip(bytes.chunks_exact_mut(INSTRUCTION_SIZE)) {
+        let (left, right) = match instruction {
+            Instruction::Move(left, right) => (*left, *right),
+            Instruction::Pen(down) => (PEN_COMMAND, *down as i16),
+        };
+        BigEndian::write_i16(&mut chunk[0..2], left);
+        BigEndian::write_i16(&mut chunk[2..4], right);
+    }
+    bytes
+}
+
+
+///
+/// Calculates the belt lengths needed to place the pen at a position on the page.
+///
+/// # Parameters:
+/// - `phys_dim`: The physical dimensions of the machine
+/// - `x`: The horizontal position on the page, in millimetres
+/// - `y`: The vertical position on the page, in millimetres
+///
+/// # Returns:
+/// - The (left, right) belt lengths, in steps
+///
+pub fn belt_steps(phys_dim: &PhysicalDimensions, x: f64, y: f64) -> (i64, i64) {
+    let abs_x = phys_dim.page_left_offset() + x;
+    let abs_y = phys_dim.page_top_offset() + y;
+
+    let left = abs_x.hypot(abs_y);
+    let right = (phys_dim.motor_interspace() - abs_x).hypot(abs_y);
+
+    ((left * STEPS_PER_MM).round() as i64, (right * STEPS_PER_MM).round() as i64)
+}
+
+///
+/// Calculates the position of the pen on the page from the belt lengths, the inverse of `belt_steps`.
+///
+/// # Parameters:
+/// - `phys_dim`: The physical dimensions of the machine
+/// - `left`: The left belt length, in steps
+/// - `right`: The right belt length, in steps
+///
+/// # Returns:
+/// - The (x, y) position on the page, in millimetres
+///
+pub fn page_position(phys_dim: &PhysicalDimensions, left: i64, right: i64) -> (f64, f64) {
+    let interspace = phys_dim.motor_interspace();
+    let left = left as f64 / STEPS_PER_MM;
+    let right = right as f64 / STEPS_PER_MM;
+
+    let abs_x = (left * left - right * right + interspace * interspace) / (2. * interspace);
+    let abs_y = (left * left - abs_x * abs_x).max(0.).sqrt();
+
+    (abs_x - phys_dim.page_left_offset(), abs_y - phys_dim.page_top_offset())
+}
+
+
+///
+/// Follows an instruction stream from its starting position, sampling the pen after each instruction.
+/// The pen is assumed to be raised at the start of the stream.
+///
+/// # Parameters:
+/// - `phys_dim`: The physical dimensions of the machine
+/// - `bytes`: The instruction bytes
+/// - `init`: The (x, y) starting position of the drawing, in page millimetres
+///
+/// # Returns:
+/// - A sample of the pen after every instruction
+///
+pub fn trace(phys_dim: &PhysicalDimensions, bytes: &[u8], init: (f64, f64)) -> Vec<PenSample> {
+    let (mut left, mut right) = belt_steps(phys_dim, init.0, init.1);
+    let mut pen_down = false;
+    let mut samples = Vec::with_capacity(bytes.len() / INSTRUCTION_SIZE);
+
+    for (i, instruction) in decode(bytes).enumerate() {
+        match instruction {
+            Instruction::Move(l, r) => { left += l as i64; right += r as i64; },
+            Instruction::Pen(down) => { pen_down = down; },
+        }
+
+        let (x, y) = page_position(phys_dim, left, right);
+        samples.push(PenSample { byte_idx: (i + 1) * INSTRUCTION_SIZE, x, y, pen_down });
+    }
+
+    samples
+}
+
+///
+/// Reconstructs the pen-down strokes of an instruction stream as page space polylines.
+/// Pen-up movements are omitted.
+///
+/// # Parameters:
+/// - `phys_dim`: The physical dimensions of the machine
+/// - `bytes`: The instruction bytes
+/// - `init`: The (x, y) starting position of the drawing, in page millimetres
+///
+/// # Returns:
+/// - A list of strokes, each a list of (x, y) points in millimetres
+///
+pub fn strokes(phys_dim: &PhysicalDimensions, bytes: &[u8], init: (f64, f64)) -> Vec<Vec<(f64, f64)>> {
+    let samples = trace(phys_dim, bytes, init);
+    trace_strokes(&samples, 0..samples.len(), init)
+}
+
+///
+/// Reconstructs the pen-down strokes drawn by a range of samples from a trace.
+/// A stroke continuing from before the range starts at the sample preceding it.
+///
+/// # Parameters:
+/// - `samples`: The trace of an instruction stream, see `trace`
+/// - `range`: The range of sample indices to reconstruct
+/// - `init`: The (x, y) starting position of the trace, in page millimetres
+///
+/// # Returns:
+/// - A list of strokes, each a list of (x, y) points in millimetres
+///
+pub fn trace_strokes(samples: &[PenSample], range: Range<usize>, init: (f64, f64)) -> Vec<Vec<(f64, f64)>> {
+    let mut strokes: Vec<Vec<(f64, f64)>> = Vec::new();
+    let mut last = match range.start.checked_sub(1).and_then(|i| samples.get(i)) {
+        Some(sample) => (sample.x, sample.y, sample.pen_down),
+        None => (init.0, init.1, false),
+    };
+
+    for sample in &samples[range] {
+        if sample.pen_down {
+            if !last.2 || strokes.is_empty() {
+                strokes.push(vec![(last.0, last.1)]);
+            }
+            if let Some(stroke) = strokes.last_mut() {
+                stroke.push((sample.x, sample.y));
+            }
+        }
+        last = (sample.x, sample.y, sample.pen_down);
+    }
+
+    strokes.retain(|stroke| stroke.len() > 1);
+    strokes
+}
+
+///
+/// Finds how many samples of a trace have been executed once the machine reaches a byte index.
+///
+/// # Parameters:
+/// - `samples`: The trace of an instruction stream, see `trace`
+/// - `byte_idx`: The byte index the machine has reached
+///
+/// # Returns:
+/// - The number of executed samples
+///
+pub fn samples_until(samples: &[PenSample], byte_idx: usize) -> usize {
+    samples.partition_point(|sample| sample.byte_idx <= byte_idx)
+}
+
+///
+/// Calculates the pen position at the end of an instruction stream.
+///
+/// # Parameters:
+/// - `phys_dim`: The physical dimensions of the machine
+/// - `bytes`: The instruction bytes
+/// - `init`: The (x, y) starting position of the drawing, in page millimetres
+///
+/// # Returns:
+/// - The final (x, y) position, in page millimetres, and whether the pen is down
+///
+pub fn end_position(phys_dim: &PhysicalDimensions, bytes: &[u8], init: (f64, f64)) -> (f64, f64, bool) {
+    match trace(phys_dim, bytes, init).last() {
+        Some(sample) => (sample.x, sample.y, sample.pen_down),
+        None => (init.0, init.1, false),
+    }
+}
+
+
+///
+/// Generates the instructions to raise the pen and travel between two positions on the page.
+/// The pen is left raised at the end of the travel.
+///
+/// # Parameters:
+/// - `phys_dim`: The physical dimensions of the machine
+/// - `from`: The (x, y) starting position, in page millimetres
+/// - `to`: The (x, y) target position, in page millimetres
+///
+/// # Returns:
+/// - The travel instructions
+///
+pub fn travel(phys_dim: &PhysicalDimensions, from: (f64, f64), to: (f64, f64)) -> Vec<Instruction> {
+    let mut instructions = vec![Instruction::Pen(false)];
+    instructions.extend(line(phys_dim, from, to, TRAVEL_SEGMENT_MM));
+    instructions
+}
+
+///
+/// Generates the instructions to draw polylines on the page, travelling between them with the pen raised.
+/// The drawing starts at the first point of the first stroke, and the pen is left raised at the end.
+///
+/// # Parameters:
+/// - `phys_dim`: The physical dimensions of the machine
+/// - `strokes`: The strokes to draw, each a list of (x, y) points in page millimetres
+///
+/// # Returns:
+/// - The instruction bytes, and the (x, y) starting position of the drawing in page millimetres
+///
+pub fn draw_strokes(phys_dim: &PhysicalDimensions, strokes: &[Vec<(f64, f64)>]) -> (Vec<u8>, (f64, f64)) {
+    let init = strokes.iter().find(|stroke| stroke.len() > 1).map_or((0., 0.), |stroke| stroke[0]);
+    let mut instructions = Vec::new();
+    let mut position = init;
+
+    for stroke in strokes.iter().filter(|stroke| stroke.len() > 1) {
+        instructions.extend(travel(phys_dim, position, stroke[0]));
+        instructions.push(Instruction::Pen(true));
+        for pair in stroke.windows(2) {
+            instructions.extend(line(phys_dim, pair[0], pair[1], DRAW_SEGMENT_MM));
+        }
+        position = stroke[stroke.len() - 1];
+    }
+    instructions.push(Instruction::Pen(false));
+
+    (encode(&instructions), init)
+}
+
+///
+/// Generates the movement instructions between two positions on the page, split into segments
+/// so the pen follows a roughly straight line.
+///
+/// # Parameters:
+/// - `phys_dim`: The physical dimensions of the machine
+/// - `from`: The (x, y) starting position, in page millimetres
+/// - `to`: The (x, y) target position, in page millimetres
+/// - `segment_mm`: The longest segment, in millimetres
+///
+/// # Returns:
+/// - The movement instructions
+///
+fn line(phys_dim: &PhysicalDimensions, from: (f64, f64), to: (f64, f64), segment_mm: f64) -> Vec<Instruction> {
+    let mut instructions = Vec::new();
+
+    let distance = (to.0 - from.0).hypot(to.1 - from.1);
+    let segments = (distance / segment_mm).ceil().max(1.) as usize;
+
+    let (mut left, mut right) = belt_steps(phys_dim, from.0, from.1);
+    for i in 1..=segments {
+        let t = i as f64 / segments as f64;
+        let (target_left, target_right) = belt_steps(phys_dim, from.0 + (to.0 - from.0) * t, from.1 + (to.1 - from.1) * t);
+        instructions.extend(steps_between((left, right), (target_left, target_right)));
+        left = target_left;
+        right = target_right;
+    }
+
+    instructions
+}
+
+///
+/// Generates movement instructions between two sets of belt lengths, split so each step count fits in an i16.
+///
+/// # Parameters:
+/// - `from`: The (left, right) starting belt lengths, in steps
+/// - `to`: The (left, right) target belt lengths, in steps
+///
+/// # Returns:
+/// - The movement instructions
+///
+pub fn steps_between(from: (i64, i64), to: (i64, i64)) -> Vec<Instruction> {
+    let mut remaining = (to.0 - from.0, to.1 - from.1);
+    let mut instructions = Vec::new();
+    let limit = i16::MAX as i64;
+
+    while remaining != (0, 0) {
+        let left = remaining.0.clamp(-limit, limit);
+        let right = remaining.1.clamp(-limit, limit);
+        instructions.push(Instruction::Move(left as i16, right as i16));
+        remaining = (remaining.0 - left, remaining.1 - right);
+    }
+
+    instructions
+}
@@ -0,0 +1,602 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+use serde::{Serialize, Deserialize};
+use bbcore::hardware::PhysicalDimensions;
+
+use crate::estimate::{self, MachineProfile};
+use crate::file::get_app_config_struct;
+use crate::motion::{self, Instruction, INSTRUCTION_SIZE};
+use crate::pens::{PenPlan, Segment};
+
+
+///
+/// The furthest apart, in millimetres, the end of one stroke and the start of the next can be for them
+/// to be merged, drawing the gap rather than lifting the pen. It's kept under the width of a pen line.
+///
+pub const MERGE_DISTANCE_MM: f64 = 0.3;
+
+///
+/// How many strokes ahead the 2-opt pass looks for a run to reverse, which keeps large drawings fast.
+///
+const TWO_OPT_WINDOW: usize = 64;
+
+///
+/// The most 2-opt passes made over a drawing, if each pass still finds improvements.
+///
+const TWO_OPT_PASSES: usize = 8;
+
+///
+/// The file in the app cache directory holding the optimisation report of the previewed drawing.
+///
+const REPORT_FILE: &str = "optimisation.json";
+
+
+///
+/// A summary of how the path optimisation changed a drawing.
+///
+/// # Fields:
+/// - `strokes`: The number of strokes in the drawing
+/// - `merged`: The number of strokes merged into the stroke before them
+/// - `travel_before_mm`: The pen-up travel of the drawing as generated, in millimetres
+/// - `travel_after_mm`: The pen-up travel of the optimised drawing, in millimetres
+/// - `secs_before`: The estimated drawing time as generated, in seconds
+/// - `secs_after`: The estimated drawing time of the optimised drawing, in seconds
+///
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct OptimisationReport {
+    pub strokes: usize,
+    pub merged: usize,
+    pub travel_before_mm: f64,
+    pub travel_after_mm: f64,
+    pub secs_before: u64,
+    pub secs_after: u64,
+}
+
+///
+/// A pen-down stroke of an instruction stream, kept as the original movements so it's redrawn exactly.
+///
+/// # Fields:
+/// - `segment`: The index of the pen plan segment the stroke belongs to
+/// - `start`: The (left, right) belt lengths at the start of the stroke, in steps
+/// - `end`: The (left, right) belt lengths at the end of the stroke, in steps
+/// - `moves`: The movements of the stroke
+/// - `start_pos`: The (x, y) page position of the start, in millimetres
+/// - `end_pos`: The (x, y) page position of the end, in millimetres
+///
+struct Stroke {
+    segment: usize,
+    start: (i64, i64),
+    end: (i64, i64),
+    moves: Vec<(i16, i16)>,
+    start_pos: (f64, f64),
+    end_pos: (f64, f64),
+}
+
+impl Stroke {
+
+    ///
+    /// Gets the (x, y) page positions the stroke is entered and left at, drawn forwards or reversed.
+    ///
+    fn ends(&self, reversed: bool) -> ((f64, f64), (f64, f64)) {
+        match reversed {
+            false => (self.start_pos, self.end_pos),
+            true => (self.end_pos, self.start_pos),
+        }
+    }
+}
+
+
+///
+/// Reorders the strokes of a drawing to cut down pen-up travel. The strokes of each pen segment are
+/// ordered with a nearest-neighbour search, reversing strokes where that's shorter, then improved with
+/// 2-opt. Strokes which end close to where the next one starts are merged. Pen-down movements are kept
+/// exactly, so only the travel between strokes changes.
+///
+/// # Parameters:
+/// - `phys_dim`: The physical dimensions of the machine
+/// - `bytes`: The instruction bytes
+/// - `init`: The (x, y) starting position of the drawing, in page millimetres
+/// - `plan`: The drawing's pen plan
+/// - `profile`: The machine profile the time estimates are made with
+///
+/// # Returns:
+/// - The optimised instruction bytes, its pen plan and a report, or None if the drawing has no strokes
+///
+pub fn optimise(phys_dim: &PhysicalDimensions, bytes: &[u8], init: (f64, f64), plan: &PenPlan, profile: &MachineProfile) -> Option<(Vec<u8>, PenPlan, OptimisationReport)> {
+    let strokes = split(phys_dim, bytes, init, plan);
+    if strokes.is_empty() {
+        return None;
+    }
+
+    let distance = |a: (f64, f64), b: (f64, f64)| (b.0 - a.0).hypot(b.1 - a.1);
+    let mut travel_before_mm = 0.;
+    let mut last = init;
+    for stroke in &strokes {
+        travel_before_mm += distance(last, stroke.start_pos);
+        last = stroke.end_pos;
+    }
+
+    let mut instructions = Vec::new();
+    let mut segments = Vec::new();
+    let mut position = motion::belt_steps(phys_dim, init.0, init.1);
+    let mut position_pos = init;
+    let mut travel_after_mm = 0.;
+    let mut merged = 0;
+
+    for (idx, segment) in plan.segments.iter().enumerate() {
+        let group: Vec<&Stroke> = strokes.iter().filter(|stroke| stroke.segment == idx).collect();
+        if group.is_empty() {
+            continue;
+        }
+        segments.push(Segment { pen: segment.pen, start: instructions.len() * INSTRUCTION_SIZE });
+
+        let mut order = nearest_neighbour(&group, position_pos);
+        two_opt(&group, &mut order, position_pos);
+
+        for (k, &(stroke_idx, reversed)) in order.iter().enumerate() {
+            let stroke = group[stroke_idx];
+            let (start, end) = if reversed { (stroke.end, stroke.start) } else { (stroke.start, stroke.end) };
+            let (start_pos, end_pos) = stroke.ends(reversed);
+
+            let gap = distance(position_pos, start_pos);
+            if k > 0 && gap <= MERGE_DISTANCE_MM {
+                instructions.extend(motion::steps_between(position, start));
+                merged += 1;
+            } else {
+                instructions.extend(travel(phys_dim, position, start));
+                instructions.push(Instruction::Pen(true));
+                travel_after_mm += gap;
+            }
+
+            match reversed {
+                false => instructions.extend(stroke.moves.iter().map(|&(left, right)| Instruction::Move(left, right))),
+                true => {
+                    for &(left, right) in stroke.moves.iter().rev() {
+                        instructions.extend(motion::steps_between((0, 0), (-(left as i64), -(right as i64))));
+                    }
+                },
+            }
+
+            position = end;
+            position_pos = end_pos;
+        }
+
+        // the pen is raised at the end of each segment, ready for the next pen
+        instructions.push(Instruction::Pen(false));
+    }
+
+    let optimised = motion::encode(&instructions);
+    let report = OptimisationReport {
+        strokes: strokes.len(),
+        merged,
+        travel_before_mm,
+        travel_after_mm,
+        secs_before: estimate::draw_time(bytes, profile).as_secs(),
+        secs_after: estimate::draw_time(&optimised, profile).as_secs(),
+    };
+
+    Some((optimised, PenPlan { pens: plan.pens.clone(), segments }, report))
+}
+
+///
+/// Splits an instruction stream into its pen-down strokes. Pen-up movements are dropped,
+/// and a stroke crossing into a new pen segment is split there.
+///
+/// # Parameters:
+/// - `phys_dim`: The physical dimensions of the machine
+/// - `bytes`: The instruction bytes
+/// - `init`: The (x, y) starting position of the drawing, in page millimetres
+/// - `plan`: The drawing's pen plan
+///
+/// # Returns:
+/// - The strokes, in the order they're drawn
+///
+fn split(phys_dim: &PhysicalDimensions, bytes: &[u8], init: (f64, f64), plan: &PenPlan) -> Vec<Stroke> {
+    let mut strokes = Vec::new();
+    let mut current: Option<Stroke> = None;
+    let mut position = motion::belt_steps(phys_dim, init.0, init.1);
+    let mut pen_down = false;
+    let mut segment = 0;
+
+    let new_stroke = |segment: usize, position: (i64, i64)| {
+        let pos = motion::page_position(phys_dim, position.0, position.1);
+        Stroke { segment, start: position, end: position, moves: Vec::new(), start_pos: pos, end_pos: pos }
+    };
+
+    for (i, instruction) in motion::decode(bytes).enumerate() {
+        while plan.segments.get(segment + 1).is_some_and(|next| next.start <= i * INSTRUCTION_SIZE) {
+            strokes.extend(current.take());
+            segment += 1;
+            if pen_down {
+                current = Some(new_stroke(segment, position));
+            }
+        }
+
+        match instruction {
+            Instruction::Pen(true) if !pen_down => {
+                pen_down = true;
+                current = Some(new_stroke(segment, position));
+            },
+            Instruction::Pen(true) => {},
+            Instruction::Pen(false) => {
+                pen_down = false;
+                strokes.extend(current.take());
+            },
+            Instruction::Move(left, right) => {
+                position = (position.0 + left as i64, position.1 + right as i64);
+                if let Some(stroke) = current.as_mut() {
+                    stroke.moves.push((left, right));
+                    stroke.end = position;
+                }
+            },
+        }
+    }
+    strokes.extend(current);
+
+    for stroke in strokes.iter_mut() {
+        stroke.end_pos = motion::page_position(phys_dim, stroke.end.0, stroke.end.1);
+    }
+    strokes
+}
+
+///
+/// Generates the instructions to raise the pen and travel between two sets of belt lengths.
+/// The travel is planned on the page, then corrected onto the exact belt lengths, so no steps are lost.
+///
+/// # Parameters:
+/// - `phys_dim`: The physical dimensions of the machine
+/// - `from`: The (left, right) starting belt lengths, in steps
+/// - `to`: The (left, right) target belt lengths, in steps
+///
+/// # Returns:
+/// - The travel instructions
+///
+fn travel(phys_dim: &PhysicalDimensions, from: (i64, i64), to: (i64, i64)) -> Vec<Instruction> {
+    let from_pos = motion::page_position(phys_dim, from.0, from.1);
+    let to_pos = motion::page_position(phys_dim, to.0, to.1);
+    let planned_from = motion::belt_steps(phys_dim, from_pos.0, from_pos.1);
+    let planned_to = motion::belt_steps(phys_dim, to_pos.0, to_pos.1);
+
+    let mut instructions = motion::travel(phys_dim, from_pos, to_pos);
+    let reached = (from.0 + planned_to.0 - planned_from.0, from.1 + planned_to.1 - planned_from.1);
+    instructions.extend(motion::steps_between(reached, to));
+    instructions
+}
+
+///
+/// Orders strokes by repeatedly drawing the stroke with the closest free end, entering it from that end.
+/// The stroke ends are bucketed into a grid, so each search only looks at nearby strokes.
+///
+/// # Parameters:
+/// - `strokes`: The strokes to order
+/// - `from`: The (x, y) page position the pen starts at, in millimetres
+///
+/// # Returns:
+/// - The order, as (stroke index, reversed) pairs
+///
+fn nearest_neighbour(strokes: &[&Stroke], from: (f64, f64)) -> Vec<(usize, bool)> {
+    let ends = strokes.iter().flat_map(|stroke| [stroke.start_pos, stroke.end_pos]).chain(std::iter::once(from));
+    let (min, max) = ends.fold(((f64::INFINITY, f64::INFINITY), (f64::NEG_INFINITY, f64::NEG_INFINITY)), |(min, max), (x, y)| {
+        ((min.0.min(x), min.1.min(y)), (max.0.max(x), max.1.max(y)))
+    });
+
+    // roughly one stroke per cell
+    let (width, height) = (max.0 - min.0, max.1 - min.1);
+    let cell = ((width * height) / strokes.len() as f64).sqrt().max(width.max(height) / strokes.len() as f64).max(1e-3);
+    let cols = (width / cell).floor() as usize + 1;
+    let rows = (height / cell).floor() as usize + 1;
+    let cell_of = |(x, y): (f64, f64)| (((x - min.0) / cell).floor().clamp(0., (cols - 1) as f64) as usize, ((y - min.1) / cell).floor().clamp(0., (rows - 1) as f64) as usize);
+
+    let mut grid: Vec<Vec<(usize, bool)>> = vec![Vec::new(); cols * rows];
+    for (idx, stroke) in strokes.iter().enumerate() {
+        let (cx, cy) = cell_of(stroke.start_pos);
+        grid[cy * cols + cx].push((idx, false));
+        let (cx, cy) = cell_of(stroke.end_pos);
+        grid[cy * cols + cx].push((idx, true));
+    }
+
+    let mut used = vec![false; strokes.len()];
+    let mut order = Vec::with_capacity(strokes.len());
+    let mut position = from;
+
+    for _ in 0..strokes.len() {
+        let (cx, cy) = cell_of(position);
+        let mut best: Option<(usize, bool, f64)> = None;
+
+        for ring in 0..=cols.max(rows) {
+            // every end in this ring is at least a ring, less a cell, away
+            if best.is_some_and(|(_, _, dist)| dist <= (ring as f64 - 1.) * cell) {
+                break;
+            }
+
+            for gy in cy.saturating_sub(ring)..=(cy + ring).min(rows - 1) {
+                for gx in cx.saturating_sub(ring)..=(cx + ring).min(cols - 1) {
+                    if gx.abs_diff(cx) != ring && gy.abs_diff(cy) != ring {
+                        continue;
+                    }
+
+                    let bucket = &mut grid[gy * cols + gx];
+                    bucket.retain(|(idx, _)| !used[*idx]);
+                    for &(idx, reversed) in bucket.iter() {
+                        let (entry, _) = strokes[idx].ends(reversed);
+                        let dist = (entry.0 - position.0).hypot(entry.1 - position.1);
+                        if best.is_none_or(|(_, _, best_dist)| dist < best_dist) {
+                            best = Some((idx, reversed, dist));
+                        }
+                    }
+                }
+            }
+        }
+
+        let Some((idx, reversed, _)) = best else { break; };
+        used[idx] = true;
+        order.push((idx, reversed));
+        position = strokes[idx].ends(reversed).1;
+    }
+
+    order
+}
+
+///
+/// Improves an order with 2-opt, reversing runs of strokes wherever that shortens the travel around them.
+/// Reversing a run also reverses the direction each of its strokes is drawn in.
+///
+/// # Parameters:
+/// - `strokes`: The strokes being ordered
+/// - `order`: The order to improve, as (stroke index, reversed) pairs
+/// - `from`: The (x, y) page position the pen starts at, in millimetres
+///
+fn two_opt(strokes: &[&Stroke], order: &mut [(usize, bool)], from: (f64, f64)) {
+    let distance = |a: (f64, f64), b: (f64, f64)| (b.0 - a.0).hypot(b.1 - a.1);
+    let entry = |&(idx, reversed): &(usize, bool)| strokes[idx].ends(reversed).0;
+    let exit = |&(idx, reversed): &(usize, bool)| strokes[idx].ends(reversed).1;
+
+    for _ in 0..TWO_OPT_PASSES {
+        let mut improved = false;
+
+        for i in 0..order.len() {
+            for j in (i + 1)..order.len().min(i + TWO_OPT_WINDOW) {
+                let before = if i == 0 { from } else { exit(&order[i - 1]) };
+                let after = order.get(j + 1).map(entry);
+
+                let current = distance(before, entry(&order[i])) + after.map_or(0., |after| distance(exit(&order[j]), after));
+                let reversed = distance(before, exit(&order[j])) + after.map_or(0., |after| distance(entry(&order[i]), after));
+
+                if reversed < current - 1e-6 {
+                    order[i..=j].reverse();
+                    for step in order[i..=j].iter_mut() {
+                        step.1 = !step.1;
+                    }
+                    improved = true;
+                }
+            }
+        }
+
+        if !improved {
+            break;
+        }
+    }
+}
+
+
+///
+/// Optimises a drawing before it's cached, if path optimisation is turned on in the app config,
+/// and caches the report for the preview.
+///
+/// # Parameters:
+/// - `app`: The Tauri app handle
+/// - `phys_dim`: The physical dimensions of the machine
+/// - `bytes`: The instruction bytes
+/// - `init`: The (x, y) starting position of the drawing, in page millimetres
+/// - `plan`: The drawing's pen plan
+///
+/// # Returns:
+/// - The instruction bytes and pen plan to use, optimised or not
+///
+pub(crate) fn apply(app: &tauri::AppHandle, phys_dim: &PhysicalDimensions, bytes: Vec<u8>, init: (f64, f64), plan: PenPlan) -> (Vec<u8>, PenPlan) {
+    let app_config = get_app_config_struct(app).ok();
+    let Ok(cache_dir) = tauri::Manager::path(app).app_cache_dir() else { return (bytes, plan); };
+
+    let optimised = match app_config.as_ref().is_some_and(|app_config| app_config.optimise_paths) {
+        true => optimise(phys_dim, &bytes, init, &plan, &estimate::machine_profile(app_config.as_ref(), &cache_dir)),
+        false => None,
+    };
+
+    write_report(&cache_dir, optimised.as_ref().map(|(_, _, report)| report));
+    match optimised {
+        Some((bytes, plan, _)) => (bytes, plan),
+        None => (bytes, plan),
+    }
+}
+
+///
+/// Caches the optimisation report of the previewed drawing, or clears it if the drawing wasn't optimised.
+///
+/// # Parameters:
+/// - `cache_dir`: The app cache directory
+/// - `report`: The report, or None
+///
+pub fn write_report(cache_dir: &Path, report: Option<&OptimisationReport>) {
+    let path = cache_dir.join(REPORT_FILE);
+    match report {
+        Some(report) => {
+            let _ = std::fs::create_dir_all(cache_dir);
+            if let Ok(handle) = File::create(path) {
+                let _ = serde_json::to_writer(handle, report);
+            }
+        },
+        None => { let _ = std::fs::remove_file(path); },
+    }
+}
+
+///
+/// Loads the optimisation report of the previewed drawing.
+///
+/// # Parameters:
+/// - `cache_dir`: The app cache directory
+///
+/// # Returns:
+/// - The report, or None if the drawing wasn't optimised
+///
+pub fn read_report(cache_dir: &Path) -> Option<OptimisationReport> {
+    let handle = File::open(cache_dir.join(REPORT_FILE)).ok()?;
+    serde_json::from_reader(BufReader::new(handle)).ok()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pens::Pen;
+
+    fn phys_dim() -> PhysicalDimensions {
+        PhysicalDimensions::new(754., (754. - 210.) / 1.98, 192., 210., 297.)
+    }
+
+    ///
+    /// Short strokes scattered over the page in a poor order, far enough apart that none are merged.
+    ///
+    fn scattered_strokes() -> Vec<Vec<(f64, f64)>> {
+        let mut seed: u64 = 7;
+        let mut next = move |range: f64| {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (seed >> 33) as f64 / (1u64 << 31) as f64 * range
+        };
+
+        (0..30).map(|i| {
+            let (x, y) = (10. + (i % 6) as f64 * 32., 10. + (i / 6) as f64 * 55.);
+            let (x, y) = (x + next(5.), y + next(5.));
+            vec![(x, y), (x + 8., y + next(10.)), (x + 4., y + 20.)]
+        }).rev().collect()
+    }
+
+    ///
+    /// Gets the pen-down moves of each stroke, ignoring the direction it's drawn in, sorted.
+    ///
+    fn drawn_moves(bytes: &[u8], init: (f64, f64), plan: &PenPlan) -> Vec<(usize, Vec<(i16, i16)>)> {
+        let mut drawn: Vec<(usize, Vec<(i16, i16)>)> = split(&phys_dim(), bytes, init, plan).into_iter().map(|stroke| {
+            let reversed: Vec<(i16, i16)> = stroke.moves.iter().rev().map(|&(left, right)| (-left, -right)).collect();
+            (stroke.segment, stroke.moves.clone().min(reversed))
+        }).collect();
+        drawn.sort();
+        drawn
+    }
+
+    fn two_pen_plan(bytes: &[u8], second_from_stroke: usize) -> PenPlan {
+        let pen_down = motion::decode(bytes).enumerate().filter(|(_, instruction)| *instruction == Instruction::Pen(true)).nth(second_from_stroke).unwrap().0;
+        PenPlan {
+            pens: vec![Pen { name: "Black".to_owned(), colour: "#000000".to_owned() }, Pen { name: "Red".to_owned(), colour: "#ff0000".to_owned() }],
+            segments: vec![Segment { pen: 0, start: 0 }, Segment { pen: 1, start: pen_down * INSTRUCTION_SIZE }],
+        }
+    }
+
+    fn travel_of(strokes: &[&Stroke], order: &[(usize, bool)], from: (f64, f64)) -> f64 {
+        let mut position = from;
+        let mut total = 0.;
+        for &(idx, reversed) in order {
+            let (entry, exit) = strokes[idx].ends(reversed);
+            total += (entry.0 - position.0).hypot(entry.1 - position.1);
+            position = exit;
+        }
+        total
+    }
+
+    #[test]
+    fn keeps_every_pen_down_move() {
+        let phys_dim = phys_dim();
+        let (bytes, init) = motion::draw_strokes(&phys_dim, &scattered_strokes());
+        let plan = PenPlan::single();
+
+        let (optimised, new_plan, report) = optimise(&phys_dim, &bytes, init, &plan, &MachineProfile::default()).unwrap();
+        assert_eq!(report.merged, 0);
+        assert_eq!(report.strokes, 30);
+        assert!(report.travel_after_mm <= report.travel_before_mm);
+        assert_eq!(drawn_moves(&optimised, init, &new_plan), drawn_moves(&bytes, init, &plan));
+    }
+
+    #[test]
+    fn ends_where_its_moves_add_up_to() {
+        let phys_dim = phys_dim();
+        let (bytes, init) = motion::draw_strokes(&phys_dim, &scattered_strokes());
+        let (optimised, new_plan, _) = optimise(&phys_dim, &bytes, init, &PenPlan::single(), &MachineProfile::default()).unwrap();
+
+        let mut position = motion::belt_steps(&phys_dim, init.0, init.1);
+        for instruction in motion::decode(&optimised) {
+            if let Instruction::Move(left, right) = instruction {
+                position = (position.0 + left as i64, position.1 + right as i64);
+            }
+        }
+
+        // travel lands exactly on each stroke, so the pen finishes where the last stroke ends
+        let original = split(&phys_dim, &bytes, init, &PenPlan::single());
+        let strokes = split(&phys_dim, &optimised, init, &new_plan);
+        for stroke in &strokes {
+            assert!(original.iter().any(|other| (other.start, other.end) == (stroke.start, stroke.end) || (other.end, other.start) == (stroke.start, stroke.end)));
+        }
+        assert_eq!(position, strokes.last().unwrap().end);
+        assert_eq!(motion::decode(&optimised).last(), Some(Instruction::Pen(false)));
+    }
+
+    #[test]
+    fn retraces_a_reversed_stroke() {
+        let phys_dim = phys_dim();
+        // the second stroke ends closer to the first than it starts, so it's drawn backwards
+        let strokes = vec![vec![(20., 20.), (30., 20.)], vec![(120., 150.), (60., 80.), (40., 40.)]];
+        let (bytes, init) = motion::draw_strokes(&phys_dim, &strokes);
+
+        let (optimised, _, _) = optimise(&phys_dim, &bytes, init, &PenPlan::single(), &MachineProfile::default()).unwrap();
+
+        // the pen is lowered without moving, so each stroke repeats its first point
+        let points = |bytes: &[u8]| motion::strokes(&phys_dim, bytes, init).into_iter().map(|mut stroke| { stroke.dedup(); stroke }).collect::<Vec<_>>();
+        let before = points(&bytes);
+        let after = points(&optimised);
+        assert_eq!(after.len(), 2);
+        assert_eq!(after[0], before[0]);
+
+        let retraced: Vec<(f64, f64)> = before[1].iter().rev().copied().collect();
+        assert_eq!(after[1].len(), retraced.len());
+        for (a, b) in after[1].iter().zip(&retraced) {
+            assert!((a.0 - b.0).abs() < 1e-9 && (a.1 - b.1).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn two_opt_never_adds_travel() {
+        let phys_dim = phys_dim();
+        let (bytes, init) = motion::draw_strokes(&phys_dim, &scattered_strokes());
+        let strokes = split(&phys_dim, &bytes, init, &PenPlan::single());
+        let group: Vec<&Stroke> = strokes.iter().collect();
+
+        let as_drawn: Vec<(usize, bool)> = (0..group.len()).map(|idx| (idx, false)).collect();
+        let alternating: Vec<(usize, bool)> = (0..group.len()).map(|idx| (idx, idx % 2 == 0)).collect();
+        for start in [as_drawn, alternating, nearest_neighbour(&group, init)] {
+            let mut order = start.clone();
+            two_opt(&group, &mut order, init);
+
+            assert!(travel_of(&group, &order, init) <= travel_of(&group, &start, init) + 1e-9);
+            let mut visited: Vec<usize> = order.iter().map(|(idx, _)| *idx).collect();
+            visited.sort();
+            assert_eq!(visited, (0..group.len()).collect::<Vec<usize>>());
+        }
+    }
+
+    #[test]
+    fn starts_segments_on_instruction_boundaries() {
+        let phys_dim = phys_dim();
+        let (bytes, init) = motion::draw_strokes(&phys_dim, &scattered_strokes());
+        let plan = two_pen_plan(&bytes, 12);
+
+        let (optimised, new_plan, _) = optimise(&phys_dim, &bytes, init, &plan, &MachineProfile::default()).unwrap();
+        assert_eq!(new_plan.segments.iter().map(|segment| segment.pen).collect::<Vec<usize>>(), vec![0, 1]);
+        assert_eq!(new_plan.segments[0].start, 0);
+
+        let second = new_plan.segments[1].start;
+        assert_eq!(second % INSTRUCTION_SIZE, 0);
+        assert!(second < optimised.len());
+        // the first pen is raised before the second segment starts
+        assert_eq!(motion::decode(&optimised[second - INSTRUCTION_SIZE..second]).next(), Some(Instruction::Pen(false)));
+        assert_eq!(drawn_moves(&optimised, init, &new_plan), drawn_moves(&bytes, init, &plan));
+    }
+}
@@ -0,0 +1,250 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+use serde::{Serialize, Deserialize};
+use bbcore::hardware::PhysicalDimensions;
+use bbcore::instruction::InstructionSet;
+use bbcore::preview::generate_preview;
+use image::{Rgba, RgbaImage};
+
+use crate::motion;
+
+
+///
+/// A pen which can be loaded into the machine.
+///
+/// # Fields:
+/// - `name`: A name for the pen, shown to the operator when swapping pens
+/// - `colour`: The colour of the pen as a hex string, such as `#1a1a1a`
+///
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Pen {
+    pub name: String,
+    pub colour: String,
+}
+
+///
+/// A contiguous run of the instruction stream which is drawn with a single pen.
+///
+/// # Fields:
+/// - `pen`: The index of the pen in the plan's pen list
+/// - `start`: The byte index in the instruction stream where the segment starts
+///
+#[derive(Serialize, Deserialize, Clone, Copy)]
+pub struct Segment {
+    pub pen: usize,
+    pub start: usize,
+}
+
+///
+/// The pens used by a drawing, and which pen draws each part of the instruction stream.
+/// It is cached alongside the instructions, so a job can stop at each pen boundary.
+///
+/// # Fields:
+/// - `pens`: The pens used by the drawing
+/// - `segments`: The segments of the instruction stream, ordered by start index
+///
+#[derive(Serialize, Deserialize, Clone)]
+pub struct PenPlan {
+    pub pens: Vec<Pen>,
+    pub segments: Vec<Segment>,
+}
+
+impl PenPlan {
+
+    ///
+    /// Creates a plan where the whole drawing is drawn with a single black pen.
+    ///
+    pub fn single() -> PenPlan {
+        PenPlan {
+            pens: vec![Pen { name: "Pen 1".to_owned(), colour: "#000000".to_owned() }],
+            segments: vec![Segment { pen: 0, start: 0 }],
+        }
+    }
+
+    ///
+    /// Gets the byte range of a segment.
+    ///
+    /// # Parameters:
+    /// - `idx`: The index of the segment
+    /// - `total_bytes`: The length of the instruction stream
+    ///
+    /// # Returns:
+    /// - The (start, end) byte indices of the segment
+    ///
+    pub fn segment_range(&self, idx: usize, total_bytes: usize) -> (usize, usize) {
+        let end = match self.segments.get(idx + 1) {
+            Some(next) => next.start,
+            None => total_bytes,
+        };
+        (self.segments[idx].start, end)
+    }
+
+    ///
+    /// Gets the pen used by a segment, falling back to a black pen if the index is invalid.
+    ///
+    /// # Parameters:
+    /// - `idx`: The index of the segment
+    ///
+    /// # Returns:
+    /// - The segment's pen
+    ///
+    pub fn segment_pen(&self, idx: usize) -> Pen {
+        match self.pens.get(self.segments[idx].pen) {
+            Some(pen) => pen.clone(),
+            None => Pen { name: format!("Pen {}", self.segments[idx].pen + 1), colour: "#000000".to_owned() },
+        }
+    }
+
+    ///
+    /// Creates the plan for the rest of a drawing, resumed part way through.
+    /// The pen in use at the resume point carries on without a pen change.
+    ///
+    /// # Parameters:
+    /// - `offset`: The byte index the drawing resumes from
+    /// - `prefix_len`: The number of bytes inserted before the remaining instructions
+    ///
+    /// # Returns:
+    /// - The pen plan of the remaining instructions
+    ///
+    pub fn resume_from(&self, offset: usize, prefix_len: usize) -> PenPlan {
+        let current = self.segments.iter().rposition(|segment| segment.start < offset).unwrap_or(0);
+
+        let mut segments = vec![Segment { pen: self.segments[current].pen, start: 0 }];
+        segments.extend(self.segments[current + 1..].iter().map(|segment| Segment { pen: segment.pen, start: segment.start - offset + prefix_len }));
+
+        PenPlan { pens: self.pens.clone(), segments }
+    }
+}
+
+
+///
+/// Saves a pen plan to the cache directory.
+///
+/// # Parameters:
+/// - `cache_dir`: The app cache directory
+/// - `plan`: The pen plan to save
+///
+pub fn write_plan(cache_dir: &Path, plan: &PenPlan) {
+    if let Ok(handle) = File::create(cache_dir.join("pens.json")) {
+        let _ = serde_json::to_writer(handle, plan);
+    }
+}
+
+///
+/// Loads the cached pen plan, falling back to a single pen if none has been cached.
+///
+/// # Parameters:
+/// - `cache_dir`: The app cache directory
+///
+/// # Returns:
+/// - The cached pen plan
+///
+pub fn read_plan(cache_dir: &Path) -> PenPlan {
+    let plan = File::open(cache_dir.join("pens.json")).ok().and_then(|handle| serde_json::from_reader::<_, PenPlan>(BufReader::new(handle)).ok());
+    match plan {
+        Some(val) if !val.segments.is_empty() => val,
+        _ => PenPlan::single(),
+    }
+}
+
+
+///
+/// Parses a hex colour string.
+///
+/// # Parameters:
+/// - `colour`: The colour, as `#rrggbb`
+///
+/// # Returns:
+/// - The colour as RGB, or black if the string is invalid
+///
+fn parse_colour(colour: &str) -> [u8; 3] {
+    let hex = colour.trim_start_matches('#');
+    if hex.len() != 6 || !hex.is_ascii() {
+        return [0, 0, 0];
+    }
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).unwrap_or(0);
+    [channel(0), channel(2), channel(4)]
+}
+
+///
+/// Renders a preview where each segment is drawn in its pen's colour.
+/// Each segment is previewed separately, and the drawn pixels are tinted and layered onto one image.
+///
+/// # Parameters:
+/// - `phys_dim`: The physical dimensions of the machine
+/// - `instruction_set`: The drawing's instruction set
+/// - `plan`: The pen plan of the drawing
+/// - `preview_path`: The path to save the preview image to
+///
+/// # Returns:
+/// - None if the preview was rendered, otherwise an error string
+///
+pub fn generate_coloured_preview(phys_dim: &PhysicalDimensions, instruction_set: &InstructionSet, plan: &PenPlan, preview_path: &Path) -> Option<String> {
+    let bytes = instruction_set.get_binary();
+    let init = instruction_set.get_init();
+    let mut canvas: Option<RgbaImage> = None;
+
+    for idx in 0..plan.segments.len() {
+        let (start, end) = plan.segment_range(idx, bytes.len());
+        if start >= end {
+            continue;
+        }
+
+        // the segment starts where the previous segment left the pen
+        let (seg_x, seg_y, _) = motion::end_position(phys_dim, &bytes[..start], (init.0, init.1));
+        let seg_init = (seg_x, seg_y);
+        let seg_set = match InstructionSet::new(bytes[start..end].to_vec(), seg_init.0, seg_init.1) {
+            Ok(val) => val,
+            Err(err) => { return Some(err.to_string()); }
+        };
+
+        let seg_path = preview_path.with_file_name(format!("preview-pen-{}.png", idx));
+        let seg_str = match seg_path.to_str() {
+            Some(val) => val,
+            None => { return Some(non_utf8_path(&seg_path)); }
+        };
+        if let Some(err) = generate_preview(seg_init, phys_dim, &seg_set, seg_str) {
+            return Some(err.to_string());
+        }
+        let seg_image = match image::open(&seg_path) {
+            Ok(val) => val.to_rgba8(),
+            Err(err) => { return Some(err.to_string()); }
+        };
+        let _ = std::fs::remove_file(&seg_path);
+
+        let canvas = canvas.get_or_insert_with(|| RgbaImage::from_pixel(seg_image.width(), seg_image.height(), Rgba([255, 255, 255, 255])));
+        let [r, g, b] = parse_colour(plan.segment_pen(idx).colour.as_str());
+
+        // darkness of the segment pixel decides how much of the pen colour covers the canvas
+        for (x, y, pixel) in seg_image.enumerate_pixels() {
+            let coverage = 1. - (pixel[0] as f64 + pixel[1] as f64 + pixel[2] as f64) / (3. * 255.);
+            if coverage <= 0.05 || x >= canvas.width() || y >= canvas.height() {
+                continue;
+            }
+            let under = *canvas.get_pixel(x, y);
+            let blend = |pen: u8, under: u8| (pen as f64 * coverage + under as f64 * (1. - coverage)).round() as u8;
+            canvas.put_pixel(x, y, Rgba([blend(r, under[0]), blend(g, under[1]), blend(b, under[2]), 255]));
+        }
+    }
+
+    match (canvas, preview_path.to_str()) {
+        (Some(canvas), _) => canvas.save(preview_path).err().map(|err| err.to_string()),
+        (None, Some(preview_str)) => generate_preview((init.0, init.1), phys_dim, instruction_set, preview_str).map(|err| err.to_string()),
+        (None, None) => Some(non_utf8_path(preview_path)),
+    }
+}
+
+///
+/// Explains that a preview can't be rendered to a path, as bbcore only takes UTF-8 paths.
+///
+/// # Parameters:
+/// - `path`: The path which isn't valid UTF-8
+///
+/// # Returns:
+/// - The error string
+///
+fn non_utf8_path(path: &Path) -> String {
+    format!("The preview can't be saved to {}, as the path isn't valid UTF-8.", path.display())
+}
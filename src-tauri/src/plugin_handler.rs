@@ -1,5 +1,7 @@
 use bbcore::plugin;
 
+use crate::error::AppError;
+
 
 /// 
 /// Gets the parameters of a plugin, give the plugin file path.
@@ -10,12 +12,12 @@ use bbcore::plugin;
 ///
 /// # Returns:
 /// - The plugins parameters as stringified JSON 
-/// - A string explaning why the function failed
+/// - An error explaning why the function failed
 ///
 #[tauri::command(async)]
-pub async fn get_parameters(_app: tauri::AppHandle, path: &str) -> Result<String, String> {
+pub async fn get_parameters(_app: tauri::AppHandle, path: &str) -> Result<String, AppError> {
     match plugin::get_parameter_string(path) {
         Ok(val) => Ok(val),
-        Err(err) => Err(format!("Couldn't load plugin parameters: {}", err.to_string()))
+        Err(err) => Err(AppError::InvalidParameters(format!("Couldn't load plugin parameters: {}", err)))
     }
 }
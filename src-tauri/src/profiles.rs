@@ -0,0 +1,264 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufReader, Read, Write};
+use std::path::Path;
+
+use serde::{Serialize, Deserialize};
+
+use crate::file::{read_app_config, AppConfig};
+use crate::error::AppError;
+
+
+///
+/// The name given to the profile imported from a settings file saved before profiles existed.
+///
+pub const DEFAULT_PROFILE: &str = "Default";
+
+///
+/// The file in the app cache directory holding the profile store.
+///
+const PROFILES_FILE: &str = "profiles.json";
+
+///
+/// The settings file saved before profiles existed, imported into the store the first time it's read.
+///
+const LEGACY_CONFIG_FILE: &str = "app_config.json";
+
+
+///
+/// The named machine profiles, each holding the address, geometry, paper size and speed of one machine.
+///
+/// # Fields:
+/// - `selected`: The name of the profile used for previewing and plotting
+/// - `profiles`: The profiles, by name
+///
+#[derive(Serialize, Deserialize, Default)]
+pub struct ProfileStore {
+    pub selected: Option<String>,
+    pub profiles: BTreeMap<String, AppConfig>,
+}
+
+///
+/// The profile names sent to the UI.
+///
+/// # Fields:
+/// - `selected`: The name of the selected profile, if there is one
+/// - `profiles`: The names of every profile, in alphabetical order
+///
+#[derive(Serialize)]
+pub struct ProfileList {
+    pub selected: Option<String>,
+    pub profiles: Vec<String>,
+}
+
+impl ProfileStore {
+
+    ///
+    /// Reads the profile store. If it hasn't been created yet, settings saved before
+    /// profiles existed are imported as the `Default` profile.
+    ///
+    /// # Parameters:
+    /// - `cache_dir`: The app cache directory
+    ///
+    /// # Returns:
+    /// - The profile store, empty if nothing has been configured
+    /// - An error if the store is corrupt
+    ///
+    pub fn read(cache_dir: &Path) -> Result<ProfileStore, AppError> {
+        let file_handle = match File::open(cache_dir.join(PROFILES_FILE)) {
+            Ok(handle) => handle,
+            Err(_) => { return Ok(ProfileStore::import_legacy(cache_dir)); }
+        };
+
+        let mut contents = String::new();
+        BufReader::new(file_handle).read_to_string(&mut contents)?;
+
+        match serde_json::from_str::<ProfileStore>(contents.as_str()) {
+            Ok(val) => Ok(val),
+            Err(err) => Err(AppError::NotConfigured(format!("The machine profiles are invalid: {}", err))),
+        }
+    }
+
+    ///
+    /// Writes the profile store to the disk.
+    ///
+    /// # Parameters:
+    /// - `cache_dir`: The app cache directory
+    ///
+    /// # Returns:
+    /// - Void if the store was written
+    /// - An error explaining why it couldn't be written
+    ///
+    pub fn write(&self, cache_dir: &Path) -> Result<(), AppError> {
+        let contents = serde_json::to_string(self).map_err(|err| AppError::Io(err.to_string()))?;
+
+        std::fs::create_dir_all(cache_dir)?;
+        let mut file_handle = File::create(cache_dir.join(PROFILES_FILE))?;
+        file_handle.write_all(contents.as_bytes())?;
+
+        Ok(())
+    }
+
+    ///
+    /// Gets the selected profile.
+    ///
+    /// # Returns:
+    /// - The app config of the selected profile
+    /// - An error if no profile is selected
+    ///
+    pub fn selected_config(&self) -> Result<&AppConfig, AppError> {
+        self.selected.as_ref()
+            .and_then(|name| self.profiles.get(name))
+            .ok_or_else(AppError::not_configured)
+    }
+
+    ///
+    /// Gets a profile by name.
+    ///
+    /// # Parameters:
+    /// - `name`: The profile name
+    ///
+    /// # Returns:
+    /// - The app config of the profile
+    /// - An error if there's no such profile
+    ///
+    pub fn get(&self, name: &str) -> Result<&AppConfig, AppError> {
+        self.profiles.get(name).ok_or_else(|| AppError::NotConfigured(format!("There's no machine profile named \"{}\".", name)))
+    }
+
+    ///
+    /// Gets the profile names to show in the UI.
+    ///
+    pub fn list(&self) -> ProfileList {
+        ProfileList { selected: self.selected.clone(), profiles: self.profiles.keys().cloned().collect() }
+    }
+
+    ///
+    /// Builds a store from the settings file saved before profiles existed, if there is one.
+    ///
+    fn import_legacy(cache_dir: &Path) -> ProfileStore {
+        match read_app_config(&cache_dir.join(LEGACY_CONFIG_FILE)) {
+            Ok(app_config) => ProfileStore {
+                selected: Some(DEFAULT_PROFILE.to_owned()),
+                profiles: BTreeMap::from([(DEFAULT_PROFILE.to_owned(), app_config)]),
+            },
+            Err(_) => ProfileStore::default(),
+        }
+    }
+}
+
+
+///
+/// Gets the app config of a machine profile.
+///
+/// # Parameters:
+/// - `app`: The Tauri app handle
+/// - `machine_id`: The ID of the machine, the name of its profile
+///
+/// # Returns:
+/// - The app config of the profile
+/// - An error if there's no such profile
+///
+pub fn machine_config(app: &tauri::AppHandle, machine_id: &str) -> Result<AppConfig, AppError> {
+    let cache_dir = tauri::Manager::path(app).app_cache_dir().map_err(|_| AppError::not_configured())?;
+    ProfileStore::read(&cache_dir)?.get(machine_id).cloned()
+}
+
+///
+/// A Tauri command to list the machine profiles.
+///
+/// # Parameters:
+/// - `app`: Injected dependency from Tauri
+///
+/// # Returns:
+/// - The profile names, and the name of the selected profile
+/// - An error if the profiles couldn't be read
+///
+#[tauri::command(async)]
+pub fn list_profiles(app: tauri::AppHandle) -> Result<ProfileList, AppError> {
+    let cache_dir = tauri::Manager::path(&app).app_cache_dir().map_err(|err| AppError::Io(err.to_string()))?;
+    Ok(ProfileStore::read(&cache_dir)?.list())
+}
+
+///
+/// A Tauri command to create a machine profile. The first profile created is selected.
+///
+/// # Parameters:
+/// - `app`: Injected dependency from Tauri
+/// - `name`: The name of the new profile
+/// - `stringified_config`: The profile's settings, as app config JSON
+///
+/// # Returns:
+/// - Void if the profile was created
+/// - An error if the name is taken or the settings are invalid
+///
+#[tauri::command(async)]
+pub fn create_profile(app: tauri::AppHandle, name: &str, stringified_config: &str) -> Result<(), AppError> {
+    let cache_dir = tauri::Manager::path(&app).app_cache_dir().map_err(|err| AppError::Io(err.to_string()))?;
+    let mut store = ProfileStore::read(&cache_dir)?;
+
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(AppError::InvalidParameters("The profile needs a name.".to_owned()));
+    }
+    if store.profiles.contains_key(name) {
+        return Err(AppError::InvalidParameters(format!("There's already a machine profile named \"{}\".", name)));
+    }
+
+    let app_config = match serde_json::from_str::<AppConfig>(stringified_config) {
+        Ok(val) => val,
+        Err(err) => { return Err(AppError::InvalidParameters(format!("Invalid print settings: {}", err))); }
+    };
+
+    store.profiles.insert(name.to_owned(), app_config);
+    if store.selected.is_none() {
+        store.selected = Some(name.to_owned());
+    }
+    store.write(&cache_dir)
+}
+
+///
+/// A Tauri command to select the machine profile used for previewing and plotting.
+///
+/// # Parameters:
+/// - `app`: Injected dependency from Tauri
+/// - `name`: The profile name
+///
+/// # Returns:
+/// - Void if the profile was selected
+/// - An error if there's no such profile
+///
+#[tauri::command(async)]
+pub fn switch_profile(app: tauri::AppHandle, name: &str) -> Result<(), AppError> {
+    let cache_dir = tauri::Manager::path(&app).app_cache_dir().map_err(|err| AppError::Io(err.to_string()))?;
+    let mut store = ProfileStore::read(&cache_dir)?;
+
+    store.get(name)?;
+    store.selected = Some(name.to_owned());
+    store.write(&cache_dir)
+}
+
+///
+/// A Tauri command to delete a machine profile. If it was selected, the first remaining profile is selected.
+///
+/// # Parameters:
+/// - `app`: Injected dependency from Tauri
+/// - `name`: The profile name
+///
+/// # Returns:
+/// - Void if the profile was deleted
+/// - An error if there's no such profile
+///
+#[tauri::command(async)]
+pub fn delete_profile(app: tauri::AppHandle, name: &str) -> Result<(), AppError> {
+    let cache_dir = tauri::Manager::path(&app).app_cache_dir().map_err(|err| AppError::Io(err.to_string()))?;
+    let mut store = ProfileStore::read(&cache_dir)?;
+
+    if store.profiles.remove(name).is_none() {
+        return Err(AppError::NotConfigured(format!("There's no machine profile named \"{}\".", name)));
+    }
+    if store.selected.as_deref() == Some(name) {
+        store.selected = store.profiles.keys().next().cloned();
+    }
+    store.write(&cache_dir)
+}
@@ -0,0 +1,194 @@
+use serde::{Serialize, Deserialize};
+use serde_json::Value;
+use tauri::Emitter;
+
+
+///
+/// The version of the progress event schema, sent with every event.
+/// It is increased whenever an event or field is changed or removed, but not when one is added.
+///
+pub const SCHEMA_VERSION: u32 = 1;
+
+///
+/// The channel progress events are emitted through.
+///
+pub const CHANNEL: &str = "firm-prog";
+
+///
+/// The channel queue events are emitted through.
+///
+pub const QUEUE_CHANNEL: &str = "queue-prog";
+
+
+///
+/// An update on the progress of a drawing, emitted through the `firm-prog` channel.
+/// It serializes with an `event` tag, such as `{ "event": "drawing", "secs_remaining": 60, "ins_pos": 4096 }`.
+///
+/// # Variants:
+/// - `PopulateNetwork`: The address of the machine being connected to
+/// - `PopulateDraw`: The number of instruction bytes in the drawing, and the page size in millimetres
+/// - `PopulateMachine`: The configuration the machine reported when it accepted the connection
+/// - `Connection`: The machine accepted the connection
+/// - `Drawing`: The estimated seconds remaining, and the byte index the machine has reached
+/// - `Estimate`: The seconds elapsed and remaining, and the estimated finish time as a unix timestamp
+/// - `PenPosition`: The estimated pen position in page millimetres, and the strokes drawn since the last position
+/// - `Pause`: The machine was paused or resumed
+/// - `PenChange`: The drawing is waiting for the operator to load a pen
+/// - `Reconnecting`: The connection was lost, and the attempt number, the wait before it, and the byte index to resume from
+/// - `Reconnected`: The connection was restored, and the byte index the drawing resumed from
+/// - `DrawingFinished`: The machine finished the drawing
+/// - `Shutdown`: The drawing was stopped or cancelled
+///
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum ProgressEvent {
+    PopulateNetwork { address: String },
+    PopulateDraw { total_bytes: usize, page_width: f64, page_height: f64 },
+    PopulateMachine { instruction_buffer_size: u64, max_motor_speed: u64, min_pulse_width: u64, protocol_version: u64 },
+    Connection { message: String },
+    Drawing { secs_remaining: u64, ins_pos: usize },
+    Estimate { secs_elapsed: u64, secs_remaining: u64, eta: u64 },
+    PenPosition { ins_pos: usize, x: f64, y: f64, pen_down: bool, drawn: Vec<Vec<(f64, f64)>> },
+    Pause { is_paused: bool },
+    PenChange { pen: usize, name: String, colour: String },
+    Reconnecting { attempt: u32, retry_in_ms: u64, ins_pos: usize },
+    Reconnected { ins_pos: usize },
+    DrawingFinished,
+    Shutdown,
+}
+
+///
+/// A progress event, as emitted, along with the schema version and the machine it refers to.
+///
+/// # Fields:
+/// - `version`: The schema version, see `SCHEMA_VERSION`
+/// - `machine`: The ID of the machine the event refers to, left out if there's only one
+/// - `event`: The progress event, flattened into the message
+///
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ProgressMessage {
+    pub version: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub machine: Option<String>,
+    #[serde(flatten)]
+    pub event: ProgressEvent,
+}
+
+///
+/// An update on the job queue, emitted through the `queue-prog` channel.
+/// It serializes with an `event` tag, such as `{ "event": "job_started", "id": 3, "name": "Drawing 3" }`.
+///
+/// # Variants:
+/// - `JobAdded`: A job was added to the queue
+/// - `JobRemoved`: A job was removed from the queue
+/// - `PaperChange`: The queue is waiting for the operator to change the paper for a job
+/// - `JobStarted`: A job started drawing
+/// - `JobFinished`: A job finished drawing
+/// - `QueueFinished`: The queue stopped running
+///
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum QueueEvent {
+    JobAdded { id: u64 },
+    JobRemoved { id: u64 },
+    PaperChange { id: u64, name: String },
+    JobStarted { id: u64, name: String },
+    JobFinished { id: u64 },
+    QueueFinished,
+}
+
+///
+/// A queue event, as emitted, along with the schema version.
+///
+/// # Fields:
+/// - `version`: The schema version, see `SCHEMA_VERSION`
+/// - `event`: The queue event, flattened into the message
+///
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct QueueMessage {
+    pub version: u32,
+    #[serde(flatten)]
+    pub event: QueueEvent,
+}
+
+impl ProgressEvent {
+
+    ///
+    /// Parses a message from one of the callbacks of `bbcore::client::state::ClientState`.
+    /// Numbers may be sent as strings or numbers, and flags as "1" / "0" or booleans.
+    ///
+    /// # Parameters:
+    /// - `msg`: The JSON message
+    ///
+    /// # Returns:
+    /// - The progress event, or None if the message isn't recognised
+    ///
+    pub fn from_bbcore(msg: &str) -> Option<ProgressEvent> {
+        let value: Value = serde_json::from_str(msg).ok()?;
+
+        match value["event"].as_str()? {
+            "drawing" => Some(ProgressEvent::Drawing { secs_remaining: number(&value["secs_remaining"])? as u64, ins_pos: number(&value["ins_pos"])? as usize }),
+            "pause" => Some(ProgressEvent::Pause { is_paused: flag(&value["is_paused"])? }),
+            "drawing_finished" => Some(ProgressEvent::DrawingFinished),
+            "shutdown" => Some(ProgressEvent::Shutdown),
+            "connection" => Some(ProgressEvent::Connection { message: value["message"].as_str().unwrap_or_default().to_owned() }),
+            _ => None,
+        }
+    }
+
+    ///
+    /// Wraps the event in a message with the current schema version.
+    ///
+    /// # Parameters:
+    /// - `machine`: The ID of the machine the event refers to, if any
+    ///
+    pub fn into_message(self, machine: Option<&str>) -> ProgressMessage {
+        ProgressMessage { version: SCHEMA_VERSION, machine: machine.map(str::to_owned), event: self }
+    }
+
+    ///
+    /// Emits the event through the `firm-prog` channel, tagged with its machine.
+    ///
+    /// # Parameters:
+    /// - `app`: The Tauri app handle
+    /// - `machine`: The ID of the machine the event refers to
+    ///
+    pub fn emit(self, app: &tauri::AppHandle, machine: &str) {
+        let _ = app.emit(CHANNEL, self.into_message(Some(machine)));
+    }
+}
+
+impl QueueEvent {
+
+    ///
+    /// Emits the event through the `queue-prog` channel.
+    ///
+    /// # Parameters:
+    /// - `app`: The Tauri app handle
+    ///
+    pub fn emit(self, app: &tauri::AppHandle) {
+        let _ = app.emit(QUEUE_CHANNEL, QueueMessage { version: SCHEMA_VERSION, event: self });
+    }
+}
+
+
+///
+/// Reads a number which may have been sent as a string.
+///
+fn number(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(num) => num.as_f64(),
+        Value::String(str) => str.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+///
+/// Reads a flag which may have been sent as a number, string or boolean.
+///
+fn flag(value: &Value) -> Option<bool> {
+    match value {
+        Value::Bool(val) => Some(*val),
+        _ => number(value).map(|num| num != 0.),
+    }
+}
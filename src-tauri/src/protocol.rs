@@ -0,0 +1,181 @@
+//! The wire protocol of the Blot Bot firmware, as the simulated machine in `mock_firmware` speaks it,
+//! over TCP or a serial port. The app itself talks to machines through bbcore's client (see `wire`),
+//! so this has to follow bbcore's wire format. `tests/firmware.rs` drives bbcore's client against
+//! the simulated machine, and fails if the two disagree.
+//!
+//! On connection, the firmware sends a handshake describing itself. The client then
+//! streams instruction buffers, each no larger than the firmware's buffer size, sending
+//! the next buffer whenever the firmware requests one. An empty buffer marks the end of the drawing.
+//!
+//! Handshake (firmware to client):
+//! - `u8` protocol version
+//! - `u32` instruction buffer size, in bytes
+//! - `u32` maximum motor speed, in steps per second
+//! - `u32` minimum pulse width, in microseconds
+//!
+//! Client to firmware, prefixed by an opcode byte:
+//! - `OP_BUFFER`, `u32` length, then the instruction bytes
+//! - `OP_PEN_LIFT` or `OP_MOTORS` (manual controls), then an `i16` value
+//! - `OP_PAUSE`, then a `u8` which is 1 to pause or 0 to resume
+//! - `OP_STOP`
+//!
+//! Firmware to client, a single status byte:
+//! - `ST_NEED_BUFFER` when the firmware is ready for the next buffer
+//! - `ST_FINISHED` once the end of the drawing has been executed
+//!
+//! Discovery (UDP, on `DISCOVERY_PORT`):
+//! - The client broadcasts `DISCOVERY_PROBE`
+//! - Each machine replies with `DISCOVERY_REPLY`, its `u16` TCP port, then its handshake
+//!
+//! All integers are big-endian.
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+
+///
+/// The newest protocol version this app speaks.
+///
+pub const PROTOCOL_VERSION: u8 = 1;
+
+///
+/// The oldest protocol version this app speaks, see `codec`.
+///
+pub const MIN_PROTOCOL_VERSION: u8 = 1;
+
+///
+/// Sends an instruction buffer, followed by its `u32` length and the instruction bytes.
+///
+pub const OP_BUFFER: u8 = 0x01;
+
+///
+/// Raises or lowers the pen by hand, followed by an `i16` value.
+///
+pub const OP_PEN_LIFT: u8 = 0x02;
+
+///
+/// Enables or disables the motors by hand, followed by an `i16` value.
+///
+pub const OP_MOTORS: u8 = 0x03;
+
+///
+/// Pauses or resumes the drawing, followed by a `u8` which is 1 to pause or 0 to resume.
+///
+pub const OP_PAUSE: u8 = 0x04;
+
+///
+/// Stops the drawing.
+///
+pub const OP_STOP: u8 = 0x05;
+
+///
+/// Sent by the firmware when it is ready for the next buffer.
+///
+pub const ST_NEED_BUFFER: u8 = 0x01;
+
+///
+/// Sent by the firmware once the end of the drawing has been executed.
+///
+pub const ST_FINISHED: u8 = 0x02;
+
+///
+/// The UDP port machines listen on for discovery probes.
+///
+pub const DISCOVERY_PORT: u16 = 41337;
+
+///
+/// The datagram the client broadcasts to find machines.
+///
+pub const DISCOVERY_PROBE: &[u8] = b"BLOTBOT?";
+
+///
+/// The prefix of a machine's reply to a discovery probe.
+///
+pub const DISCOVERY_REPLY: &[u8] = b"BLOTBOT!";
+
+
+///
+/// The configuration a machine reports in its handshake.
+///
+/// # Fields:
+/// - `protocol_version`: The firmware's protocol version
+/// - `instruction_buffer_size`: The size of the firmware's instruction buffer, in bytes
+/// - `max_motor_speed`: The maximum motor speed, in steps per second
+/// - `min_pulse_width`: The minimum step pulse width, in microseconds
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Handshake {
+    pub protocol_version: u8,
+    pub instruction_buffer_size: u32,
+    pub max_motor_speed: u32,
+    pub min_pulse_width: u32,
+}
+
+impl Handshake {
+
+    ///
+    /// The length of an encoded handshake, in bytes.
+    ///
+    pub const SIZE: usize = 13;
+
+    ///
+    /// Encodes the handshake.
+    ///
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(Handshake::SIZE);
+        bytes.push(self.protocol_version);
+        bytes.extend_from_slice(&self.instruction_buffer_size.to_be_bytes());
+        bytes.extend_from_slice(&self.max_motor_speed.to_be_bytes());
+        bytes.extend_from_slice(&self.min_pulse_width.to_be_bytes());
+        bytes
+    }
+
+    ///
+    /// Decodes a handshake.
+    ///
+    /// # Parameters:
+    /// - `bytes`: The encoded handshake
+    ///
+    /// # Returns:
+    /// - The handshake, or None if there are too few bytes
+    ///
+    pub fn from_bytes(bytes: &[u8]) -> Option<Handshake> {
+        let word = |idx: usize| bytes.get(idx..idx + 4).map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]));
+
+        Some(Handshake {
+            protocol_version: *bytes.first()?,
+            instruction_buffer_size: word(1)?,
+            max_motor_speed: word(5)?,
+            min_pulse_width: word(9)?,
+        })
+    }
+
+    ///
+    /// Writes the handshake to a stream.
+    ///
+    /// # Parameters:
+    /// - `stream`: The stream to write to
+    ///
+    pub async fn write<W: AsyncWrite + Unpin>(&self, stream: &mut W) -> std::io::Result<()> {
+        stream.write_all(&self.to_bytes()).await?;
+        stream.flush().await
+    }
+
+    ///
+    /// Reads a handshake from a stream.
+    ///
+    /// # Parameters:
+    /// - `stream`: The stream to read from
+    ///
+    /// # Returns:
+    /// - The handshake
+    /// - An IO error if the stream ended or failed
+    ///
+    pub async fn read<R: AsyncRead + Unpin>(stream: &mut R) -> std::io::Result<Handshake> {
+        Ok(Handshake {
+            protocol_version: stream.read_u8().await?,
+            instruction_buffer_size: stream.read_u32().await?,
+            max_motor_speed: stream.read_u32().await?,
+            min_pulse_width: stream.read_u32().await?,
+        })
+    }
+}
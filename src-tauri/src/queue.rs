@@ -0,0 +1,377 @@
+use std::fs::File;
+use std::io::{BufReader, Read, Write};
+use std::path::PathBuf;
+use std::sync::atomic::Ordering;
+
+use serde::{Serialize, Deserialize};
+use tauri::State;
+use tokio::sync::oneshot;
+
+use crate::client::{read_cached_instructions, read_start_position, run_job, AppState, JobContext};
+use crate::pens::{self, PenPlan};
+use crate::progress::QueueEvent;
+use crate::wire;
+use crate::error::AppError;
+
+
+///
+/// The state of a job in the queue.
+///
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Finished,
+    Failed,
+}
+
+///
+/// A snapshot of a drawing waiting to be plotted. The instructions are saved
+/// alongside the queue, so the job is unaffected by later previews.
+///
+/// # Fields:
+/// - `id`: A unique ID for the job
+/// - `name`: A name for the job, shown in the queue
+/// - `created`: The time the job was queued, in unix seconds
+/// - `init_x`: The horizontal starting position of the drawing
+/// - `init_y`: The vertical starting position of the drawing
+/// - `total_bytes`: The number of instruction bytes
+/// - `plan`: The pen plan of the drawing
+/// - `wait_for_paper`: Whether to wait for the operator to change the paper before drawing
+/// - `status`: The state of the job
+/// - `message`: The reason the job failed, if it did
+///
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Job {
+    pub id: u64,
+    pub name: String,
+    pub created: u64,
+    pub init_x: f64,
+    pub init_y: f64,
+    pub total_bytes: usize,
+    pub plan: PenPlan,
+    pub wait_for_paper: bool,
+    pub status: JobStatus,
+    #[serde(default)]
+    pub message: String,
+}
+
+
+///
+/// Gets the directory the queue is stored in, creating it if needed.
+///
+/// # Parameters:
+/// - `app`: The Tauri app handle
+///
+/// # Returns:
+/// - The path to the queue directory
+/// - An error if the directory couldn't be created
+///
+fn queue_dir(app: &tauri::AppHandle) -> Result<PathBuf, AppError> {
+    let data_dir = tauri::Manager::path(app).app_data_dir().map_err(|err| AppError::Io(err.to_string()))?;
+    let queue_dir = data_dir.join("queue");
+    std::fs::create_dir_all(&queue_dir)?;
+    Ok(queue_dir)
+}
+
+///
+/// Loads the queue from the disk.
+///
+/// # Parameters:
+/// - `app`: The Tauri app handle
+///
+/// # Returns:
+/// - The jobs in the queue, in order, or an empty queue if none has been saved
+/// - An error if the queue couldn't be read, or is corrupt
+///
+fn read_queue(app: &tauri::AppHandle) -> Result<Vec<Job>, AppError> {
+    let queue_path = queue_dir(app)?.join("queue.json");
+    let file_handle = match File::open(queue_path) {
+        Ok(val) => val,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => { return Ok(Vec::new()); },
+        Err(err) => { return Err(err.into()); }
+    };
+    serde_json::from_reader(BufReader::new(file_handle)).map_err(|err| AppError::Io(format!("The queue is corrupt: {}", err)))
+}
+
+///
+/// Saves the queue to the disk.
+///
+/// # Parameters:
+/// - `app`: The Tauri app handle
+/// - `jobs`: The jobs in the queue, in order
+///
+/// # Returns:
+/// - Void if the function succeeded
+/// - An error explaining why the function could not succeed
+///
+fn write_queue(app: &tauri::AppHandle, jobs: &[Job]) -> Result<(), AppError> {
+    let queue_path = queue_dir(app)?.join("queue.json");
+    let file_handle = File::create(queue_path)?;
+    serde_json::to_writer(file_handle, jobs).map_err(|err| AppError::Io(err.to_string()))
+}
+
+///
+/// Updates the status of a job, and saves the queue.
+///
+/// # Parameters:
+/// - `app`: The Tauri app handle
+/// - `state`: The global drawing state, holding the queue lock
+/// - `id`: The ID of the job
+/// - `status`: The new status of the job
+/// - `message`: The reason for the status, or an empty string
+///
+/// # Returns:
+/// - Void if the function succeeded
+/// - An error explaining why the function could not succeed
+///
+async fn set_status(app: &tauri::AppHandle, state: &AppState, id: u64, status: JobStatus, message: &str) -> Result<(), AppError> {
+    let _queue_lock = state.queue_file.lock().await;
+    let mut jobs = read_queue(app)?;
+    if let Some(job) = jobs.iter_mut().find(|job| job.id == id) {
+        job.status = status;
+        job.message = message.to_owned();
+    }
+    write_queue(app, &jobs)
+}
+
+
+///
+/// A Tauri command which adds the currently previewed drawing to the queue.
+///
+/// # Parameters:
+/// - `app`: Injected dependency from Tauri
+/// - `state`: A Tauri-injected global state object
+/// - `name`: A name for the job
+/// - `wait_for_paper`: Whether to wait for the operator to change the paper before drawing
+///
+/// # Returns:
+/// - The queued job
+/// - An error explaining why the function could not succeed
+///
+#[tauri::command(async)]
+pub async fn enqueue_job(app: tauri::AppHandle, state: State<'_, AppState>, name: &str, wait_for_paper: bool) -> Result<Job, AppError> {
+    let cache_dir = tauri::Manager::path(&app).app_cache_dir().map_err(|err| AppError::Io(err.to_string()))?;
+
+    let buffer = read_cached_instructions(&cache_dir)?;
+
+    let (init_x, init_y) = read_start_position(&app).unwrap_or((0., 0.));
+
+    let created = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let queue_lock = state.queue_file.lock().await;
+    let mut jobs = read_queue(&app)?;
+    let id = jobs.iter().map(|job| job.id + 1).max().unwrap_or(1);
+
+    let job = Job {
+        id,
+        name: name.to_owned(),
+        created,
+        init_x,
+        init_y,
+        total_bytes: buffer.len(),
+        plan: pens::read_plan(&cache_dir),
+        wait_for_paper,
+        status: JobStatus::Queued,
+        message: String::new(),
+    };
+
+    let mut job_file = File::create(queue_dir(&app)?.join(format!("job-{}.bin", id)))?;
+    job_file.write_all(&buffer)?;
+
+    jobs.push(job.clone());
+    write_queue(&app, &jobs)?;
+    drop(queue_lock);
+    QueueEvent::JobAdded { id }.emit(&app);
+
+    Ok(job)
+}
+
+///
+/// A Tauri command which lists the jobs in the queue.
+///
+/// # Parameters:
+/// - `app`: Injected dependency from Tauri
+/// - `state`: A Tauri-injected global state object
+///
+/// # Returns:
+/// - The jobs in the queue, in order
+/// - An error if the queue couldn't be read, or is corrupt
+///
+#[tauri::command(async)]
+pub async fn list_jobs(app: tauri::AppHandle, state: State<'_, AppState>) -> Result<Vec<Job>, AppError> {
+    let _queue_lock = state.queue_file.lock().await;
+    read_queue(&app)
+}
+
+///
+/// A Tauri command which removes a job from the queue.
+///
+/// # Parameters:
+/// - `app`: Injected dependency from Tauri
+/// - `state`: A Tauri-injected global state object
+/// - `id`: The ID of the job
+///
+/// # Returns:
+/// - Void if the function succeeded
+/// - An error explaining why the function could not succeed
+///
+#[tauri::command(async)]
+pub async fn remove_job(app: tauri::AppHandle, state: State<'_, AppState>, id: u64) -> Result<(), AppError> {
+    let queue_lock = state.queue_file.lock().await;
+    let mut jobs = read_queue(&app)?;
+    match jobs.iter().position(|job| job.id == id) {
+        Some(idx) if jobs[idx].status == JobStatus::Running => { return Err(AppError::Protocol("The job is running.".to_owned())); },
+        Some(idx) => { jobs.remove(idx); },
+        None => { return Err(AppError::InvalidParameters("No such job.".to_owned())); },
+    }
+
+    let _ = std::fs::remove_file(queue_dir(&app)?.join(format!("job-{}.bin", id)));
+    write_queue(&app, &jobs)?;
+    drop(queue_lock);
+    QueueEvent::JobRemoved { id }.emit(&app);
+
+    Ok(())
+}
+
+///
+/// A Tauri command which runs every queued job, one after another.
+/// Jobs which ask for it wait for the operator to change the paper first.
+/// It emits updates through the `queue-prog` channel, and each job emits through `firm-prog`.
+///
+/// # Parameters:
+/// - `app`: Injected dependency from Tauri
+/// - `state`: A Tauri-injected global state object
+/// - `machine_id`: The ID of the machine to run the queue on
+///
+/// # Returns:
+/// - Void if the function succeeded
+/// - An error explaining why the function could not succeed
+///
+#[tauri::command(async)]
+pub async fn start_queue(app: tauri::AppHandle, state: State<'_, AppState>, machine_id: &str) -> Result<(), AppError> {
+    if state.queue_running.swap(true, Ordering::SeqCst) {
+        return Err(AppError::Protocol("The queue is already running.".to_owned()));
+    }
+    state.queue_stop.store(false, Ordering::SeqCst);
+
+    let result = run_queue(&app, &state, machine_id).await;
+
+    state.queue_running.store(false, Ordering::SeqCst);
+    QueueEvent::QueueFinished.emit(&app);
+
+    result
+}
+
+///
+/// Runs queued jobs until the queue is empty, or the operator stops it.
+///
+/// # Parameters:
+/// - `app`: The Tauri app handle
+/// - `state`: The global drawing state
+/// - `machine_id`: The ID of the machine to run the jobs on
+///
+/// # Returns:
+/// - Void if the function succeeded
+/// - An error explaining why the function could not succeed
+///
+async fn run_queue(app: &tauri::AppHandle, state: &AppState, machine_id: &str) -> Result<(), AppError> {
+    // jobs can only be left running if the app was closed mid-job, so queue them again
+    let queue_lock = state.queue_file.lock().await;
+    let mut jobs = read_queue(app)?;
+    for job in jobs.iter_mut().filter(|job| job.status == JobStatus::Running) {
+        job.status = JobStatus::Queued;
+    }
+    write_queue(app, &jobs)?;
+    drop(queue_lock);
+
+    let ctx = JobContext::for_app(app, machine_id)?;
+    let session = state.session(machine_id).await;
+    while !state.queue_stop.load(Ordering::SeqCst) {
+        let queue_lock = state.queue_file.lock().await;
+        let next = read_queue(app)?.into_iter().find(|job| job.status == JobStatus::Queued);
+        drop(queue_lock);
+
+        let job = match next {
+            Some(job) => job,
+            None => { break; }
+        };
+
+        if job.wait_for_paper {
+            let (paper_tx, paper_rx) = oneshot::channel();
+            *state.paper_swap.lock().await = Some(paper_tx);
+            QueueEvent::PaperChange { id: job.id, name: job.name.clone() }.emit(app);
+
+            if !matches!(paper_rx.await, Ok(true)) {
+                break;
+            }
+        }
+
+        let mut buffer = Vec::new();
+        let read = File::open(queue_dir(app)?.join(format!("job-{}.bin", job.id))).and_then(|mut handle| handle.read_to_end(&mut buffer));
+        if let Err(err) = read {
+            set_status(app, state, job.id, JobStatus::Failed, err.to_string().as_str()).await?;
+            continue;
+        }
+
+        set_status(app, state, job.id, JobStatus::Running, "").await?;
+        QueueEvent::JobStarted { id: job.id, name: job.name.clone() }.emit(app);
+
+        // every job starts from its own starting position, as move_pen_to_start does for a single drawing
+        if let Err(err) = wire::goto(&ctx.app_config, job.init_x, job.init_y).await {
+            set_status(app, state, job.id, JobStatus::Failed, err.message()).await?;
+            break;
+        }
+
+        match run_job(&ctx, &session, buffer.as_slice(), (job.init_x, job.init_y), &job.plan).await {
+            Ok(true) => { set_status(app, state, job.id, JobStatus::Finished, "").await?; },
+            Ok(false) => {
+                // a stopped job stops the queue, so the operator can check the machine
+                set_status(app, state, job.id, JobStatus::Failed, "Stopped by the operator").await?;
+                break;
+            },
+            Err(err) => {
+                set_status(app, state, job.id, JobStatus::Failed, err.message()).await?;
+                break;
+            },
+        }
+
+        QueueEvent::JobFinished { id: job.id }.emit(app);
+    }
+
+    Ok(())
+}
+
+///
+/// A Tauri command which stops the queue once the current job has finished.
+///
+/// # Parameters:
+/// - `state`: A Tauri-injected global state object
+///
+#[tauri::command(async)]
+pub async fn stop_queue(state: State<'_, AppState>) -> Result<(), AppError> {
+    state.queue_stop.store(true, Ordering::SeqCst);
+    if let Some(paper_tx) = state.paper_swap.lock().await.take() {
+        let _ = paper_tx.send(false);
+    }
+    Ok(())
+}
+
+///
+/// Confirms or cancels a paper change, starting or stopping a job waiting in the queue.
+///
+/// # Parameters:
+/// - `state`: A Tauri-injected global state object
+/// - `proceed`: True if the operator has changed the paper, false to stop the queue
+///
+/// # Returns:
+/// - Void if the function succeeded
+/// - An error explaining why the function could not succeed
+///
+#[tauri::command(async)]
+pub async fn confirm_paper_change(state: State<'_, AppState>, proceed: bool) -> Result<(), AppError> {
+    match state.paper_swap.lock().await.take() {
+        Some(paper_tx) => { let _ = paper_tx.send(proceed); Ok(()) },
+        None => Err(AppError::Protocol("The queue is not waiting for a paper change.".to_owned())),
+    }
+}
@@ -0,0 +1,92 @@
+use std::time::{Duration, Instant};
+
+use crate::file::AppConfig;
+
+
+///
+/// How long a drawing keeps trying to reconnect when the profile doesn't set a timeout.
+///
+pub const DEFAULT_RECONNECT_TIMEOUT: Duration = Duration::from_secs(60);
+
+///
+/// The wait before the first reconnection attempt.
+///
+const INITIAL_DELAY: Duration = Duration::from_millis(500);
+
+///
+/// The longest wait between reconnection attempts.
+///
+const MAX_DELAY: Duration = Duration::from_secs(8);
+
+
+///
+/// Gets how long a machine's drawings keep trying to reconnect after the connection is lost.
+///
+/// # Parameters:
+/// - `app_config`: The machine's app config
+///
+/// # Returns:
+/// - The reconnection timeout, zero if drawings shouldn't reconnect
+///
+pub fn reconnect_timeout(app_config: &AppConfig) -> Duration {
+    app_config.machine_reconnect_timeout_secs.map(Duration::from_secs).unwrap_or(DEFAULT_RECONNECT_TIMEOUT)
+}
+
+
+///
+/// Spaces out reconnection attempts, doubling the wait after each one until the timeout runs out.
+///
+/// # Fields:
+/// - `started`: When the connection was lost
+/// - `timeout`: How long to keep trying
+/// - `delay`: The wait before the next attempt
+/// - `attempts`: The number of attempts so far
+///
+pub struct Backoff {
+    started: Instant,
+    timeout: Duration,
+    delay: Duration,
+    attempts: u32,
+}
+
+impl Backoff {
+
+    ///
+    /// Starts backing off, from the moment the connection was lost.
+    ///
+    /// # Parameters:
+    /// - `timeout`: How long to keep trying
+    ///
+    pub fn new(timeout: Duration) -> Backoff {
+        Backoff { started: Instant::now(), timeout, delay: INITIAL_DELAY, attempts: 0 }
+    }
+
+    ///
+    /// Gets the wait before the next attempt. The last wait is shortened to end at the timeout.
+    ///
+    /// # Returns:
+    /// - The wait, or None once the timeout has run out
+    ///
+    pub fn next_delay(&mut self) -> Option<Duration> {
+        let remaining = self.timeout.checked_sub(self.started.elapsed()).filter(|remaining| !remaining.is_zero())?;
+
+        let delay = self.delay.min(remaining);
+        self.delay = (self.delay * 2).min(MAX_DELAY);
+        self.attempts += 1;
+        Some(delay)
+    }
+
+    ///
+    /// Gets the number of attempts so far, including the one being waited for.
+    ///
+    pub fn attempts(&self) -> u32 {
+        self.attempts
+    }
+
+    ///
+    /// Gets the time since the connection was lost.
+    ///
+    pub fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+}
@@ -0,0 +1,199 @@
+use std::sync::OnceLock;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use bbcore::drawing::DrawMethod;
+use bbcore::drawing::lines::{LinesMethod, LinesParameters};
+use bbcore::drawing::cascade::{CascadeMethod, CascadeParameters};
+use bbcore::drawing::scribble::{ScribbleMethod, ScribbleParameters};
+use bbcore::drawing::dunes::{DunesMethod, DunesParameters};
+use bbcore::drawing::islands::{IslandsMethod, IslandsParameters};
+use bbcore::drawing::bubbles::{BubblesMethod, BubblesParameters};
+use bbcore::drawing::waves::{WavesMethod, WavesParameters};
+use bbcore::drawing::entropy::{EntropyMethod, EntropyParameters};
+use bbcore::drawing::vinyl::{VinylMethod, VinylParameters};
+use bbcore::drawing::shades::{ShadesMethod, ShadesParameters};
+use bbcore::drawing::atom::{AtomMethod, AtomParameters};
+use bbcore::drawing::custom::{CustomMethod, CustomParameters};
+use bbcore::hardware::PhysicalDimensions;
+
+use crate::svg_import::{self, SvgParameters};
+use crate::hpgl::{self, HpglParameters};
+use crate::pens::PenPlan;
+
+
+///
+/// Generates the instruction bytes and (x, y) starting position of a drawing from its JSON parameters.
+///
+pub type GenInstructions = fn(&PhysicalDimensions, &str) -> Result<(Vec<u8>, f64, f64), String>;
+
+///
+/// Generates the pen plan of a drawing from its JSON parameters.
+///
+pub type GenPenPlan = fn(&PhysicalDimensions, &str) -> Result<PenPlan, String>;
+
+
+///
+/// A single entry in the drawing style registry. Each entry binds a style ID to its
+/// `DrawMethod` and `DrawParameters` types through monomorphised function pointers.
+/// Styles implemented in the app rather than `bbcore` are registered with `native`, and bind a generator function instead,
+/// followed by a pen plan generator if they draw with several pens.
+///
+/// # Fields:
+/// - `id`: The drawing method ID, as used by the frontend and save files
+/// - `name`: The formatted name of the drawing method
+/// - `gen_instructions`: Deserializes the JSON parameters and generates the instruction bytes
+/// - `asset_fields`: The parameters which hold paths to external files, such as images, audio or plugins
+/// - `validate`: Deserializes JSON parameters into the typed parameters, returning them re-serialized
+/// - `gen_pen_plan`: Generates the pen plan of styles drawn with several pens, otherwise None for a single pen
+///
+pub struct Style {
+    pub id: &'static str,
+    pub name: &'static str,
+    pub asset_fields: &'static [&'static str],
+    pub gen_instructions: GenInstructions,
+    pub validate: fn(&Value) -> Result<Value, String>,
+    pub gen_pen_plan: Option<GenPenPlan>,
+}
+
+
+macro_rules! style {
+    (@pens $drw_p:ty) => { None };
+    (@pens $drw_p:ty, $plan:path) => {
+        Some(|phys_dim, json_params| {
+            match serde_json::from_str::<$drw_p>(json_params) {
+                Ok(val) => $plan(phys_dim, &val),
+                Err(err) => Err(err.to_string()),
+            }
+        })
+    };
+    (native $id:expr, $name:expr, $gen:path, $drw_p:ty, $assets:expr $(, $plan:path)?) => {
+        Style {
+            id: $id,
+            name: $name,
+            asset_fields: $assets,
+            gen_instructions: |phys_dim, json_params| {
+                match serde_json::from_str::<$drw_p>(json_params) {
+                    Ok(val) => $gen(phys_dim, &val),
+                    Err(err) => Err(err.to_string()),
+                }
+            },
+            validate: |json_value| {
+                match serde_json::from_value::<$drw_p>(json_value.clone()) {
+                    Ok(val) => serde_json::to_value(&val).map_err(|err| err.to_string()),
+                    Err(err) => Err(err.to_string()),
+                }
+            },
+            gen_pen_plan: style!(@pens $drw_p $(, $plan)?),
+        }
+    };
+    ($id:expr, $name:expr, $drw_t:expr, $drw_p:ty, $assets:expr) => {
+        Style {
+            id: $id,
+            name: $name,
+            asset_fields: $assets,
+            gen_instructions: |phys_dim, json_params| {
+                match serde_json::from_str::<$drw_p>(json_params) {
+                    Ok(val) => $drw_t.gen_instructions(phys_dim, &val),
+                    Err(err) => Err(err.to_string()),
+                }
+            },
+            validate: |json_value| {
+                match serde_json::from_value::<$drw_p>(json_value.clone()) {
+                    Ok(val) => serde_json::to_value(&val).map_err(|err| err.to_string()),
+                    Err(err) => Err(err.to_string()),
+                }
+            },
+            gen_pen_plan: None,
+        }
+    }
+}
+
+
+///
+/// The registry of every drawing style the app supports. Preview generation, saving,
+/// loading and the style list sent to the frontend are all driven from here, so a new
+/// `bbcore` drawing method only needs adding once.
+///
+pub static STYLES: &[Style] = &[
+    style!("islands", "Islands", IslandsMethod {}, IslandsParameters, &[]),
+    style!("dunes", "Dunes", DunesMethod {}, DunesParameters, &[]),
+    style!("entropy", "Entropy", EntropyMethod {}, EntropyParameters, &[]),
+    style!("waves", "Waves", WavesMethod {}, WavesParameters, &["image_path"]),
+    style!("cascade", "Cascade", CascadeMethod {}, CascadeParameters, &[]),
+    style!("bubbles", "Bubbles", BubblesMethod {}, BubblesParameters, &["image_path"]),
+    style!("scribble", "Scribbles", ScribbleMethod {}, ScribbleParameters, &["image_path"]),
+    style!("lines", "Lines", LinesMethod {}, LinesParameters, &[]),
+    style!("vinyl", "Vinyl", VinylMethod {}, VinylParameters, &["audio_path"]),
+    style!("shades", "Shades", ShadesMethod {}, ShadesParameters, &[]),
+    style!("atom", "Atom", AtomMethod {}, AtomParameters, &[]),
+    style!(native "svg", "SVG Import", svg_import::gen_instructions, SvgParameters, &["svg_path"]),
+    style!(native "hpgl", "HPGL Import", hpgl::gen_instructions, HpglParameters, &["hpgl_path"], hpgl::gen_pen_plan),
+    style!("custom", "Custom Plugin", CustomMethod {}, CustomParameters, &["plugin_path"]),
+];
+
+
+///
+/// Looks up a drawing style in the registry.
+///
+/// # Parameters:
+/// - `style_id`: The drawing method ID
+///
+/// # Returns:
+/// - The registry entry for the style, or None if no such style exists
+///
+pub fn get_style(style_id: &str) -> Option<&'static Style> {
+    STYLES.iter().find(|style| style.id == style_id)
+}
+
+
+///
+/// Gets the default parameters of a drawing style, as configured for the frontend
+/// in `parameters.json`.
+///
+/// # Parameters:
+/// - `style_id`: The drawing method ID
+///
+/// # Returns:
+/// - A map of parameter IDs to their default values, or None if the style has no configuration
+///
+pub fn default_parameters(style_id: &str) -> Option<Map<String, Value>> {
+    static CONFIGURATION: OnceLock<Value> = OnceLock::new();
+    let configuration = CONFIGURATION.get_or_init(|| {
+        serde_json::from_str(include_str!("../../src/configuration/parameters.json")).expect("parameters.json should be valid JSON")
+    });
+
+    let parameters = configuration.get(style_id)?.get("parameters")?.as_array()?;
+    let mut defaults = Map::new();
+    for parameter in parameters {
+        if let (Some(id), Some(default)) = (parameter.get("id").and_then(|id| id.as_str()), parameter.get("default")) {
+            defaults.insert(id.to_owned(), default.clone());
+        }
+    }
+
+    Some(defaults)
+}
+
+
+///
+/// A serializable summary of a registry entry, sent to the frontend.
+///
+/// # Fields:
+/// - `id`: The drawing method ID
+/// - `name`: The formatted name of the drawing method
+///
+#[derive(Serialize)]
+pub struct StyleInfo {
+    id: &'static str,
+    name: &'static str,
+}
+
+///
+/// A Tauri command to list every drawing style in the registry.
+///
+/// # Returns:
+/// - A list of style IDs and their formatted names, in registry order
+///
+#[tauri::command]
+pub fn list_styles() -> Vec<StyleInfo> {
+    STYLES.iter().map(|style| StyleInfo { id: style.id, name: style.name }).collect()
+}
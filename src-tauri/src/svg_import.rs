@@ -0,0 +1,260 @@
+use serde::{Serialize, Deserialize};
+use bbcore::hardware::PhysicalDimensions;
+use usvg::tiny_skia_path::PathSegment;
+
+use crate::motion;
+
+
+///
+/// The parameters of the `svg` drawing style.
+///
+/// # Fields:
+/// - `svg_path`: The path of the SVG file to draw
+/// - `margin`: The space left around the drawing on every side of the page, in millimetres
+/// - `rotation`: The clockwise rotation of the artwork, in degrees
+/// - `curve_tolerance_um`: How far flattened curves may stray from the true curve, in micrometres
+///
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SvgParameters {
+    pub svg_path: String,
+    pub margin: f64,
+    pub rotation: f64,
+    pub curve_tolerance_um: f64,
+}
+
+///
+/// A point in the artwork, in SVG user units once transformed.
+///
+type Point = (f64, f64);
+
+///
+/// A piece of an SVG subpath, continuing from the end of the previous one.
+///
+/// # Variants:
+/// - `Line`: A straight line to a point
+/// - `Quad`: A quadratic Bézier through a control point to a point
+/// - `Cubic`: A cubic Bézier through two control points to a point
+///
+#[derive(Clone, Copy)]
+enum Edge {
+    Line(Point),
+    Quad(Point, Point),
+    Cubic(Point, Point, Point),
+}
+
+///
+/// A continuous run of an SVG path, which becomes one stroke once flattened.
+///
+/// # Fields:
+/// - `start`: The point the subpath starts at
+/// - `edges`: The edges of the subpath, in order
+///
+struct Subpath {
+    start: Point,
+    edges: Vec<Edge>,
+}
+
+
+///
+/// Generates the instructions of the `svg` drawing style, which plots the outlines of an SVG's shapes.
+/// Paths, lines, polylines, polygons, rectangles, circles and ellipses are all drawn, with their transforms applied.
+/// The artwork is rotated, then scaled and centred to fit the page inside the margins.
+///
+/// # Parameters:
+/// - `phys_dim`: The physical dimensions of the machine
+/// - `params`: The parameters of the style
+///
+/// # Returns:
+/// - The instruction bytes, and the (x, y) starting position of the drawing
+/// - An error explaining why the SVG couldn't be drawn
+///
+pub fn gen_instructions(phys_dim: &PhysicalDimensions, params: &SvgParameters) -> Result<(Vec<u8>, f64, f64), String> {
+    if params.svg_path.is_empty() {
+        return Err("Select an SVG file to draw.".to_owned());
+    }
+    if !params.curve_tolerance_um.is_finite() || params.curve_tolerance_um <= 0. {
+        return Err("The curve tolerance must be greater than 0.".to_owned());
+    }
+
+    let data = std::fs::read(&params.svg_path).map_err(|err| format!("Couldn't read the SVG: {}", err))?;
+    let tree = usvg::Tree::from_data(&data, &usvg::Options::default()).map_err(|err| format!("Couldn't parse the SVG: {}", err))?;
+
+    let (sin, cos) = params.rotation.to_radians().sin_cos();
+    let mut subpaths = Vec::new();
+    collect_subpaths(tree.root(), &|(x, y): Point| (x * cos - y * sin, x * sin + y * cos), &mut subpaths);
+
+    // curves are flattened in SVG units, so the tolerance depends on the scale, which depends on the flattened bounds.
+    // the control points bound the curves, so they give a scale no larger than the final one to start from
+    let area = (phys_dim.page_width() - 2. * params.margin, phys_dim.page_height() - 2. * params.margin);
+    if area.0 <= 0. || area.1 <= 0. {
+        return Err("The margins leave no room on the page.".to_owned());
+    }
+
+    let control_points = subpaths.iter().flat_map(|subpath| std::iter::once(subpath.start).chain(subpath.edges.iter().flat_map(edge_points)));
+    let rough_scale = bounds(control_points).and_then(|bounds| fit_scale(bounds, area)).ok_or("The SVG has nothing to draw.")?;
+
+    let tolerance_mm = params.curve_tolerance_um / 1000.;
+    let strokes = flatten(&subpaths, tolerance_mm / rough_scale);
+    let scale = bounds(strokes.iter().flatten().copied()).and_then(|bounds| fit_scale(bounds, area)).ok_or("The SVG has nothing to draw.")?;
+    let strokes = flatten(&subpaths, tolerance_mm / scale);
+
+    let page_strokes = fit_to_page(phys_dim, strokes, area).ok_or("The SVG has nothing to draw.")?;
+    let (bytes, init) = motion::draw_strokes(phys_dim, &page_strokes);
+    Ok((bytes, init.0, init.1))
+}
+
+///
+/// Scales and centres strokes to fill an area of the page, keeping their proportions.
+///
+/// # Parameters:
+/// - `phys_dim`: The physical dimensions of the machine
+/// - `strokes`: The strokes, in any units
+/// - `area`: The (width, height) of the area, centred on the page, in millimetres
+///
+/// # Returns:
+/// - The strokes in page millimetres, or None if they have no extent
+///
+pub(crate) fn fit_to_page(phys_dim: &PhysicalDimensions, strokes: Vec<Vec<Point>>, area: (f64, f64)) -> Option<Vec<Vec<Point>>> {
+    let (min, max) = bounds(strokes.iter().flatten().copied())?;
+    let scale = fit_scale((min, max), area)?;
+
+    let centre = ((min.0 + max.0) / 2., (min.1 + max.1) / 2.);
+    let page_centre = (phys_dim.page_width() / 2., phys_dim.page_height() / 2.);
+
+    Some(strokes.into_iter().map(|stroke| {
+        stroke.into_iter().map(|(x, y)| (page_centre.0 + (x - centre.0) * scale, page_centre.1 + (y - centre.1) * scale)).collect()
+    }).collect())
+}
+
+///
+/// Finds the bounding box of a set of points.
+///
+/// # Returns:
+/// - The (min, max) corners, or None if there are no points
+///
+fn bounds(points: impl Iterator<Item = Point>) -> Option<(Point, Point)> {
+    points.fold(None, |acc, (x, y)| match acc {
+        None => Some(((x, y), (x, y))),
+        Some((min, max)) => Some(((min.0.min(x), min.1.min(y)), (max.0.max(x), max.1.max(y)))),
+    })
+}
+
+///
+/// Finds the largest scale which fits a bounding box into an area. A box with no width
+/// is fitted by its height, and the other way round.
+///
+/// # Returns:
+/// - The scale, or None if the box is a single point
+///
+fn fit_scale((min, max): (Point, Point), area: (f64, f64)) -> Option<f64> {
+    let scale_x = if max.0 > min.0 { area.0 / (max.0 - min.0) } else { f64::INFINITY };
+    let scale_y = if max.1 > min.1 { area.1 / (max.1 - min.1) } else { f64::INFINITY };
+    let scale = scale_x.min(scale_y);
+    scale.is_finite().then_some(scale)
+}
+
+///
+/// Collects the subpaths of every visible path in a group and its children, transformed into canvas units.
+///
+/// # Parameters:
+/// - `group`: The group to collect from
+/// - `rotate`: Rotates a canvas point
+/// - `subpaths`: The list to add the subpaths to
+///
+fn collect_subpaths(group: &usvg::Group, rotate: &dyn Fn(Point) -> Point, subpaths: &mut Vec<Subpath>) {
+    for node in group.children() {
+        match node {
+            usvg::Node::Group(group) => collect_subpaths(group, rotate, subpaths),
+            usvg::Node::Path(path) if path.is_visible() => {
+                let ts = path.abs_transform();
+                let map = |point: usvg::tiny_skia_path::Point| {
+                    let (x, y) = (point.x as f64, point.y as f64);
+                    rotate((ts.sx as f64 * x + ts.kx as f64 * y + ts.tx as f64, ts.ky as f64 * x + ts.sy as f64 * y + ts.ty as f64))
+                };
+
+                for segment in path.data().segments() {
+                    match segment {
+                        PathSegment::MoveTo(point) => subpaths.push(Subpath { start: map(point), edges: Vec::new() }),
+                        PathSegment::LineTo(point) => push_edge(subpaths, Edge::Line(map(point))),
+                        PathSegment::QuadTo(control, point) => push_edge(subpaths, Edge::Quad(map(control), map(point))),
+                        PathSegment::CubicTo(control1, control2, point) => push_edge(subpaths, Edge::Cubic(map(control1), map(control2), map(point))),
+                        PathSegment::Close => {
+                            if let Some(subpath) = subpaths.last_mut() {
+                                subpath.edges.push(Edge::Line(subpath.start));
+                            }
+                        },
+                    }
+                }
+            },
+            _ => {},
+        }
+    }
+}
+
+///
+/// Adds an edge to the last subpath, as every SVG path starts with a move.
+///
+fn push_edge(subpaths: &mut [Subpath], edge: Edge) {
+    if let Some(subpath) = subpaths.last_mut() {
+        subpath.edges.push(edge);
+    }
+}
+
+///
+/// Gets the control points and end point of an edge.
+///
+fn edge_points(edge: &Edge) -> Vec<Point> {
+    match *edge {
+        Edge::Line(p) => vec![p],
+        Edge::Quad(c, p) => vec![c, p],
+        Edge::Cubic(c1, c2, p) => vec![c1, c2, p],
+    }
+}
+
+///
+/// Flattens subpaths into polylines, splitting each curve into enough lines to stay within a tolerance.
+/// The number of lines comes from Wang's formula, which bounds the distance from the curve.
+///
+/// # Parameters:
+/// - `subpaths`: The subpaths to flatten
+/// - `tolerance`: The furthest a line may stray from its curve, in the subpaths' units
+///
+/// # Returns:
+/// - A polyline for each subpath with at least one edge
+///
+fn flatten(subpaths: &[Subpath], tolerance: f64) -> Vec<Vec<Point>> {
+    let lerp = |a: Point, b: Point, t: f64| (a.0 + (b.0 - a.0) * t, a.1 + (b.1 - a.1) * t);
+    let second_diff = |a: Point, b: Point, c: Point| (a.0 - 2. * b.0 + c.0).hypot(a.1 - 2. * b.1 + c.1);
+    let pieces = |factor: f64| ((factor / tolerance).sqrt().ceil() as usize).clamp(1, 1000);
+
+    subpaths.iter().filter(|subpath| !subpath.edges.is_empty()).map(|subpath| {
+        let mut points = vec![subpath.start];
+        let mut last = subpath.start;
+
+        for edge in &subpath.edges {
+            match *edge {
+                Edge::Line(p) => points.push(p),
+                Edge::Quad(c, p) => {
+                    let n = pieces(0.25 * second_diff(last, c, p));
+                    points.extend((1..=n).map(|i| {
+                        let t = i as f64 / n as f64;
+                        lerp(lerp(last, c, t), lerp(c, p, t), t)
+                    }));
+                },
+                Edge::Cubic(c1, c2, p) => {
+                    let n = pieces(0.75 * second_diff(last, c1, c2).max(second_diff(c1, c2, p)));
+                    points.extend((1..=n).map(|i| {
+                        let t = i as f64 / n as f64;
+                        let (a, b, c) = (lerp(last, c1, t), lerp(c1, c2, t), lerp(c2, p, t));
+                        lerp(lerp(a, b, t), lerp(b, c, t), t)
+                    }));
+                },
+            }
+            last = match *edge {
+                Edge::Line(p) | Edge::Quad(_, p) | Edge::Cubic(_, _, p) => p,
+            };
+        }
+
+        points
+    }).collect()
+}
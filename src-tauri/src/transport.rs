@@ -0,0 +1,198 @@
+use std::fmt;
+use std::net::Ipv4Addr;
+
+use serde::{Serialize, Deserialize};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+use tokio_serial::SerialPortBuilderExt;
+
+use crate::file::AppConfig;
+use crate::error::AppError;
+
+
+///
+/// The baud rate used when a serial profile doesn't set one.
+///
+pub const DEFAULT_BAUD_RATE: u32 = 115200;
+
+
+///
+/// A byte stream to a machine, over any transport. bbcore's client only connects over TCP,
+/// so serial links are relayed to it through an `Endpoint`.
+///
+pub trait Link: AsyncRead + AsyncWrite + Send + Unpin {}
+
+impl<T: AsyncRead + AsyncWrite + Send + Unpin> Link for T {}
+
+
+///
+/// How a machine profile is connected, saved in the app config.
+///
+/// # Variants:
+/// - `Tcp`: Over the network, to `machine_addr` and `machine_port`
+/// - `Serial`: Over a USB cable, to `machine_serial_port` at `machine_baud_rate`
+///
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum TransportKind {
+    #[default]
+    Tcp,
+    Serial,
+}
+
+///
+/// Where to reach a machine.
+///
+/// # Variants:
+/// - `Tcp`: The IP address and port of the machine
+/// - `Serial`: The serial port the machine is plugged into, such as `/dev/ttyUSB0` or `COM3`, and its baud rate
+///
+#[derive(Clone, Debug, PartialEq)]
+pub enum Transport {
+    Tcp { addr: String, port: u16 },
+    Serial { path: String, baud_rate: u32 },
+}
+
+impl Transport {
+
+    ///
+    /// Gets the transport of a machine profile.
+    ///
+    /// # Parameters:
+    /// - `app_config`: The machine's app config
+    ///
+    /// # Returns:
+    /// - The transport
+    /// - An error if a serial profile has no serial port
+    ///
+    pub fn from_config(app_config: &AppConfig) -> Result<Transport, AppError> {
+        match app_config.machine_transport {
+            TransportKind::Tcp => Ok(Transport::Tcp { addr: app_config.machine_addr.clone(), port: app_config.machine_port }),
+            TransportKind::Serial => match app_config.machine_serial_port.as_deref().filter(|path| !path.is_empty()) {
+                Some(path) => Ok(Transport::Serial { path: path.to_owned(), baud_rate: app_config.machine_baud_rate.unwrap_or(DEFAULT_BAUD_RATE) }),
+                None => Err(AppError::NotConfigured("The machine profile uses a serial connection, but no serial port has been chosen.".to_owned())),
+            },
+        }
+    }
+
+    ///
+    /// Opens a connection to the machine.
+    ///
+    /// # Returns:
+    /// - The connection
+    /// - An IO error if the machine couldn't be reached
+    ///
+    pub async fn open(&self) -> std::io::Result<Box<dyn Link>> {
+        match self {
+            Transport::Tcp { addr, port } => {
+                let stream = TcpStream::connect((addr.as_str(), *port)).await?;
+                stream.set_nodelay(true)?;
+                Ok(Box::new(stream))
+            },
+            Transport::Serial { path, baud_rate } => {
+                let port = tokio_serial::new(path.as_str(), *baud_rate).open_native_async()?;
+                Ok(Box::new(port))
+            },
+        }
+    }
+
+    ///
+    /// Gets an address bbcore's client can connect to. TCP machines are connected to directly,
+    /// and serial machines through a local bridge, which accepts a single client and relays bytes
+    /// between it and the serial port until either side closes. Each connection needs its own endpoint.
+    ///
+    /// # Returns:
+    /// - The endpoint, which stops the bridge when dropped
+    /// - An IO error if the serial port couldn't be opened, or the bridge couldn't be bound
+    ///
+    pub async fn endpoint(&self) -> std::io::Result<Endpoint> {
+        if let Transport::Tcp { addr, port } = self {
+            return Ok(Endpoint { addr: addr.clone(), port: *port, bridge: None });
+        }
+
+        // the port is opened up front, so a missing port is reported here rather than to the client
+        let link = self.open().await?;
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await?;
+        let port = listener.local_addr()?.port();
+
+        let bridge = tokio::spawn(async move {
+            // the listener is dropped once the client has connected, so no other process can take over the port
+            let stream = match listener.accept().await {
+                Ok((stream, _)) => stream,
+                Err(_) => { return; }
+            };
+            drop(listener);
+
+            let _ = stream.set_nodelay(true);
+            relay(stream, link).await;
+        });
+
+        Ok(Endpoint { addr: Ipv4Addr::LOCALHOST.to_string(), port, bridge: Some(bridge) })
+    }
+}
+
+impl fmt::Display for Transport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Transport::Tcp { addr, port } => write!(f, "{}:{}", addr, port),
+            Transport::Serial { path, baud_rate } => write!(f, "{} ({} baud)", path, baud_rate),
+        }
+    }
+}
+
+
+///
+/// An address to reach a machine with bbcore's client, see `Transport::endpoint`.
+///
+/// # Fields:
+/// - `addr`: The IP address to connect to
+/// - `port`: The port to connect to
+/// - `bridge`: The task relaying a serial port, or None for TCP machines
+///
+pub struct Endpoint {
+    pub addr: String,
+    pub port: u16,
+    bridge: Option<JoinHandle<()>>,
+}
+
+impl Drop for Endpoint {
+    fn drop(&mut self) {
+        if let Some(bridge) = self.bridge.take() {
+            bridge.abort();
+        }
+    }
+}
+
+///
+/// Relays bytes both ways between a client of the bridge and a machine, until either side closes.
+///
+/// # Parameters:
+/// - `client`: The client's connection to the bridge
+/// - `link`: The connection to the machine
+///
+async fn relay(client: TcpStream, link: Box<dyn Link>) {
+    let (mut client_reader, mut client_writer) = client.into_split();
+    let (mut link_reader, mut link_writer) = tokio::io::split(link);
+
+    tokio::select! {
+        _ = tokio::io::copy(&mut client_reader, &mut link_writer) => {},
+        _ = tokio::io::copy(&mut link_reader, &mut client_writer) => {},
+    }
+}
+
+
+///
+/// A Tauri command which lists the serial ports a machine could be plugged into.
+///
+/// # Returns:
+/// - The names of the serial ports, such as `/dev/ttyUSB0` or `COM3`
+/// - An error if the ports couldn't be listed
+///
+#[tauri::command(async)]
+pub fn list_serial_ports() -> Result<Vec<String>, AppError> {
+    match tokio_serial::available_ports() {
+        Ok(ports) => Ok(ports.into_iter().map(|port| port.port_name).collect()),
+        Err(err) => Err(AppError::Io(format!("Couldn't list the serial ports: {}", err))),
+    }
+}
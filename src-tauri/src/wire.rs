@@ -0,0 +1,155 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::sync::Notify;
+
+use bbcore::client::state::{ClientState, MachineConfig};
+
+use crate::file::AppConfig;
+use crate::transport::{Endpoint, Transport};
+use crate::error::AppError;
+
+
+///
+/// How long a machine has to send its handshake once the connection is open.
+/// Serial boards often reset when the port is opened, so this allows for them to boot.
+///
+pub const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+
+///
+/// Cancels a job while it has no connection to the machine, such as while it waits for a pen swap.
+/// Once connected, jobs are stopped through the machine with `ClientState::stop`.
+///
+/// # Fields:
+/// - `requested`: Set once the job should stop
+/// - `notify`: Wakes the job while it waits
+///
+#[derive(Default)]
+pub struct StopSignal {
+    requested: AtomicBool,
+    notify: Notify,
+}
+
+impl StopSignal {
+
+    ///
+    /// Asks the waiting job to stop.
+    ///
+    pub fn request(&self) {
+        self.requested.store(true, Ordering::SeqCst);
+        self.notify.notify_one();
+    }
+
+    ///
+    /// Clears a stop request, before the job starts waiting.
+    ///
+    pub fn reset(&self) {
+        self.requested.store(false, Ordering::SeqCst);
+    }
+
+    ///
+    /// Checks if the job has been asked to stop.
+    ///
+    pub fn is_requested(&self) -> bool {
+        self.requested.load(Ordering::SeqCst)
+    }
+
+    ///
+    /// Waits until the job has been asked to stop, so other waits can be cancelled with it.
+    ///
+    pub async fn requested(&self) {
+        while !self.is_requested() {
+            self.notify.notified().await;
+        }
+    }
+}
+
+
+///
+/// Connects bbcore's client to a machine and reads its handshake.
+///
+/// # Parameters:
+/// - `endpoint`: Where to reach the machine, see `Transport::endpoint`
+///
+/// # Returns:
+/// - The read and write halves of the connection, and the machine's configuration
+/// - An error if the machine couldn't be reached, or didn't send a handshake
+///
+pub async fn connect(endpoint: &Endpoint) -> Result<(OwnedReadHalf, OwnedWriteHalf, MachineConfig), AppError> {
+    match tokio::time::timeout(HANDSHAKE_TIMEOUT, ClientState::new(&endpoint.addr, endpoint.port)).await {
+        Ok(Ok((client, machine_config))) => {
+            let (reader, writer) = client.into_split();
+            Ok((reader, writer, machine_config))
+        },
+        Ok(Err(err)) => Err(AppError::Connection(format!("Couldn't connect to the machine: {}", err))),
+        Err(_) => Err(AppError::Connection(format!("The machine didn't send a handshake within {} seconds.", HANDSHAKE_TIMEOUT.as_secs()))),
+    }
+}
+
+///
+/// Applies a manual control, such as lifting the pen, over the machine profile's transport.
+///
+/// # Parameters:
+/// - `app_config`: The machine's app config
+/// - `target_byte`: The control to apply
+/// - `data`: The value of the control
+///
+/// # Returns:
+/// - Void if the control was sent
+/// - An error explaining why it couldn't be sent
+///
+pub async fn manual_control(app_config: &AppConfig, target_byte: u8, data: i16) -> Result<(), AppError> {
+    with_endpoint(app_config, move |addr, port| bbcore::client::apply_manual_control(addr, port, target_byte, data)).await
+}
+
+///
+/// Moves the pen, raised, to a position on the page over the machine profile's transport.
+///
+/// # Parameters:
+/// - `app_config`: The machine's app config
+/// - `x`: The horizontal position on the page, in millimetres
+/// - `y`: The vertical position on the page, in millimetres
+///
+/// # Returns:
+/// - Void once the machine has reached the position
+/// - An error explaining why it couldn't be moved
+///
+pub async fn goto(app_config: &AppConfig, x: f64, y: f64) -> Result<(), AppError> {
+    let phys_dim = app_config.physical_dimensions();
+    with_endpoint(app_config, move |addr, port| bbcore::client::move_to_start(addr, port, &phys_dim, x, y)).await
+}
+
+///
+/// Runs one of bbcore's blocking client calls against the machine profile's endpoint.
+/// The call is kept off the async runtime, which relays serial machines while it runs.
+///
+/// # Parameters:
+/// - `app_config`: The machine's app config
+/// - `call`: The client call, given the endpoint's address and port
+///
+/// # Returns:
+/// - Void if the call succeeded
+/// - An error explaining why it failed
+///
+async fn with_endpoint<F, E>(app_config: &AppConfig, call: F) -> Result<(), AppError>
+where
+    F: FnOnce(&str, u16) -> Result<(), E> + Send + 'static,
+    E: std::fmt::Display,
+{
+    let endpoint = match Transport::from_config(app_config)?.endpoint().await {
+        Ok(val) => val,
+        Err(err) => { return Err(AppError::Connection(format!("Couldn't connect to the machine: {}", err))); }
+    };
+
+    let (addr, port) = (endpoint.addr.clone(), endpoint.port);
+    let result = tokio::task::spawn_blocking(move || call(&addr, port).map_err(|err| err.to_string())).await;
+    drop(endpoint);
+
+    match result {
+        Ok(Ok(())) => Ok(()),
+        Ok(Err(err)) => Err(AppError::Connection(err)),
+        Err(err) => Err(AppError::Connection(err.to_string())),
+    }
+}
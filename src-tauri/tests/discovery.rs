@@ -0,0 +1,38 @@
+use std::time::Duration;
+
+use tokio::net::TcpStream;
+
+use desktop_lib::discovery::{self, Responder};
+use desktop_lib::mock_firmware::{MockConfig, MockFirmware};
+use desktop_lib::protocol::Handshake;
+
+
+#[tokio::test]
+async fn discovers_a_simulated_machine_over_loopback() {
+    let config = MockConfig::default();
+    let handshake = config.handshake;
+    let firmware = MockFirmware::start(config).await.expect("the simulated machine should start");
+    let firmware_addr = firmware.addr().expect("the simulated machine should listen on TCP");
+
+    let responder = Responder::bind("127.0.0.1:0", firmware_addr.port(), handshake).await.expect("the responder should bind");
+    let found = discovery::discover(&[responder.addr()], Duration::from_millis(500)).await.expect("the probe should be sent");
+    responder.shutdown();
+
+    assert_eq!(found.len(), 1);
+    let machine = &found[0];
+    assert_eq!(machine.addr, "127.0.0.1");
+    assert_eq!(machine.port, firmware_addr.port());
+    assert_eq!(machine.protocol_version, handshake.protocol_version);
+    assert_eq!(machine.instruction_buffer_size, handshake.instruction_buffer_size);
+    assert_eq!(machine.max_motor_speed, handshake.max_motor_speed);
+    assert_eq!(machine.min_pulse_width, handshake.min_pulse_width);
+
+    // the advertised port is the one the machine accepts drawings on
+    let mut stream = TcpStream::connect((machine.addr.as_str(), machine.port)).await.expect("the advertised port should accept connections");
+    let received = tokio::time::timeout(Duration::from_secs(2), Handshake::read(&mut stream)).await
+        .expect("the machine should send its handshake")
+        .expect("the handshake should be readable");
+    assert_eq!(received, handshake);
+
+    firmware.shutdown();
+}
@@ -0,0 +1,168 @@
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use bbcore::instruction::InstructionSet;
+
+use desktop_lib::client::{self, JobContext, Session};
+use desktop_lib::export::GcodeOptions;
+use desktop_lib::file::AppConfig;
+use desktop_lib::mock_firmware::{MockConfig, MockFirmware, MockLog};
+use desktop_lib::motion;
+use desktop_lib::pens::PenPlan;
+use desktop_lib::progress::ProgressEvent;
+use desktop_lib::transport::TransportKind;
+
+
+///
+/// Builds the instructions of a drawing spanning several of the simulated machine's buffers.
+///
+fn instruction_set() -> InstructionSet {
+    let bytes: Vec<u8> = (0..motion::INSTRUCTION_SIZE * 600).map(|i| (i % 251) as u8).collect();
+    InstructionSet::new(bytes, 0., 0.).expect("the instructions should be valid")
+}
+
+///
+/// A temporary directory for a job's cache and data, removed when dropped.
+///
+struct TempDir(PathBuf);
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+///
+/// Builds the context of a job on a simulated machine, recording the events it emits.
+///
+fn job_context(firmware: &MockFirmware, name: &str) -> (JobContext, Arc<Mutex<Vec<ProgressEvent>>>, TempDir) {
+    let port = firmware.addr().expect("the simulated machine should listen on TCP").port();
+    let app_config = AppConfig {
+        machine_addr: "127.0.0.1".to_owned(),
+        machine_port: port,
+        phys_motor_interspace: 1000.,
+        phys_page_left_offset: 350.,
+        phys_page_top_offset: 300.,
+        phys_page_width: 300.,
+        phys_page_height: 300.,
+        machine_max_motor_speed: None,
+        machine_min_pulse_width: None,
+        machine_reconnect_timeout_secs: Some(0),
+        machine_transport: TransportKind::Tcp,
+        machine_serial_port: None,
+        machine_baud_rate: None,
+        export_gcode: GcodeOptions::default(),
+        optimise_paths: false,
+    };
+
+    let dir = TempDir(std::env::temp_dir().join(format!("blot-firmware-test-{}-{}", std::process::id(), name)));
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let emitted = events.clone();
+
+    let ctx = JobContext {
+        machine_id: name.to_owned(),
+        app_config,
+        cache_dir: dir.0.join("cache"),
+        data_dir: dir.0.join("data"),
+        emit: Arc::new(move |event| emitted.lock().unwrap().push(event)),
+    };
+    (ctx, events, dir)
+}
+
+///
+/// Waits until the simulated machine's log satisfies a condition, and returns it.
+///
+async fn wait_for(firmware: &MockFirmware, what: &str, condition: impl Fn(&MockLog) -> bool) -> MockLog {
+    tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            let log = firmware.log().await;
+            if condition(&log) {
+                return log;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }).await.unwrap_or_else(|_| panic!("{}", what))
+}
+
+///
+/// Waits until the simulated machine has received more than a number of buffers.
+///
+async fn wait_for_buffers(firmware: &MockFirmware, count: usize) {
+    wait_for(firmware, "the machine should receive buffers", |log| log.buffers.len() > count).await;
+}
+
+
+#[tokio::test]
+async fn streams_an_instruction_set_in_buffers() {
+    let config = MockConfig { ack_delay: Duration::from_millis(5), ..MockConfig::default() };
+    let buffer_size = config.handshake.instruction_buffer_size as usize;
+    let firmware = MockFirmware::start(config).await.unwrap();
+    let (ctx, events, _dir) = job_context(&firmware, "stream");
+    let ins_set = instruction_set();
+
+    let finished = client::run_job(&ctx, &Session::default(), ins_set.get_binary(), (0., 0.), &PenPlan::single()).await;
+    assert!(matches!(finished, Ok(true)));
+
+    let log = firmware.log().await;
+    assert_eq!(&log.instructions, ins_set.get_binary());
+    assert!(log.buffers.len() > 1);
+    assert!(log.buffers.iter().all(|len| *len <= buffer_size));
+    assert!(log.finished);
+    assert!(events.lock().unwrap().contains(&ProgressEvent::DrawingFinished));
+
+    firmware.shutdown();
+}
+
+#[tokio::test]
+async fn pauses_and_resumes_a_drawing() {
+    let firmware = MockFirmware::start(MockConfig { ack_delay: Duration::from_millis(100), ..MockConfig::default() }).await.unwrap();
+    let (ctx, events, _dir) = job_context(&firmware, "pause");
+    let ins_set = instruction_set();
+    let bytes = ins_set.get_binary().clone();
+    let session = Arc::new(Session::default());
+
+    let job_session = session.clone();
+    let job = tokio::spawn(async move { client::run_job(&ctx, &job_session, &bytes, (0., 0.), &PenPlan::single()).await });
+    wait_for_buffers(&firmware, 0).await;
+
+    session.set_paused(true, |_| {}).await.expect("the drawing should pause");
+    // a paused machine holds its buffer, rather than asking for more
+    wait_for(&firmware, "the machine should hold its buffer while paused", |log| log.paused && log.holding).await;
+
+    session.set_paused(false, |_| {}).await.expect("the drawing should resume");
+    let finished = tokio::time::timeout(Duration::from_secs(10), job).await.expect("the drawing should finish").unwrap();
+    assert!(matches!(finished, Ok(true)));
+
+    let log = firmware.log().await;
+    assert!(!log.paused);
+    assert_eq!(log.requests_while_paused, 0);
+    assert!(log.finished);
+    assert_eq!(&log.instructions, ins_set.get_binary());
+    assert!(events.lock().unwrap().contains(&ProgressEvent::DrawingFinished));
+
+    firmware.shutdown();
+}
+
+#[tokio::test]
+async fn stops_a_drawing() {
+    let firmware = MockFirmware::start(MockConfig { ack_delay: Duration::from_millis(100), ..MockConfig::default() }).await.unwrap();
+    let (ctx, events, _dir) = job_context(&firmware, "stop");
+    let bytes = instruction_set().get_binary().clone();
+    let session = Arc::new(Session::default());
+
+    let job_session = session.clone();
+    let job = tokio::spawn(async move { client::run_job(&ctx, &job_session, &bytes, (0., 0.), &PenPlan::single()).await });
+    wait_for_buffers(&firmware, 0).await;
+
+    session.stop(|_| {}).await.expect("the drawing should stop");
+    let finished = tokio::time::timeout(Duration::from_secs(10), job).await.expect("the drawing should end").unwrap();
+    assert!(matches!(finished, Ok(false)));
+
+    let log = firmware.log().await;
+    assert!(log.stopped);
+    assert!(!log.finished);
+    assert!(events.lock().unwrap().contains(&ProgressEvent::Shutdown));
+
+    firmware.shutdown();
+}
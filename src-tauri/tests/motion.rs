@@ -0,0 +1,74 @@
+use bbcore::hardware::PhysicalDimensions;
+use bbcore::instruction::InstructionSet;
+use serde_json::Value;
+
+use desktop_lib::motion::{self, Instruction, INSTRUCTION_SIZE, STEPS_PER_MM};
+use desktop_lib::styles;
+
+
+///
+/// The physical dimensions of a machine with an A4 page, as the app defaults to.
+///
+fn phys_dim() -> PhysicalDimensions {
+    PhysicalDimensions::new(754., (754. - 210.) / 1.98, 192., 210., 297.)
+}
+
+///
+/// Generates an instruction set with bbcore for every style which draws without an asset, using its default parameters.
+///
+fn bbcore_drawings(phys_dim: &PhysicalDimensions) -> Vec<(&'static str, InstructionSet)> {
+    styles::STYLES.iter().filter(|style| style.asset_fields.is_empty()).map(|style| {
+        let defaults = Value::Object(styles::default_parameters(style.id).unwrap_or_default());
+        let parameters = (style.validate)(&defaults).unwrap_or_else(|err| panic!("{} should accept its defaults: {}", style.id, err));
+        let (bytes, x, y) = (style.gen_instructions)(phys_dim, parameters.to_string().as_str()).unwrap_or_else(|err| panic!("{} should generate: {}", style.id, err));
+        let ins_set = InstructionSet::new(bytes, x, y).unwrap_or_else(|err| panic!("{} should make an instruction set: {}", style.id, err));
+        (style.id, ins_set)
+    }).collect()
+}
+
+
+#[test]
+fn reads_bbcore_instructions_in_the_same_format() {
+    let phys_dim = phys_dim();
+    let drawings = bbcore_drawings(&phys_dim);
+    assert!(!drawings.is_empty());
+
+    for (id, ins_set) in &drawings {
+        let bytes = ins_set.get_binary();
+        assert_eq!(bytes.len() % INSTRUCTION_SIZE, 0, "{} should be whole instructions", id);
+
+        let instructions: Vec<Instruction> = motion::decode(bytes).collect();
+        assert!(instructions.contains(&Instruction::Pen(true)), "{} should lower the pen", id);
+        assert!(instructions.iter().any(|instruction| matches!(instruction, Instruction::Move(..))), "{} should move", id);
+        assert_eq!(&motion::encode(&instructions), bytes, "{} should re-encode to the same bytes", id);
+    }
+}
+
+#[test]
+fn places_bbcore_drawings_on_the_page() {
+    let phys_dim = phys_dim();
+
+    // a different step size or belt geometry would trace the drawing off the page
+    for (id, ins_set) in bbcore_drawings(&phys_dim) {
+        let samples = motion::trace(&phys_dim, ins_set.get_binary(), ins_set.get_init());
+        assert!(samples.iter().any(|sample| sample.pen_down), "{} should draw", id);
+
+        for sample in samples.iter().filter(|sample| sample.pen_down) {
+            assert!((-1. ..=phys_dim.page_width() + 1.).contains(&sample.x), "{} draws off the page at x = {}", id, sample.x);
+            assert!((-1. ..=phys_dim.page_height() + 1.).contains(&sample.y), "{} draws off the page at y = {}", id, sample.y);
+        }
+    }
+}
+
+#[test]
+fn converts_between_belt_lengths_and_page_positions() {
+    let phys_dim = phys_dim();
+
+    for x in (0..=210).step_by(15) {
+        for y in (0..=297).step_by(27) {
+            let (left, right) = motion::belt_steps(&phys_dim, x as f64, y as f64);
+            let (page_x, page_y) = motion::page_position(&phys_dim, left, right);
+            assert!((page_x - x as f64).abs() <= 1. / STEPS_PER_MM && (page_y - y as f64).abs() <= 1. / STEPS_PER_MM, "({}, {}) came back as ({}, {})", x, y, page_x, page_y);
+        }
+    }
+}
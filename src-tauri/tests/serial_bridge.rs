@@ -0,0 +1,71 @@
+#![cfg(unix)]
+
+use std::process::Stdio;
+use std::time::Duration;
+
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::process::{Child, Command};
+
+use desktop_lib::mock_firmware::MockConfig;
+use desktop_lib::protocol::{self, Handshake};
+use desktop_lib::transport::{Transport, DEFAULT_BAUD_RATE};
+
+
+///
+/// Starts `mock_firmware --pty`, and reads the serial port it prints.
+///
+async fn spawn_pty_machine() -> (Child, String) {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_mock_firmware"))
+        .arg("--pty")
+        .stdout(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .expect("the simulated machine should start");
+
+    let mut lines = BufReader::new(child.stdout.take().unwrap()).lines();
+    let line = tokio::time::timeout(Duration::from_secs(5), lines.next_line()).await
+        .expect("the simulated machine should print its serial port")
+        .expect("the output should be readable")
+        .expect("the simulated machine should print its serial port");
+    let path = line.strip_prefix("Simulated machine attached to ").expect("the output should name the serial port").trim().to_owned();
+
+    (child, path)
+}
+
+#[tokio::test]
+async fn relays_a_session_with_a_simulated_serial_machine() {
+    let (mut child, path) = spawn_pty_machine().await;
+
+    let endpoint = Transport::Serial { path, baud_rate: DEFAULT_BAUD_RATE }.endpoint().await.expect("the serial port should open");
+    let mut stream = TcpStream::connect((endpoint.addr.as_str(), endpoint.port)).await.expect("the bridge should accept connections");
+
+    // machine to client
+    let handshake = tokio::time::timeout(Duration::from_secs(5), Handshake::read(&mut stream)).await
+        .expect("the machine should send its handshake")
+        .expect("the handshake should be readable");
+    assert_eq!(handshake, MockConfig::default().handshake);
+
+    // the bridge stops listening once its client is connected
+    assert!(TcpStream::connect((endpoint.addr.as_str(), endpoint.port)).await.is_err());
+    assert_eq!(stream.read_u8().await.unwrap(), protocol::ST_NEED_BUFFER);
+
+    // client to machine, an empty buffer ends the drawing
+    stream.write_u8(protocol::OP_BUFFER).await.unwrap();
+    stream.write_u32(0).await.unwrap();
+    stream.flush().await.unwrap();
+    let status = tokio::time::timeout(Duration::from_secs(5), stream.read_u8()).await
+        .expect("the machine should answer through the bridge")
+        .unwrap();
+    assert_eq!(status, protocol::ST_FINISHED);
+
+    drop(stream);
+    drop(endpoint);
+    let _ = child.kill().await;
+}
+
+#[tokio::test]
+async fn reports_a_missing_serial_port() {
+    let transport = Transport::Serial { path: "/dev/blot-missing-port".to_owned(), baud_rate: DEFAULT_BAUD_RATE };
+    assert!(transport.endpoint().await.is_err());
+}
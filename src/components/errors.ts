@@ -0,0 +1,33 @@
+//
+// The error returned by every backend command, see AppError in error.rs.
+//
+export type AppError = {
+    kind: "NotConfigured" | "NoPreview" | "Connection" | "Protocol" | "InvalidParameters" | "Io";
+    message: string;
+};
+
+//
+// Usage: gets a human readable message from an error thrown by invoke().
+//
+// Parameters: the thrown error
+// Returns: the message
+//
+export function errorMessage(err: any): string {
+    if(err != null && typeof err == "object" && "message" in err) {
+        return err.message;
+    }
+    return String(err);
+}
+
+//
+// Usage: gets the kind of an error thrown by invoke().
+//
+// Parameters: the thrown error
+// Returns: the error kind, or null if it isn't an AppError
+//
+export function errorKind(err: any): AppError["kind"] | null {
+    if(err != null && typeof err == "object" && "kind" in err) {
+        return err.kind;
+    }
+    return null;
+}
@@ -0,0 +1,37 @@
+//
+// The progress events emitted through the "firm-prog" channel, see ProgressEvent in progress.rs.
+// Every message carries the schema version it was written with.
+//
+export const PROGRESS_SCHEMA_VERSION = 1;
+
+export type ProgressEvent =
+    | { event: "populate_network"; address: string }
+    | { event: "populate_draw"; total_bytes: number; page_width: number; page_height: number }
+    | { event: "populate_machine"; instruction_buffer_size: number; max_motor_speed: number; min_pulse_width: number; protocol_version: number }
+    | { event: "connection"; message: string }
+    | { event: "drawing"; secs_remaining: number; ins_pos: number }
+    | { event: "estimate"; secs_elapsed: number; secs_remaining: number; eta: number }
+    | { event: "pen_position"; ins_pos: number; x: number; y: number; pen_down: boolean; drawn: [number, number][][] }
+    | { event: "pause"; is_paused: boolean }
+    | { event: "pen_change"; pen: number; name: string; colour: string }
+    | { event: "reconnecting"; attempt: number; retry_in_ms: number; ins_pos: number }
+    | { event: "reconnected"; ins_pos: number }
+    | { event: "drawing_finished" }
+    | { event: "shutdown" };
+
+// The machine is the ID of the machine the event refers to, the name of its profile.
+export type ProgressMessage = { version: number; machine?: string } & ProgressEvent;
+
+//
+// The queue events emitted through the "queue-prog" channel, see QueueEvent in progress.rs.
+// They share the progress schema version.
+//
+export type QueueEvent =
+    | { event: "job_added"; id: number }
+    | { event: "job_removed"; id: number }
+    | { event: "paper_change"; id: number; name: string }
+    | { event: "job_started"; id: number; name: string }
+    | { event: "job_finished"; id: number }
+    | { event: "queue_finished" };
+
+export type QueueMessage = { version: number } & QueueEvent;